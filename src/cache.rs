@@ -0,0 +1,305 @@
+use std::{
+    fs,
+    io::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    diagnostic::{Applicability, Code, Diagnostic, Fix},
+    interface::Declarations,
+    tokenizer::Span,
+};
+
+/// Name of the directory, next to the compiled `.jack` files, holding the
+/// build cache.
+pub const CACHE_DIR: &str = ".jack-cache";
+
+/// Remembers which classes were compiled from which source so unchanged
+/// classes can be skipped on the next run.
+///
+/// Each class has an entry file holding the key it was last compiled with (a
+/// hash of the compiler version, the options affecting code generation and the
+/// source) and a hash of the `.vm` it produced, followed by a line per class
+/// its calls were checked against with a hash of that class's interface, or
+/// `-` when it was unknown. A class is fresh when all of them still match, so
+/// changing the signature of a subroutine recompiles the classes calling it.
+/// The warnings of the class follow after an empty line, to be reported again
+/// when it is skipped.
+pub struct BuildCache {
+    dir: PathBuf,
+    options: String,
+}
+
+impl BuildCache {
+    pub fn open(source_dir: &Path, options: &[&str]) -> BuildCache {
+        BuildCache {
+            dir: source_dir.join(CACHE_DIR),
+            options: options.join(" "),
+        }
+    }
+
+    pub fn key(&self, source: &str) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET_BASIS, env!("CARGO_PKG_VERSION").as_bytes());
+        hash = fnv1a(hash, &[0]);
+        hash = fnv1a(hash, self.options.as_bytes());
+        hash = fnv1a(hash, &[0]);
+        fnv1a(hash, source.as_bytes())
+    }
+
+    /// Returns the warnings the class was last compiled with when it is
+    /// fresh, `None` when it has to be compiled again.
+    pub fn lookup(
+        &self,
+        class_name: &str,
+        key: u64,
+        output: &Path,
+        declarations: &Declarations,
+    ) -> Option<Vec<Diagnostic>> {
+        let entry = fs::read_to_string(self.entry_path(class_name)).ok()?;
+        let output = fs::read(output).ok()?;
+        let (dependencies, warnings) = entry.split_once("\n\n").unwrap_or((&entry, ""));
+        let mut lines = dependencies.lines();
+        if lines.next() != Some(&format_entry(key, fnv1a(FNV_OFFSET_BASIS, &output))) {
+            return None;
+        }
+        let fresh = lines.all(|line| {
+            line.split_once(' ').is_some_and(|(dependency, hash)| {
+                format_hash(interface_hash(declarations, dependency)) == hash
+            })
+        });
+        if !fresh {
+            return None;
+        }
+        warnings.lines().map(decode_warning).collect()
+    }
+
+    pub fn store(
+        &self,
+        class_name: &str,
+        key: u64,
+        output: &str,
+        dependencies: &[String],
+        warnings: &[Diagnostic],
+        declarations: &Declarations,
+    ) -> Result<(), Error> {
+        let mut entry = format_entry(key, fnv1a(FNV_OFFSET_BASIS, output.as_bytes()));
+        entry.push('\n');
+        for dependency in dependencies {
+            let hash = format_hash(interface_hash(declarations, dependency));
+            entry.push_str(&format!("{dependency} {hash}\n"));
+        }
+        if !warnings.is_empty() {
+            entry.push('\n');
+            for warning in warnings {
+                entry.push_str(&encode_warning(warning));
+                entry.push('\n');
+            }
+        }
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.entry_path(class_name), entry)
+    }
+
+    fn entry_path(&self, class_name: &str) -> PathBuf {
+        self.dir.join(format!("{class_name}.entry"))
+    }
+}
+
+/// Formats the hash of an interface, `-` for a class whose interface is
+/// unknown so that the classes using it are checked again once it is known.
+fn format_hash(hash: Option<u64>) -> String {
+    hash.map_or_else(|| String::from("-"), |hash| format!("{hash:016x}"))
+}
+
+fn format_entry(key: u64, output_hash: u64) -> String {
+    format!("{key:016x} {output_hash:016x}")
+}
+
+/// Writes `warning` on a line, as tab separated fields: its code, span and
+/// message, the span, message, replacement and applicability of its fix,
+/// empty without one, and its notes.
+fn encode_warning(warning: &Diagnostic) -> String {
+    let mut fields = vec![
+        warning.code.as_str().to_string(),
+        warning.span.start.to_string(),
+        warning.span.end.to_string(),
+        warning.message.clone(),
+    ];
+    match &warning.fix {
+        Some(fix) => fields.extend([
+            fix.span.start.to_string(),
+            fix.span.end.to_string(),
+            fix.message.clone(),
+            fix.replacement.clone(),
+            fix.applicability.as_str().to_string(),
+        ]),
+        None => fields.extend(std::iter::repeat_n(String::new(), 5)),
+    }
+    fields.extend(warning.notes.iter().cloned());
+    fields
+        .iter()
+        .map(|field| escape(field))
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+/// Reads back a warning written by `encode_warning`, `None` if the line is
+/// malformed or of a code this version doesn't know.
+fn decode_warning(line: &str) -> Option<Diagnostic> {
+    let fields: Vec<String> = line.split('\t').map(unescape).collect();
+    let [code, start, end, message, fix_start, fix_end, fix_message, replacement, applicability, notes @ ..] =
+        fields.as_slice()
+    else {
+        return None;
+    };
+    let span = Span::new(start.parse().ok()?, end.parse().ok()?);
+    let mut warning = Diagnostic::warning(Code::parse(code)?, span, message.as_str());
+    if !fix_start.is_empty() {
+        warning.fix = Some(Fix {
+            message: fix_message.clone(),
+            span: Span::new(fix_start.parse().ok()?, fix_end.parse().ok()?),
+            replacement: replacement.clone(),
+            applicability: Applicability::parse(applicability)?,
+        });
+    }
+    warning.notes = notes.to_vec();
+    Some(warning)
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+fn interface_hash(declarations: &Declarations, class_name: &str) -> Option<u64> {
+    let interface = declarations.get(class_name)?;
+    Some(fnv1a(FNV_OFFSET_BASIS, interface.to_string().as_bytes()))
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a, used instead of `DefaultHasher` because its output has to
+/// stay stable across Rust releases.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jack-cache-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn declarations(source: &str) -> Declarations {
+        let mut declarations = Declarations::new();
+        for class in interface::parse(source).unwrap() {
+            declarations.add(class).unwrap();
+        }
+        declarations
+    }
+
+    #[test]
+    fn warnings_round_trip() {
+        let warning =
+            Diagnostic::warning(Code::UnusedVariable, Span::new(3, 9), "unused\tvariable")
+                .with_note("a note\nover two lines with a \\")
+                .with_note("")
+                .with_fix("remove it", Span::new(1, 10), "")
+                .maybe_incorrect();
+        let decoded = decode_warning(&encode_warning(&warning)).unwrap();
+        assert_eq!(decoded.code, warning.code);
+        assert_eq!(decoded.span, warning.span);
+        assert_eq!(decoded.message, warning.message);
+        assert_eq!(decoded.notes, warning.notes);
+        let fix = decoded.fix.unwrap();
+        assert_eq!(
+            (fix.span, fix.message.as_str()),
+            (Span::new(1, 10), "remove it")
+        );
+        assert_eq!(fix.replacement, "");
+        assert_eq!(fix.applicability, Applicability::MaybeIncorrect);
+
+        let plain = Diagnostic::warning(Code::EvaluationOrder, Span::new(0, 1), "order");
+        let decoded = decode_warning(&encode_warning(&plain)).unwrap();
+        assert!(decoded.fix.is_none() && decoded.notes.is_empty());
+        assert!(decode_warning("J9999\t0\t1\tunknown\t\t\t\t\t").is_none());
+    }
+
+    #[test]
+    fn lookup_checks_source_output_and_dependencies() {
+        let dir = temp_dir("lookup");
+        let output = dir.join("Main.vm");
+        fs::write(&output, "function Main.main 0\n").unwrap();
+        let cache = BuildCache::open(&dir, &["standard"]);
+        let key = cache.key("class Main {}");
+        let foo = declarations("class Foo { function void f(); }");
+        let warning = Diagnostic::warning(Code::UnusedVariable, Span::new(3, 9), "unused");
+        let dependencies = [String::from("Foo")];
+        let code = "function Main.main 0\n";
+        cache
+            .store("Main", key, code, &dependencies, &[warning], &foo)
+            .unwrap();
+
+        let warnings = cache.lookup("Main", key, &output, &foo).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].message, "unused");
+        // changed source
+        assert!(cache
+            .lookup("Main", cache.key("class Main { }"), &output, &foo)
+            .is_none());
+        // changed options
+        let other = BuildCache::open(&dir, &["extended"]);
+        assert!(other
+            .lookup("Main", other.key("class Main {}"), &output, &foo)
+            .is_none());
+        // changed or missing dependency
+        let changed = declarations("class Foo { function void f(int x); }");
+        assert!(cache.lookup("Main", key, &output, &changed).is_none());
+        assert!(cache
+            .lookup("Main", key, &output, &Declarations::new())
+            .is_none());
+        // edited output
+        fs::write(&output, "function Main.main 1\n").unwrap();
+        assert!(cache.lookup("Main", key, &output, &foo).is_none());
+
+        // a dependency whose interface was unknown, once it is known
+        fs::write(&output, code).unwrap();
+        let unknown = Declarations::new();
+        cache
+            .store("Main", key, code, &dependencies, &[], &unknown)
+            .unwrap();
+        assert!(cache.lookup("Main", key, &output, &unknown).is_some());
+        assert!(cache.lookup("Main", key, &output, &foo).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,14 +1,41 @@
-use std::{io::Error, iter::Peekable, path::PathBuf};
+use std::{
+    collections::{BTreeSet, HashSet},
+    iter::Peekable,
+};
 
 use crate::{
-    symbol_table::{Category, SymbolTable},
-    tokenizer::Token,
+    diagnostic::{Applicability, Code, Diagnostic, Fix, Severity},
+    hack,
+    interface::{Declarations, SubroutineKind},
+    runtime, suggest,
+    symbol_table::{Category, ClassSymbols, SubroutineSymbols, SymbolTable},
+    tokenizer::{self, Dialect, Invalid, Span, Token, EXTENDED_KEYWORDS},
     vm_writer::{Arithmetic, Segment, VmWriter},
 };
 
+/// Order in which the binary operators of an expression apply.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum Precedence {
+    /// Strictly from left to right, as specified by nand2tetris.
+    #[default]
+    Jack,
+    /// `*`, `/` and `%` first, then `+` and `-`, shifts, comparisons, `&`
+    /// and `|`, and last `&&` and `||`.
+    Conventional,
+}
+
+impl Precedence {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Precedence::Jack => "jack",
+            Precedence::Conventional => "conventional",
+        }
+    }
+}
+
 pub struct CompilationEngine<'a, I>
 where
-    I: Iterator<Item = Token<'a>>,
+    I: Iterator<Item = (Span, Token<'a>)>,
 {
     vm_writer: VmWriter,
     tokenizer: Peekable<I>,
@@ -19,17 +46,47 @@ where
     subroutine_type: String,
     subroutine_category: String,
     control_counter: usize,
+    declarations: &'a Declarations,
+    // other classes whose interface the calls compiled so far were checked
+    // against, or used as a type
+    dependencies: BTreeSet<String>,
+    calls: Vec<CallSite>,
+    references: BTreeSet<String>,
+    subroutine_symbols: Vec<SubroutineSymbols>,
+    // labels `continue` and `break` jump to in the enclosing loops and
+    // switches, innermost last; a switch has no `continue` of its own but
+    // that of the loop around it
+    loops: Vec<(Option<String>, String)>,
+    precedence: Precedence,
+    // whether the last term or expression compiled is a condition, e.g. a
+    // comparison
+    boolean: bool,
+    // number of calls and array accesses compiled so far, to tell whether an
+    // operand has side effects or may dereference null
+    effects: usize,
+    dialect: Dialect,
+    source: &'a str,
+    var_decs: Vec<VarDec<'a>>,
+    used_vars: HashSet<&'a str>,
+    // span of the last token consumed
+    span: Span,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'a, I> CompilationEngine<'a, I>
 where
-    I: Iterator<Item = Token<'a>>,
+    I: Iterator<Item = (Span, Token<'a>)>,
 {
-    pub fn build(tokenizer: I, path: PathBuf) -> Result<CompilationEngine<'a, I>, Error> {
-        let vm_writer = VmWriter::build(path)?;
-
-        Ok(CompilationEngine {
-            vm_writer,
+    /// `source` is the text `tokenizer` reads from, used to lay out suggested
+    /// fixes. `declarations` are the classes the program can use, to check
+    /// references to classes and calls against.
+    pub fn new(
+        source: &'a str,
+        tokenizer: I,
+        declarations: &'a Declarations,
+    ) -> CompilationEngine<'a, I> {
+        CompilationEngine {
+            vm_writer: VmWriter::new(),
             tokenizer: tokenizer.peekable(),
             class_symbol_table: SymbolTable::new(),
             subroutine_symbol_table: SymbolTable::new(),
@@ -38,14 +95,188 @@ where
             subroutine_type: String::new(),
             subroutine_category: String::new(),
             control_counter: 0,
-        })
+            declarations,
+            dependencies: BTreeSet::new(),
+            calls: Vec::new(),
+            references: BTreeSet::new(),
+            subroutine_symbols: Vec::new(),
+            loops: Vec::new(),
+            precedence: Precedence::Jack,
+            boolean: false,
+            effects: 0,
+            dialect: Dialect::Standard,
+            source,
+            var_decs: Vec::new(),
+            used_vars: HashSet::new(),
+            span: Span::default(),
+            diagnostics: Vec::new(),
+        }
     }
 
-    fn process(&mut self, token: Token) {
+    /// Compiles the extensions of `dialect`, which the tokenizer must be
+    /// reading too.
+    pub fn with_dialect(mut self, dialect: Dialect) -> CompilationEngine<'a, I> {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Applies the binary operators of expressions in the order of
+    /// `precedence`.
+    pub fn with_precedence(mut self, precedence: Precedence) -> CompilationEngine<'a, I> {
+        self.precedence = precedence;
+        self
+    }
+
+    /// Consumes the engine, returning the generated VM code, unless an error
+    /// was reported, along with every diagnostic. Nothing is written to disk
+    /// here so that a failed compilation never leaves a partial `.vm` file
+    /// behind.
+    pub fn finish(mut self) -> Compilation {
+        self.diagnostics
+            .sort_by_key(|diagnostic| diagnostic.span.start);
+        let failed = self
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error);
+        let output = (!failed).then(|| self.vm_writer.into_output());
+        Compilation {
+            output,
+            diagnostics: self.diagnostics,
+            dependencies: self.dependencies.into_iter().collect(),
+            calls: self.calls,
+            references: self.references.into_iter().collect(),
+            symbols: ClassSymbols {
+                class: self.class_name,
+                symbols: self
+                    .class_symbol_table
+                    .symbols()
+                    .into_iter()
+                    .cloned()
+                    .collect(),
+                subroutines: self.subroutine_symbols,
+            },
+        }
+    }
+
+    /// Peeks at the next token, reporting and skipping any invalid tokens.
+    fn peek_token(&mut self) -> Option<Token<'a>> {
+        while let Some(&(span, Token::Invalid(token))) = self.tokenizer.peek() {
+            self.tokenizer.next();
+            self.invalid_token(span, token);
+        }
+        self.tokenizer.peek().map(|&(_, token)| token)
+    }
+
+    fn peek_span(&mut self) -> Span {
+        self.peek_token();
+        self.tokenizer
+            .peek()
+            .map_or(Span::new(self.span.end, self.span.end), |&(span, _)| span)
+    }
+
+    fn next_token(&mut self) -> Option<Token<'a>> {
+        self.peek_token();
         match self.tokenizer.next() {
-            Some(current_token) if current_token == token => return,
-            None => return,
-            Some(_) => println!("error: syntax error"),
+            Some((span, token)) => {
+                self.span = span;
+                Some(token)
+            }
+            None => {
+                self.span = Span::new(self.span.end, self.span.end);
+                None
+            }
+        }
+    }
+
+    fn invalid_token(&mut self, span: Span, token: &str) {
+        let diagnostic = match Invalid::of(token, self.dialect) {
+            Invalid::Token if tokenizer::has_radix_prefix(token) => {
+                let note = match self.dialect {
+                    Dialect::Standard => "hexadecimal and binary constants are part of the extended dialect, enabled by `--dialect=extended`",
+                    Dialect::Extended => "hexadecimal constants are made of the digits 0-9 and a-f after `0x`, binary constants of 0 and 1 after `0b`",
+                };
+                Diagnostic::error(Code::InvalidToken, span, format!("invalid token `{token}`"))
+                    .with_note(note)
+            }
+            Invalid::Token if token.starts_with('\'') => {
+                Diagnostic::error(Code::InvalidToken, span, format!("invalid token `{token}`"))
+                    .with_note(
+                        "character constants are part of the extended dialect, enabled by `--dialect=extended`",
+                    )
+            }
+            Invalid::Token if token == "%" => {
+                Diagnostic::error(Code::InvalidToken, span, format!("invalid token `{token}`"))
+                    .with_note(
+                        "`%` is part of the extended dialect, enabled by `--dialect=extended`",
+                    )
+            }
+            Invalid::Token => {
+                Diagnostic::error(Code::InvalidToken, span, format!("invalid token `{token}`"))
+                    .with_note(
+                    "identifiers are made of letters, digits and `_` and cannot start with a digit",
+                )
+            }
+            Invalid::UnterminatedString => Diagnostic::error(
+                Code::UnterminatedString,
+                span,
+                "unterminated string constant",
+            )
+            .with_note("string constants cannot contain a newline"),
+            Invalid::UnterminatedChar => Diagnostic::error(
+                Code::UnterminatedString,
+                span,
+                "unterminated character constant",
+            )
+            .with_note("character constants cannot contain a newline"),
+            Invalid::UnterminatedComment => Diagnostic::error(
+                Code::UnterminatedComment,
+                span,
+                "unterminated block comment",
+            ),
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Reports that the last token consumed, `found`, is not what was
+    /// `expected`.
+    fn expected(&mut self, expected: &str, found: Option<Token>) {
+        self.diagnostics.push(Diagnostic::error(
+            Code::UnexpectedToken,
+            self.span,
+            format!("expected {}, found {}", expected, describe(found)),
+        ));
+    }
+
+    /// Reports that the next token is not what was `expected`, without
+    /// consuming it.
+    fn expected_next(&mut self, expected: &str) -> &mut Diagnostic {
+        let found = self.peek_token();
+        let span = self.peek_span();
+        self.diagnostics.push(Diagnostic::error(
+            Code::UnexpectedToken,
+            span,
+            format!("expected {}, found {}", expected, describe(found)),
+        ));
+        self.diagnostics.last_mut().expect("Expected just pushed")
+    }
+
+    fn process(&mut self, token: Token) {
+        match self.peek_token() {
+            Some(current_token) if current_token == token => {
+                self.next_token();
+            }
+            _ => {
+                let end = Span::new(self.span.end, self.span.end);
+                let diagnostic = self.expected_next(&token.to_string());
+                if token == Token::Symbol(';') {
+                    diagnostic.fix = Some(Fix {
+                        message: String::from("insert `;`"),
+                        span: end,
+                        replacement: String::from(";"),
+                        applicability: Applicability::MachineApplicable,
+                    });
+                }
+            }
         }
     }
 
@@ -54,153 +285,291 @@ where
         self.process(Token::Keyword("class"));
 
         // parse className
-        match self.tokenizer.next() {
+        match self.next_token() {
             Some(Token::Identifier(class_name)) => self.class_name.push_str(class_name),
-            _ => println!("error: syntax error"),
+            found => self.expected("a class name", found),
         }
 
         // parse body
         self.process(Token::Symbol('{'));
-        while let Some(Token::Keyword("static" | "field")) = self.tokenizer.peek() {
-            self.compile_class_var_dec();
+        loop {
+            match self.peek_token() {
+                Some(Token::Keyword("static" | "field")) => self.compile_class_var_dec(),
+                Some(Token::Keyword("const")) => self.compile_const_dec(),
+                Some(Token::Keyword("enum")) => self.compile_enum_dec(),
+                _ => break,
+            }
         }
-        while let Some(Token::Keyword("constructor" | "function" | "method")) =
-            self.tokenizer.peek()
-        {
+        while let Some(Token::Keyword("constructor" | "function" | "method")) = self.peek_token() {
             self.compile_subroutine();
         }
+        if let Some(Token::Identifier(name)) = self.peek_token() {
+            let span = self.peek_span();
+            let diagnostic = self.expected_next("a subroutine declaration or `}`");
+            if EXTENDED_KEYWORDS.contains(&name) {
+                diagnostic.notes.push(format!(
+                    "`{name}` is part of the extended dialect, enabled by `--dialect=extended`"
+                ));
+            }
+            suggest_keyword(diagnostic, name, span, CLASS_KEYWORDS);
+            return;
+        }
         self.process(Token::Symbol('}'));
+
+        if self.peek_token().is_some() {
+            self.expected_next("end of file");
+        }
     }
 
     pub fn compile_class_var_dec(&mut self) {
         // parse static | field
-        let category = match self.tokenizer.next() {
+        let category = match self.next_token() {
             Some(Token::Keyword(k @ ("static" | "field"))) => Some(k),
-            _ => {
-                println!("error: syntax error");
+            found => {
+                self.expected("`static` or `field`", found);
                 None
             }
         };
 
         // parse type
-        let _type = match self.tokenizer.next() {
-            Some(Token::Keyword(k @ ("int" | "char" | "boolean")) | Token::Identifier(k)) => {
+        let _type = match self.next_token() {
+            Some(Token::Keyword(k @ ("int" | "char" | "boolean"))) => Some(k),
+            Some(Token::Identifier(k)) => {
+                self.check_class(k, &["int", "char", "boolean"]);
                 Some(k)
             }
-            _ => {
-                println!("error: syntax error");
+            found => {
+                self.expected("a type", found);
                 None
             }
         };
 
         // parse varName
-        match self.tokenizer.next() {
+        match self.next_token() {
             Some(Token::Identifier(var_name)) => {
-                let kind = category.and_then(|category| get_category(category));
-                let _type = _type;
+                let kind = category.and_then(get_category);
                 if let (Some(kind), Some(_type)) = (kind, _type) {
-                    self.class_symbol_table.define(var_name, _type, kind);
+                    self.class_symbol_table
+                        .define(var_name, _type, kind, self.span);
                 }
             }
-            _ => println!("error: syntax error"),
+            found => self.expected("a variable name", found),
         }
 
         // parse delimiter whether , or ;
-        while let Some(token) = self.tokenizer.next() {
-            match token {
-                Token::Symbol(',') => {
-                    if let Some(Token::Identifier(var_name)) = self.tokenizer.next() {
-                        let kind = category.and_then(|category| get_category(category));
-                        let _type = _type;
-                        if let (Some(kind), Some(_type)) = (kind, _type) {
-                            self.class_symbol_table.define(var_name, _type, kind);
-                        }
-                    } else {
-                        println!("error: syntax error");
+        while let Some(Token::Symbol(',')) = self.peek_token() {
+            self.next_token();
+            match self.next_token() {
+                Some(Token::Identifier(var_name)) => {
+                    let kind = category.and_then(get_category);
+                    if let (Some(kind), Some(_type)) = (kind, _type) {
+                        self.class_symbol_table
+                            .define(var_name, _type, kind, self.span);
                     }
                 }
-                Token::Symbol(';') => {
-                    break;
+                found => self.expected("a variable name", found),
+            }
+        }
+        self.process(Token::Symbol(';'));
+    }
+
+    /// Compiles a `const` declaration of the extended dialect. The constant
+    /// only lives in the class symbol table, its uses compile to its value.
+    pub fn compile_const_dec(&mut self) {
+        self.process(Token::Keyword("const"));
+        let _type = match self.next_token() {
+            Some(Token::Keyword(k @ ("int" | "char" | "boolean"))) => Some(k),
+            found => {
+                self.expected("`int`, `char` or `boolean`", found);
+                None
+            }
+        };
+        let name = match self.next_token() {
+            Some(Token::Identifier(name)) => Some((name, self.span)),
+            found => {
+                self.expected("a constant name", found);
+                None
+            }
+        };
+        self.process(Token::Symbol('='));
+        let word = self.constant_value();
+        if let (Some(_type), Some((name, span))) = (_type, name) {
+            self.class_symbol_table
+                .define_constant(name, _type, word, span);
+        }
+        self.process(Token::Symbol(';'));
+    }
+
+    /// Compiles an `enum` declaration of the extended dialect, defining its
+    /// members as constants named `Enum.MEMBER`. Members without a value
+    /// follow the previous one, starting from 0.
+    pub fn compile_enum_dec(&mut self) {
+        self.process(Token::Keyword("enum"));
+        let name = match self.next_token() {
+            Some(Token::Identifier(name)) => name,
+            found => {
+                self.expected("an enum name", found);
+                return;
+            }
+        };
+        self.process(Token::Symbol('{'));
+        let mut word: u16 = 0;
+        loop {
+            match self.next_token() {
+                Some(Token::Symbol('}')) => break,
+                Some(Token::Identifier(member)) => {
+                    let span = self.span;
+                    if let Some(Token::Symbol('=')) = self.peek_token() {
+                        self.next_token();
+                        word = self.constant_value();
+                    }
+                    self.class_symbol_table.define_constant(
+                        &format!("{name}.{member}"),
+                        name,
+                        word,
+                        span,
+                    );
+                    word = word.wrapping_add(1);
+                    match self.peek_token() {
+                        Some(Token::Symbol(',')) => {
+                            self.next_token();
+                        }
+                        Some(Token::Symbol('}')) => {}
+                        _ => {
+                            self.expected_next("`,` or `}`");
+                            return;
+                        }
+                    }
                 }
-                _ => {
-                    println!("error: syntax error");
-                    break;
+                found => {
+                    self.expected("an enum member or `}`", found);
+                    return;
                 }
             }
         }
     }
 
+    /// Compiles the value of a constant: an integer, character or boolean
+    /// constant, possibly negated.
+    fn constant_value(&mut self) -> u16 {
+        let negate = matches!(self.peek_token(), Some(Token::Symbol('-')));
+        if negate {
+            self.next_token();
+        }
+        let word = match self.next_token() {
+            Some(Token::IntConstant(c)) => self.int_constant_word(c),
+            Some(Token::CharConst(c)) => self.char_constant(c),
+            Some(Token::Keyword("true")) => u16::MAX,
+            Some(Token::Keyword("false" | "null")) => 0,
+            found => {
+                self.expected("a constant value", found);
+                0
+            }
+        };
+        if negate {
+            word.wrapping_neg()
+        } else {
+            word
+        }
+    }
+
     pub fn compile_subroutine(&mut self) {
         self.subroutine_symbol_table.reset();
+        self.var_decs.clear();
+        self.used_vars.clear();
         self.subroutine_name.clear();
         self.subroutine_category.clear();
         self.subroutine_type.clear();
         // parse constructor | function | method
-        match self.tokenizer.next() {
+        match self.next_token() {
             Some(Token::Keyword(c @ ("constructor" | "function" | "method"))) => {
                 self.subroutine_category.push_str(c)
             }
-            _ => {
-                println!("error: syntax error");
-            }
+            found => self.expected("`constructor`, `function` or `method`", found),
         };
         if self.subroutine_category == "method" {
-            self.subroutine_symbol_table
-                .define("this", self.class_name.as_str(), Category::Arg);
+            self.subroutine_symbol_table.define(
+                "this",
+                self.class_name.as_str(),
+                Category::Arg,
+                self.span,
+            );
         }
 
         // parse type
-        match self.tokenizer.next() {
-            Some(
-                Token::Keyword(_type @ ("void" | "int" | "char" | "boolean"))
-                | Token::Identifier(_type),
-            ) => self.subroutine_type.push_str(_type),
-            _ => println!("error: syntax error"),
+        match self.next_token() {
+            Some(Token::Keyword(_type @ ("void" | "int" | "char" | "boolean"))) => {
+                self.subroutine_type.push_str(_type)
+            }
+            Some(Token::Identifier(_type)) => {
+                self.check_class(_type, &["void", "int", "char", "boolean"]);
+                self.subroutine_type.push_str(_type)
+            }
+            found => self.expected("a return type", found),
         }
 
         // parse subroutineName
-        match self.tokenizer.next() {
+        match self.next_token() {
             Some(Token::Identifier(i)) => self.subroutine_name.push_str(i),
-            _ => println!("error: syntax error"),
+            found => self.expected("a subroutine name", found),
         };
 
         self.process(Token::Symbol('('));
         self.compile_parameter_list();
         self.process(Token::Symbol(')'));
         self.compile_subroutine_body();
+        self.subroutine_symbols.push(SubroutineSymbols {
+            name: format!("{}.{}", self.class_name, self.subroutine_name),
+            kind: self.subroutine_category.clone(),
+            symbols: self
+                .subroutine_symbol_table
+                .symbols()
+                .into_iter()
+                .cloned()
+                .collect(),
+        });
     }
 
     pub fn compile_parameter_list(&mut self) {
         // peek if its )
         // if its not, process the parameter list
-        while let Some(token) = self.tokenizer.peek() {
-            match *token {
+        while let Some(token) = self.peek_token() {
+            match token {
                 Token::Symbol(')') => break,
                 Token::Keyword(t @ ("int" | "char" | "boolean")) | Token::Identifier(t) => {
                     // parse type and advance the iterator
-                    self.tokenizer.next();
+                    self.next_token();
+                    if let Token::Identifier(t) = token {
+                        self.check_class(t, &["int", "char", "boolean"]);
+                    }
 
                     // parse varName
-                    match self.tokenizer.next() {
-                        Some(Token::Identifier(arg_name)) => {
-                            self.subroutine_symbol_table
-                                .define(arg_name, t, Category::Arg)
-                        }
-                        _ => println!("error: syntax error"),
+                    match self.next_token() {
+                        Some(Token::Identifier(arg_name)) => self.subroutine_symbol_table.define(
+                            arg_name,
+                            t,
+                            Category::Arg,
+                            self.span,
+                        ),
+                        found => self.expected("a parameter name", found),
                     }
                 }
                 Token::Symbol(',') => {
-                    self.tokenizer.next();
+                    self.next_token();
+                }
+                _ => {
+                    self.expected_next("a parameter type or `)`");
+                    break;
                 }
-                _ => println!("error: syntax error"),
             }
         }
     }
 
     pub fn compile_subroutine_body(&mut self) {
         self.process(Token::Symbol('{'));
+        let open_brace = self.span;
         // loop
-        while let Some(Token::Keyword("var")) = self.tokenizer.peek() {
+        while let Some(Token::Keyword("var")) = self.peek_token() {
             self.compile_var_dec();
         }
         let n_vars = self.subroutine_symbol_table.var_count(Category::Var);
@@ -214,7 +583,7 @@ where
                 Segment::CONSTANT,
                 self.class_symbol_table.var_count(Category::Field),
             );
-            self.vm_writer.write_call("Memory.alloc", 1);
+            self.write_runtime_call("Memory.alloc", 1);
             self.vm_writer.write_pop(Segment::POINTER, 0);
         }
 
@@ -223,92 +592,235 @@ where
             self.vm_writer.write_pop(Segment::POINTER, 0);
         }
 
-        self.compile_statements();
+        let statements_start = self.span;
+        if !self.compile_statements() {
+            self.missing_return(open_brace, statements_start);
+        }
         self.process(Token::Symbol('}'));
+        self.unused_vars();
+    }
+
+    /// Reports a subroutine whose last statement isn't a `return`, offering
+    /// to add one on a new line after the last statement when the value to
+    /// return is known.
+    fn missing_return(&mut self, open_brace: Span, statements_start: Span) {
+        let close_brace = self.peek_span();
+        let mut diagnostic = Diagnostic::error(
+            Code::MissingReturn,
+            close_brace,
+            format!(
+                "`{}.{}` does not end with a `return` statement",
+                self.class_name, self.subroutine_name
+            ),
+        )
+        .with_note("the VM code of a subroutine falls through into whatever follows it");
+
+        let statement = match self.subroutine_category.as_str() {
+            "constructor" => Some("return this;"),
+            _ if self.subroutine_type == "void" => Some("return;"),
+            _ => None,
+        };
+        if let Some(statement) = statement {
+            let indent = if self.span == statements_start {
+                // empty body, indent one level deeper than the opening brace
+                format!("{}    ", self.indentation(open_brace.start))
+            } else {
+                String::from(self.indentation(self.span.start))
+            };
+            diagnostic = diagnostic.with_fix(
+                format!("add `{statement}`"),
+                Span::new(self.span.end, self.span.end),
+                &format!("\n{indent}{statement}"),
+            );
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Returns the whitespace at the start of the line containing `offset`.
+    fn indentation(&self, offset: usize) -> &'a str {
+        let line_start = self.source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line = &self.source[line_start..];
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+    }
+
+    /// Warns about the local variables of the subroutine just compiled that
+    /// are never referenced, offering to remove their declaration.
+    fn unused_vars(&mut self) {
+        let mut diagnostics = Vec::new();
+        for var_dec in &self.var_decs {
+            for (i, &(name, span)) in var_dec.names.iter().enumerate() {
+                if self.used_vars.contains(name) {
+                    continue;
+                }
+                let removed = if var_dec.names.len() == 1 {
+                    var_dec.span
+                } else if i == 0 {
+                    Span::new(span.start, var_dec.names[1].1.start)
+                } else {
+                    Span::new(var_dec.names[i - 1].1.end, span.end)
+                };
+                diagnostics.push(
+                    Diagnostic::warning(
+                        Code::UnusedVariable,
+                        span,
+                        format!("unused variable `{name}`"),
+                    )
+                    .with_fix("remove the unused variable", removed, ""),
+                );
+            }
+        }
+        self.diagnostics.extend(diagnostics);
     }
 
     pub fn compile_var_dec(&mut self) {
         self.process(Token::Keyword("var"));
+        let start = self.span.start;
         // parse type
-        if let Some(
-            Token::Keyword(_type @ ("int" | "char" | "boolean")) | Token::Identifier(_type),
-        ) = self.tokenizer.next()
-        {
-            // parse varName
-            match self.tokenizer.next() {
-                Some(Token::Identifier(var_name)) => {
-                    self.subroutine_symbol_table
-                        .define(var_name, _type, Category::Var)
-                }
-                _ => println!("error: syntax error"),
+        let _type = match self.next_token() {
+            Some(Token::Keyword(_type @ ("int" | "char" | "boolean"))) => _type,
+            Some(Token::Identifier(_type)) => {
+                self.check_class(_type, &["int", "char", "boolean"]);
+                _type
+            }
+            found => {
+                self.expected("a type", found);
+                return;
             }
+        };
+
+        let mut names = Vec::new();
+        // parse varName
+        match self.next_token() {
+            Some(Token::Identifier(var_name)) => {
+                self.subroutine_symbol_table
+                    .define(var_name, _type, Category::Var, self.span);
+                names.push((var_name, self.span));
+            }
+            found => self.expected("a variable name", found),
+        }
 
-            // parse delimiter whether , or ;
-            while let Some(Token::Symbol(',')) = self.tokenizer.next() {
-                if let Some(Token::Identifier(var_name)) = self.tokenizer.next() {
+        // parse delimiter whether , or ;
+        while let Some(Token::Symbol(',')) = self.peek_token() {
+            self.next_token();
+            match self.next_token() {
+                Some(Token::Identifier(var_name)) => {
                     self.subroutine_symbol_table
-                        .define(var_name, _type, Category::Var);
+                        .define(var_name, _type, Category::Var, self.span);
+                    names.push((var_name, self.span));
                 }
+                found => self.expected("a variable name", found),
             }
         }
+        self.process(Token::Symbol(';'));
+        self.var_decs.push(VarDec {
+            span: Span::new(start, self.span.end),
+            names,
+        });
     }
 
-    pub fn compile_statements(&mut self) {
-        while let Some(token) = self.tokenizer.peek() {
-            match *token {
+    /// Compiles statements up to the closing `}`, returning whether they
+    /// always end in a `return`.
+    pub fn compile_statements(&mut self) -> bool {
+        let mut returns = false;
+        while let Some(token) = self.peek_token() {
+            match token {
                 Token::Symbol('}') => break,
                 Token::Keyword("let") => self.compile_let(),
-                Token::Keyword("if") => self.compile_if(),
+                Token::Keyword("if") => returns |= self.compile_if(),
                 Token::Keyword("while") => self.compile_while(),
+                Token::Keyword("for") => self.compile_for(),
+                Token::Keyword("switch") => returns |= self.compile_switch(),
                 Token::Keyword("do") => self.compile_do(),
-                Token::Keyword("return") => self.compile_return(),
+                Token::Keyword("return") => {
+                    self.compile_return();
+                    returns = true;
+                }
+                Token::Keyword(keyword @ ("break" | "continue")) => {
+                    self.compile_loop_control(keyword)
+                }
                 _ => {
-                    println!("error: syntax error");
-                    break;
+                    let span = self.peek_span();
+                    let diagnostic = self.expected_next("a statement");
+                    if let Token::Identifier(name) = token {
+                        if EXTENDED_KEYWORDS.contains(&name) {
+                            diagnostic.notes.push(format!(
+                                "`{name}` is part of the extended dialect, enabled by `--dialect=extended`"
+                            ));
+                        }
+                        suggest_keyword(diagnostic, name, span, STATEMENT_KEYWORDS);
+                    }
+                    // it may well be a misspelled `return`, don't also
+                    // report a missing one
+                    returns = true;
+                    // skip to the end of the statement
+                    while let Some(token) = self.peek_token() {
+                        match token {
+                            Token::Symbol('}') => break,
+                            Token::Symbol(';') => {
+                                self.next_token();
+                                break;
+                            }
+                            _ => {
+                                self.next_token();
+                            }
+                        }
+                    }
                 }
             }
         }
+        returns
     }
 
     pub fn compile_let(&mut self) {
+        self.compile_assignment();
+        self.process(Token::Symbol(';'));
+    }
+
+    /// Compiles a `let` statement without its closing `;`, as also found in
+    /// the step of a `for` loop.
+    fn compile_assignment(&mut self) {
         // parse let
         self.process(Token::Keyword("let"));
 
         // parse varName
-        match self.tokenizer.next() {
-            Some(Token::Identifier(var_name)) => {
-                let segment = self.kind_of(var_name).map(category_to_segment);
-                let _type = self.type_of(var_name);
-                let index = self.index_of(var_name);
-                if let (Some(segment), Some(index)) = (segment, index) {
-                    if let Some(Token::Symbol('[')) = self.tokenizer.peek() {
-                        self.vm_writer.write_push(segment, index);
-                        self.process(Token::Symbol('['));
-                        self.compile_expression();
-                        self.vm_writer.write_arithmetic(Arithmetic::ADD);
-                        self.process(Token::Symbol(']'));
+        let var_name = match self.next_token() {
+            Some(Token::Identifier(var_name)) => var_name,
+            found => {
+                self.expected("a variable name", found);
+                return;
+            }
+        };
+        let variable = self.resolve_variable(var_name);
+
+        if let Some(Token::Symbol('[')) = self.peek_token() {
+            if let Some((segment, index)) = variable {
+                self.vm_writer.write_push(segment, index);
+            }
+            self.process(Token::Symbol('['));
+            self.compile_expression();
+            self.vm_writer.write_arithmetic(Arithmetic::ADD);
+            self.process(Token::Symbol(']'));
 
-                        self.process(Token::Symbol('='));
+            self.process(Token::Symbol('='));
 
-                        self.compile_expression();
-                        self.vm_writer.write_pop(Segment::TEMP, 0);
-                        // pops to arr + i
-                        self.vm_writer.write_pop(Segment::POINTER, 1);
-                        self.vm_writer.write_push(Segment::TEMP, 0);
-                        self.vm_writer.write_pop(Segment::THAT, 0);
-                    } else {
-                        self.process(Token::Symbol('='));
-                        self.compile_expression();
-                        self.vm_writer.write_pop(segment, index);
-                    }
-                }
-                self.process(Token::Symbol(';'));
+            self.compile_expression();
+            self.vm_writer.write_pop(Segment::TEMP, 0);
+            // pops to arr + i
+            self.vm_writer.write_pop(Segment::POINTER, 1);
+            self.vm_writer.write_push(Segment::TEMP, 0);
+            self.vm_writer.write_pop(Segment::THAT, 0);
+        } else {
+            self.process(Token::Symbol('='));
+            self.compile_expression();
+            if let Some((segment, index)) = variable {
+                self.vm_writer.write_pop(segment, index);
             }
-            _ => println!("error: syntax error"),
-        };
+        }
     }
 
-    pub fn compile_if(&mut self) {
+    /// Compiles an `if` statement, returning whether both of its branches
+    /// always end in a `return`.
+    pub fn compile_if(&mut self) -> bool {
         let else_label = &format!("{}L{}", self.class_name, self.control_counter);
         self.control_counter += 1;
         let exit_label = &format!("{}L{}", self.class_name, self.control_counter);
@@ -322,20 +834,22 @@ where
         self.vm_writer.write_if(else_label);
         self.process(Token::Symbol(')'));
         self.process(Token::Symbol('{'));
-        self.compile_statements();
+        let mut returns = self.compile_statements();
         self.vm_writer.write_goto(exit_label);
         self.process(Token::Symbol('}'));
 
-        if let Some(Token::Keyword("else")) = self.tokenizer.peek() {
+        if let Some(Token::Keyword("else")) = self.peek_token() {
             self.process(Token::Keyword("else"));
             self.process(Token::Symbol('{'));
             self.vm_writer.write_label(else_label);
-            self.compile_statements();
+            returns &= self.compile_statements();
             self.process(Token::Symbol('}'));
         } else {
             self.vm_writer.write_label(else_label);
+            returns = false;
         }
         self.vm_writer.write_label(exit_label);
+        returns
     }
 
     fn generate_control_label(&mut self) -> String {
@@ -358,12 +872,243 @@ where
         self.process(Token::Symbol(')'));
 
         self.process(Token::Symbol('{'));
+        self.loops
+            .push((Some(loop_label.clone()), exit_label.clone()));
         self.compile_statements();
+        self.loops.pop();
         self.vm_writer.write_goto(loop_label);
         self.vm_writer.write_label(exit_label);
         self.process(Token::Symbol('}'));
     }
 
+    /// Compiles `for (let i = 0; i < n; let i = i + 1) { ... }` as the
+    /// equivalent `while` loop, the step running after the body and on
+    /// `continue`.
+    pub fn compile_for(&mut self) {
+        let loop_label = self.generate_control_label();
+        let step_label = self.generate_control_label();
+        let exit_label = self.generate_control_label();
+        self.process(Token::Keyword("for"));
+        self.process(Token::Symbol('('));
+
+        let n_diagnostics = self.diagnostics.len();
+        self.compile_let();
+        for diagnostic in &mut self.diagnostics[n_diagnostics..] {
+            if diagnostic.code == Code::UndeclaredVariable {
+                diagnostic.notes.push(String::from(
+                    "the variable of a `for` loop is declared beforehand, e.g. `var int i;`",
+                ));
+            }
+        }
+
+        self.vm_writer.write_label(&loop_label);
+        self.compile_expression();
+        self.vm_writer.write_arithmetic(Arithmetic::NOT);
+        self.vm_writer.write_if(&exit_label);
+        self.process(Token::Symbol(';'));
+
+        // the step is compiled now but runs after the body
+        let writer = std::mem::replace(&mut self.vm_writer, VmWriter::new());
+        self.compile_assignment();
+        let step = std::mem::replace(&mut self.vm_writer, writer);
+        self.process(Token::Symbol(')'));
+
+        self.process(Token::Symbol('{'));
+        self.loops
+            .push((Some(step_label.clone()), exit_label.clone()));
+        self.compile_statements();
+        self.loops.pop();
+        self.vm_writer.write_label(&step_label);
+        self.vm_writer.append(step);
+        self.vm_writer.write_goto(&loop_label);
+        self.vm_writer.write_label(&exit_label);
+        self.process(Token::Symbol('}'));
+    }
+
+    /// Compiles `break;` or `continue;`, jumping out of the innermost loop or
+    /// switch, or back to the start of the innermost loop.
+    fn compile_loop_control(&mut self, keyword: &str) {
+        self.next_token();
+        let label = match self.loops.last() {
+            Some((_, break_label)) if keyword == "break" => Some(break_label),
+            Some((continue_label, _)) => continue_label.as_ref(),
+            None => None,
+        };
+        match label {
+            Some(label) => self.vm_writer.write_goto(label),
+            None => {
+                self.diagnostics.push(Diagnostic::error(
+                    Code::OutsideLoop,
+                    self.span,
+                    format!("`{keyword}` outside of a loop"),
+                ));
+            }
+        }
+        self.process(Token::Symbol(';'));
+    }
+
+    /// Compiles a `switch` statement of the extended dialect, returning
+    /// whether it has a `default` and all of its cases always end in a
+    /// `return`. The value is stored in `temp 1` and looked up among the
+    /// cases before jumping to the block of the one matching it.
+    pub fn compile_switch(&mut self) -> bool {
+        let exit_label = self.generate_control_label();
+        self.process(Token::Keyword("switch"));
+        self.process(Token::Symbol('('));
+        self.compile_expression();
+        self.vm_writer.write_pop(Segment::TEMP, SWITCH_TEMP);
+        self.process(Token::Symbol(')'));
+        self.process(Token::Symbol('{'));
+
+        // the blocks are compiled first, the lookup written ahead of them
+        let writer = std::mem::replace(&mut self.vm_writer, VmWriter::new());
+        let continue_label = self.loops.last().and_then(|(label, _)| label.clone());
+        self.loops.push((continue_label, exit_label.clone()));
+        let mut cases: Vec<(u16, Span, String)> = Vec::new();
+        let mut default: Option<(Span, String)> = None;
+        let mut returns = true;
+        loop {
+            match self.peek_token() {
+                Some(Token::Keyword("case")) => {
+                    self.next_token();
+                    let label = self.generate_control_label();
+                    if let Some(value) = self.case_value() {
+                        self.check_duplicate_case(value, &cases);
+                        cases.push((value.0, value.1, label.clone()));
+                    }
+                    self.process(Token::Symbol(':'));
+                    self.vm_writer.write_label(&label);
+                }
+                Some(Token::Keyword("default")) => {
+                    self.next_token();
+                    let label = self.generate_control_label();
+                    if let Some((first, _)) = &default {
+                        let line = self.line_of(first.start);
+                        self.diagnostics.push(
+                            Diagnostic::error(
+                                Code::DuplicateCase,
+                                self.span,
+                                "`switch` already has a `default` case",
+                            )
+                            .with_note(format!("the first `default` is on line {line}")),
+                        );
+                    } else {
+                        default = Some((self.span, label.clone()));
+                    }
+                    self.process(Token::Symbol(':'));
+                    self.vm_writer.write_label(&label);
+                }
+                Some(Token::Symbol('}')) | None => break,
+                _ => {
+                    self.expected_next("`case`, `default` or `}`");
+                    break;
+                }
+            }
+            self.process(Token::Symbol('{'));
+            returns &= self.compile_statements();
+            self.vm_writer.write_goto(&exit_label);
+            self.process(Token::Symbol('}'));
+        }
+        self.loops.pop();
+        self.process(Token::Symbol('}'));
+
+        let blocks = std::mem::replace(&mut self.vm_writer, writer);
+        let otherwise = default.as_ref().map_or(&exit_label, |(_, label)| label);
+        let mut lookup: Vec<(i16, &str)> = cases
+            .iter()
+            .map(|(word, _, label)| (*word as i16, label.as_str()))
+            .collect();
+        lookup.sort_unstable();
+        self.write_case_lookup(&lookup, otherwise);
+        self.vm_writer.append(blocks);
+        self.vm_writer.write_label(&exit_label);
+        returns && default.is_some()
+    }
+
+    /// Compiles the value of a `case`, returning its word and span when it
+    /// is a constant.
+    fn case_value(&mut self) -> Option<(u16, Span)> {
+        let start = self.peek_span();
+        let n_diagnostics = self.diagnostics.len();
+        let writer = std::mem::replace(&mut self.vm_writer, VmWriter::new());
+        self.compile_expression();
+        let value = std::mem::replace(&mut self.vm_writer, writer);
+        let span = Span::new(start.start, self.span.end);
+        let word = value.constant_word();
+        if word.is_none() && self.diagnostics.len() == n_diagnostics {
+            let text = &self.source[span.start..span.end];
+            self.diagnostics.push(
+                Diagnostic::error(
+                    Code::NonConstantCase,
+                    span,
+                    format!("`case` value `{text}` is not a constant"),
+                )
+                .with_note(
+                    "cases are integer, character or boolean constants, or the constants and enum members of a class",
+                ),
+            );
+        }
+        word.map(|word| (word, span))
+    }
+
+    /// Reports the `case` of `value` when one of `cases` already has its
+    /// word.
+    fn check_duplicate_case(&mut self, value: (u16, Span), cases: &[(u16, Span, String)]) {
+        let (word, span) = value;
+        let Some((_, first, _)) = cases.iter().find(|(other, _, _)| *other == word) else {
+            return;
+        };
+        let text = &self.source[span.start..span.end];
+        let first_text = &self.source[first.start..first.end];
+        let line = self.line_of(first.start);
+        let note = if text == first_text {
+            format!("it is first handled on line {line}, whose block is the one run")
+        } else {
+            format!(
+                "`{first_text}` on line {line} has the same value, {}, and its block is the one run",
+                word as i16
+            )
+        };
+        self.diagnostics.push(
+            Diagnostic::error(
+                Code::DuplicateCase,
+                span,
+                format!("`case {text}` is already handled"),
+            )
+            .with_note(note),
+        );
+    }
+
+    /// Jumps to the label of the case among `cases`, sorted by value, equal
+    /// to the value of the `switch`, or to `otherwise`. Cases are compared
+    /// in turn when there are few of them, and searched by halves otherwise.
+    fn write_case_lookup(&mut self, cases: &[(i16, &str)], otherwise: &str) {
+        if cases.len() <= LINEAR_CASES {
+            for &(value, label) in cases {
+                self.vm_writer.write_push(Segment::TEMP, SWITCH_TEMP);
+                self.write_word(value as u16);
+                self.vm_writer.write_arithmetic(Arithmetic::EQ);
+                self.vm_writer.write_if(label);
+            }
+            self.vm_writer.write_goto(otherwise);
+            return;
+        }
+        let (low, high) = cases.split_at(cases.len() / 2);
+        let low_label = self.generate_control_label();
+        self.vm_writer.write_push(Segment::TEMP, SWITCH_TEMP);
+        self.write_word(high[0].0 as u16);
+        self.vm_writer.write_arithmetic(Arithmetic::LT);
+        self.vm_writer.write_if(&low_label);
+        self.write_case_lookup(high, otherwise);
+        self.vm_writer.write_label(&low_label);
+        self.write_case_lookup(low, otherwise);
+    }
+
+    /// Returns the line, counted from 1, of the byte at `offset`.
+    fn line_of(&self, offset: usize) -> usize {
+        self.source[..offset].matches('\n').count() + 1
+    }
+
     pub fn compile_do(&mut self) {
         // parse do
         self.process(Token::Keyword("do"));
@@ -376,7 +1121,7 @@ where
         // parse return
         self.process(Token::Keyword("return"));
 
-        if let Some(Token::Symbol(';')) = self.tokenizer.peek() {
+        if let Some(Token::Symbol(';')) = self.peek_token() {
             self.process(Token::Symbol(';'));
         } else {
             self.compile_expression();
@@ -390,59 +1135,275 @@ where
     }
 
     pub fn compile_expression(&mut self) {
-        self.compile_term();
-        while let Some(token) = self.tokenizer.peek() {
-            match *token {
-                Token::Symbol('+' | '-' | '*' | '/' | '&' | '|' | '<' | '>' | '=') => {
-                    if let Some(Token::Symbol(op)) = self.tokenizer.next() {
-                        self.compile_term();
-                        match op {
-                            '+' => self.vm_writer.write_arithmetic(Arithmetic::ADD),
-                            '-' => self.vm_writer.write_arithmetic(Arithmetic::SUB),
-                            '*' => self.vm_writer.write_call("Math.multiply", 2),
-                            '/' => self.vm_writer.write_call("Math.divide", 2),
-                            '&' => self.vm_writer.write_arithmetic(Arithmetic::AND),
-                            '|' => self.vm_writer.write_arithmetic(Arithmetic::OR),
-                            '<' => self.vm_writer.write_arithmetic(Arithmetic::LT),
-                            '>' => self.vm_writer.write_arithmetic(Arithmetic::GT),
-                            '=' => self.vm_writer.write_arithmetic(Arithmetic::EQ),
-                            _ => {}
-                        };
-                    }
-                }
-                _ => break,
-            }
-        }
+        let start = self.peek_span().start;
+        self.compile_operations(start, 0);
     }
 
-    pub fn compile_term(&mut self) {
-        match self.tokenizer.next() {
-            Some(Token::IntConstant(c)) => {
-                self.vm_writer.write_push(Segment::CONSTANT, c);
+    /// Compiles a term followed by the binary operators binding at least as
+    /// tightly as `min_precedence`, along with their right operands. The
+    /// expression starts at `start` in the source.
+    fn compile_operations(&mut self, start: usize, min_precedence: u8) {
+        self.compile_term();
+        // conventional precedence of the previous operator, to warn about
+        // Jack's evaluation order once per expression
+        let mut previous = None;
+        let mut warned = false;
+        while let Some(op) = self.peek_token().and_then(binary_operator) {
+            let precedence = match self.precedence {
+                Precedence::Jack => 1,
+                Precedence::Conventional => conventional_precedence(op),
+            };
+            if precedence < min_precedence {
+                break;
             }
-            Some(Token::StringConst(c)) => {
-                self.vm_writer.write_push(Segment::CONSTANT, c.len());
-                self.vm_writer.write_call("String.new", 1);
-                for char in c.bytes() {
-                    self.vm_writer.write_push(Segment::CONSTANT, char.into());
-                    self.vm_writer.write_call("String.appendChar", 2);
+            self.next_token();
+            let op_span = self.span;
+            if matches!(op, "&&" | "||") {
+                self.compile_short_circuit(op, precedence + 1);
+                self.boolean = true;
+            } else {
+                let left_boolean = self.boolean;
+                let effects = self.effects;
+                let right = self.vm_writer.position();
+                self.compile_operand(precedence + 1);
+                let right_boolean = self.boolean;
+                if matches!(op, "&" | "|")
+                    && left_boolean
+                    && right_boolean
+                    && self.effects > effects
+                {
+                    self.eager_operator(op, op_span);
                 }
+                self.boolean = match op {
+                    "<" | ">" | "=" | "<=" | ">=" | "!=" => true,
+                    "&" | "|" => left_boolean && right_boolean,
+                    _ => false,
+                };
+                self.write_operator(op, right);
             }
 
-            Some(Token::Keyword(c @ ("true" | "false" | "null" | "this"))) => match c {
-                "null" | "false" => self.vm_writer.write_push(Segment::CONSTANT, 0),
-                "true" => {
-                    self.vm_writer.write_push(Segment::CONSTANT, 0);
-                    self.vm_writer.write_arithmetic(Arithmetic::NOT)
+            if self.precedence == Precedence::Jack {
+                let conventional = conventional_precedence(op);
+                if !warned && previous.is_some_and(|previous| conventional > previous) {
+                    self.evaluation_order(start, op, op_span);
+                    warned = true;
                 }
-                "this" => self.vm_writer.write_push(Segment::POINTER, 0),
-                _ => {}
-            },
-            Some(Token::Symbol('(')) => {
-                self.compile_expression();
-                self.process(Token::Symbol(')'));
+                previous = Some(conventional);
             }
-            Some(Token::Identifier(ident)) => {
+        }
+    }
+
+    /// Compiles the right operand of a binary operator: a term under Jack
+    /// precedence, or the operators binding at least as tightly as
+    /// `min_precedence` under conventional precedence.
+    fn compile_operand(&mut self, min_precedence: u8) {
+        match self.precedence {
+            Precedence::Jack => self.compile_term(),
+            Precedence::Conventional => {
+                let start = self.peek_span().start;
+                self.compile_operations(start, min_precedence);
+            }
+        }
+    }
+
+    /// Writes the code applying `op` to the two values on the stack, the code
+    /// of the right one starting at `right`.
+    fn write_operator(&mut self, op: &str, right: usize) {
+        match op {
+            "+" => self.vm_writer.write_arithmetic(Arithmetic::ADD),
+            "-" => self.vm_writer.write_arithmetic(Arithmetic::SUB),
+            "*" => self.write_runtime_call("Math.multiply", 2),
+            "/" => self.write_runtime_call("Math.divide", 2),
+            "&" => self.vm_writer.write_arithmetic(Arithmetic::AND),
+            "|" => self.vm_writer.write_arithmetic(Arithmetic::OR),
+            "<" => self.vm_writer.write_arithmetic(Arithmetic::LT),
+            ">" => self.vm_writer.write_arithmetic(Arithmetic::GT),
+            "=" => self.vm_writer.write_arithmetic(Arithmetic::EQ),
+            "<=" => {
+                self.vm_writer.write_arithmetic(Arithmetic::GT);
+                self.vm_writer.write_arithmetic(Arithmetic::NOT);
+            }
+            ">=" => {
+                self.vm_writer.write_arithmetic(Arithmetic::LT);
+                self.vm_writer.write_arithmetic(Arithmetic::NOT);
+            }
+            "!=" => {
+                self.vm_writer.write_arithmetic(Arithmetic::EQ);
+                self.vm_writer.write_arithmetic(Arithmetic::NOT);
+            }
+            "%" => self.write_runtime_call(&format!("{}.mod", runtime::CLASS), 2),
+            "<<" => match self.vm_writer.take_constant(right) {
+                // doubled in place, through temp 0, rather than called
+                Some(n) if n < 16 => {
+                    for _ in 0..n {
+                        self.vm_writer.write_pop(Segment::TEMP, 0);
+                        self.vm_writer.write_push(Segment::TEMP, 0);
+                        self.vm_writer.write_push(Segment::TEMP, 0);
+                        self.vm_writer.write_arithmetic(Arithmetic::ADD);
+                    }
+                }
+                Some(_) => {
+                    self.vm_writer.write_pop(Segment::TEMP, 0);
+                    self.vm_writer.write_push(Segment::CONSTANT, 0);
+                }
+                None => self.write_runtime_call(&format!("{}.shiftLeft", runtime::CLASS), 2),
+            },
+            // halving has no VM command and takes a division, rounded down
+            // for negative numbers, so only the amounts leaving nothing or
+            // the sign bit to divide are compiled in place
+            ">>" => match self.vm_writer.take_constant(right) {
+                Some(0) => {}
+                Some(n) if n > 14 => {
+                    self.vm_writer.write_push(Segment::CONSTANT, 0);
+                    self.vm_writer.write_arithmetic(Arithmetic::LT);
+                }
+                Some(n) => {
+                    self.vm_writer.write_push(Segment::CONSTANT, n);
+                    self.write_runtime_call(&format!("{}.shiftRight", runtime::CLASS), 2);
+                }
+                None => self.write_runtime_call(&format!("{}.shiftRight", runtime::CLASS), 2),
+            },
+            _ => {}
+        }
+    }
+
+    /// Compiles the right operand of `&&` or `||`, the left one being on the
+    /// stack, only evaluating it when the left one does not decide the
+    /// result. Leaves true (-1) or false (0) on the stack.
+    fn compile_short_circuit(&mut self, operator: &str, min_precedence: u8) {
+        let right_label = self.generate_control_label();
+        let end_label = self.generate_control_label();
+        if operator == "&&" {
+            self.vm_writer.write_if(&right_label);
+            self.vm_writer.write_push(Segment::CONSTANT, 0);
+            self.vm_writer.write_goto(&end_label);
+            self.vm_writer.write_label(&right_label);
+            self.compile_condition(min_precedence);
+        } else {
+            let true_label = right_label;
+            self.vm_writer.write_if(&true_label);
+            self.compile_condition(min_precedence);
+            self.vm_writer.write_goto(&end_label);
+            self.vm_writer.write_label(&true_label);
+            self.vm_writer.write_push(Segment::CONSTANT, 0);
+            self.vm_writer.write_arithmetic(Arithmetic::NOT);
+        }
+        self.vm_writer.write_label(&end_label);
+    }
+
+    /// Compiles an operand, turning any value other than 0 into true.
+    fn compile_condition(&mut self, min_precedence: u8) {
+        self.compile_operand(min_precedence);
+        if !self.boolean {
+            self.vm_writer.write_push(Segment::CONSTANT, 0);
+            self.vm_writer.write_arithmetic(Arithmetic::EQ);
+            self.vm_writer.write_arithmetic(Arithmetic::NOT);
+        }
+    }
+
+    /// Warns that `op`, just compiled along with its right operand, applies
+    /// to everything before it in the expression starting at `start`, where
+    /// conventional precedence would have it bind tighter.
+    fn evaluation_order(&mut self, start: usize, op: &str, op_span: Span) {
+        let left = self.source[start..op_span.start].trim_end();
+        let right = self.source[op_span.end..self.span.end].trim_start();
+        self.diagnostics.push(
+            Diagnostic::warning(
+                Code::EvaluationOrder,
+                op_span,
+                format!("`{op}` applies to the result of `{left}`"),
+            )
+            .with_note(format!(
+                "Jack evaluates operators from left to right, so this computes `({left}) {op} {right}`"
+            ))
+            .with_note(
+                "add parentheses to make the order explicit, or build with `--precedence=conventional`",
+            ),
+        );
+    }
+
+    /// Warns about `&` or `|` combining conditions when its right operand,
+    /// evaluated whatever the left one, calls a subroutine or reads an array.
+    fn eager_operator(&mut self, op: &str, span: Span) {
+        if self.dialect == Dialect::Standard {
+            return;
+        }
+        let short_circuit = format!("{op}{op}");
+        self.diagnostics.push(
+            Diagnostic::warning(
+                Code::EagerOperator,
+                span,
+                format!(
+                    "`{op}` evaluates its right operand even when the left one decides the result"
+                ),
+            )
+            .with_note("the right operand calls a subroutine or reads an array")
+            .with_fix(
+                format!("use `{short_circuit}` to only evaluate it when needed"),
+                span,
+                &short_circuit,
+            )
+            .maybe_incorrect(),
+        );
+    }
+
+    pub fn compile_term(&mut self) {
+        self.boolean = false;
+        if !self.peek_token().is_some_and(starts_term) {
+            self.expected_next("an expression");
+            return;
+        }
+
+        match self.next_token() {
+            Some(Token::IntConstant(c)) => {
+                let word = self.int_constant_word(c);
+                self.write_word(word);
+            }
+            Some(Token::StringConst(c)) => {
+                let codes = self.char_codes(c, self.span);
+                self.vm_writer.write_push(Segment::CONSTANT, codes.len());
+                self.write_runtime_call("String.new", 1);
+                for code in codes {
+                    self.vm_writer.write_push(Segment::CONSTANT, code);
+                    self.write_runtime_call("String.appendChar", 2);
+                }
+            }
+            Some(Token::CharConst(c)) => {
+                let code = self.char_constant(c);
+                self.write_word(code);
+            }
+
+            Some(Token::Keyword(c @ ("true" | "false" | "null" | "this"))) => match c {
+                "null" => self.vm_writer.write_push(Segment::CONSTANT, 0),
+                "false" => {
+                    self.vm_writer.write_push(Segment::CONSTANT, 0);
+                    self.boolean = true;
+                }
+                "true" => {
+                    self.boolean = true;
+                    self.vm_writer.write_push(Segment::CONSTANT, 0);
+                    self.vm_writer.write_arithmetic(Arithmetic::NOT)
+                }
+                "this" => {
+                    if self.subroutine_category == "function" {
+                        self.no_this(
+                            self.span,
+                            format!(
+                                "`this` cannot be used in function `{}.{}`",
+                                self.class_name, self.subroutine_name
+                            ),
+                        );
+                    }
+                    self.vm_writer.write_push(Segment::POINTER, 0)
+                }
+                _ => {}
+            },
+            Some(Token::Symbol('(')) => {
+                self.compile_expression();
+                self.process(Token::Symbol(')'));
+            }
+            Some(Token::Identifier(ident)) => {
+                let ident_span = self.span;
                 let mut subroutine_name = String::new();
                 let index = self.index_of(ident);
                 let _type = self.type_of(ident);
@@ -453,9 +1414,10 @@ where
                     subroutine_name.push_str(ident);
                 }
 
-                match self.tokenizer.peek() {
+                match self.peek_token() {
                     Some(Token::Symbol('[')) => {
-                        if let (Some(segment), Some(index)) = (segment, index) {
+                        self.effects += 1;
+                        if let Some((segment, index)) = self.resolve_variable(ident) {
                             self.vm_writer.write_push(segment, index);
                         }
                         self.process(Token::Symbol('['));
@@ -466,39 +1428,82 @@ where
                         self.process(Token::Symbol(']'));
                     }
                     Some(Token::Symbol('(' | '.')) => {
+                        self.effects += 1;
                         let mut n_args = 0;
-                        if let Some(Token::Symbol('.')) = self.tokenizer.peek() {
+                        let call;
+                        if let Some(Token::Symbol('.')) = self.peek_token() {
+                            if segment.is_none() {
+                                self.check_class(ident, &[]);
+                            }
+                            let class = subroutine_name.clone();
                             subroutine_name.push('.');
                             self.process(Token::Symbol('.'));
 
                             // parse subroutineName
-                            match self.tokenizer.next() {
-                                Some(Token::Identifier(ident)) => {
-                                    subroutine_name.push_str(ident);
+                            call = match self.next_token() {
+                                // Class.CONSTANT or Enum.MEMBER
+                                Some(Token::Identifier(name))
+                                    if segment.is_none()
+                                        && self.dialect == Dialect::Extended
+                                        && self.peek_token() != Some(Token::Symbol('(')) =>
+                                {
+                                    self.compile_constant(&class, name);
+                                    return;
                                 }
-                                _ => println!("error: syntax error"),
-                            }
+                                Some(Token::Identifier(name)) => {
+                                    subroutine_name.push_str(name);
+                                    let style = match segment {
+                                        Some(_) => CallStyle::Method(ident, ident_span),
+                                        None => CallStyle::Function,
+                                    };
+                                    Some(Call {
+                                        class,
+                                        name,
+                                        span: self.span,
+                                        style,
+                                    })
+                                }
+                                found => {
+                                    self.expected("a subroutine name", found);
+                                    None
+                                }
+                            };
                         } else {
                             // subroutine() which is implicitly this.subroutine()
                             subroutine_name = format!("{}.{}", self.class_name, ident);
                             // push `this` as receiver
                             self.vm_writer.write_push(Segment::POINTER, 0);
                             n_args += 1;
+                            call = Some(Call {
+                                class: self.class_name.clone(),
+                                name: ident,
+                                span: ident_span,
+                                style: CallStyle::Implicit,
+                            });
                         }
 
                         self.process(Token::Symbol('('));
                         // push the receiver
                         if let (Some(segment), Some(index)) = (segment, index) {
+                            self.used_vars.insert(ident);
+                            self.check_field(ident, ident_span);
                             self.vm_writer.write_push(segment, index);
                             n_args += 1;
                         }
-                        n_args += self.compile_expression_list();
+                        let n_arguments = self.compile_expression_list();
+                        n_args += n_arguments;
                         self.vm_writer.write_call(subroutine_name.as_str(), n_args);
                         self.process(Token::Symbol(')'));
+                        if let Some(call) = call {
+                            self.check_call(call, n_arguments);
+                        }
+                    }
+                    _ if segment == Some(Segment::CONSTANT) => {
+                        self.write_word(self.value_of(ident).unwrap_or_default());
                     }
                     _ => {
                         // variable
-                        if let (Some(segment), Some(index)) = (segment, index) {
+                        if let Some((segment, index)) = self.resolve_variable(ident) {
                             self.vm_writer.write_push(segment, index);
                         }
                     }
@@ -506,25 +1511,128 @@ where
             }
             Some(Token::Symbol(op @ ('-' | '~'))) => {
                 self.compile_term();
+                if op == '-' {
+                    self.boolean = false;
+                }
                 match op {
                     '-' => self.vm_writer.write_arithmetic(Arithmetic::NEG),
                     '~' => self.vm_writer.write_arithmetic(Arithmetic::NOT),
                     _ => {}
                 }
             }
-            _ => println!("error: syntax error"),
+            _ => {}
+        }
+    }
+
+    /// Pushes the 16-bit `word`. Words with the highest bit set are pushed as
+    /// the complement of a constant a `push constant` can load.
+    fn write_word(&mut self, word: u16) {
+        if usize::from(word) <= hack::MAX_CONSTANT {
+            self.vm_writer.write_push(Segment::CONSTANT, word.into());
+        } else {
+            self.vm_writer.write_push(Segment::CONSTANT, (!word).into());
+            self.vm_writer.write_arithmetic(Arithmetic::NOT);
+        }
+    }
+
+    /// Returns the word of the integer constant `value` that was just
+    /// consumed. Words with the highest bit set can only be written in
+    /// hexadecimal or binary.
+    fn int_constant_word(&mut self, value: usize) -> u16 {
+        let text = &self.source[self.span.start..self.span.end];
+        if value <= hack::MAX_CONSTANT
+            || tokenizer::has_radix_prefix(text) && value <= usize::from(u16::MAX)
+        {
+            value as u16
+        } else {
+            let note = if tokenizer::has_radix_prefix(text) {
+                "hexadecimal and binary constants hold at most 16 bits"
+            } else if self.dialect == Dialect::Extended {
+                "integer constants go from 0 to 32767, write -32768 as `0x8000`"
+            } else {
+                "integer constants go from 0 to 32767"
+            };
+            self.diagnostics.push(
+                Diagnostic::error(
+                    Code::IntegerOverflow,
+                    self.span,
+                    format!("integer constant `{text}` is out of range"),
+                )
+                .with_note(note),
+            );
+            0
+        }
+    }
+
+    /// Returns the code of the character constant `text` that was just
+    /// consumed.
+    fn char_constant(&mut self, text: &str) -> u16 {
+        let n_diagnostics = self.diagnostics.len();
+        let codes = self.char_codes(text, self.span);
+        if codes.len() != 1 && self.diagnostics.len() == n_diagnostics {
+            self.diagnostics.push(Diagnostic::error(
+                Code::CharacterCount,
+                self.span,
+                format!(
+                    "character constant must contain exactly one character, found {}",
+                    codes.len()
+                ),
+            ));
+        }
+        codes.first().map_or(0, |&code| code as u16)
+    }
+
+    /// Returns the character codes of the string or character constant
+    /// `text`, read from `span` along with its quotes. The extended dialect
+    /// decodes escape sequences and reports characters outside of the Hack
+    /// character set; the standard one takes bytes as they are.
+    fn char_codes(&mut self, text: &str, span: Span) -> Vec<usize> {
+        if self.dialect == Dialect::Standard {
+            return text.bytes().map(usize::from).collect();
+        }
+        let mut codes = Vec::new();
+        let mut chars = text.char_indices();
+        while let Some((i, c)) = chars.next() {
+            let start = span.start + 1 + i;
+            if c == '\\' {
+                // the tokenizer never ends a constant on a `\`
+                let Some((_, escaped)) = chars.next() else {
+                    break;
+                };
+                match hack::escaped(escaped) {
+                    Some(code) => codes.push(code),
+                    None => self.diagnostics.push(
+                        Diagnostic::error(
+                            Code::UnknownEscape,
+                            Span::new(start, start + 1 + escaped.len_utf8()),
+                            format!("unknown escape sequence `\\{escaped}`"),
+                        )
+                        .with_note(
+                            "the escape sequences are `\\n`, `\\b`, `\\\"`, `\\'` and `\\\\`",
+                        ),
+                    ),
+                }
+            } else if (' '..='~').contains(&c) {
+                codes.push(c as usize);
+            } else {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        Code::UnsupportedCharacter,
+                        Span::new(start, start + c.len_utf8()),
+                        format!("`{c}` is not in the Hack character set"),
+                    )
+                    .with_note("the Hack character set is printable ASCII, with `\\n` for a new line and `\\b` for backspace"),
+                );
+            }
         }
+        codes
     }
 
     pub fn compile_expression_list(&mut self) -> usize {
         let mut count = 0;
-        while let Some(token) = self.tokenizer.peek() {
-            match *token {
-                Token::StringConst(_)
-                | Token::IntConstant(_)
-                | Token::Identifier(_)
-                | Token::Symbol('-' | '~' | '(')
-                | Token::Keyword("true" | "false" | "null" | "this") => {
+        while let Some(token) = self.peek_token() {
+            match token {
+                token if starts_term(token) => {
                     count += 1;
                     self.compile_expression();
                 }
@@ -532,7 +1640,359 @@ where
                 _ => break,
             }
         }
-        return count;
+        count
+    }
+
+    /// Looks up the variable `name` that was just consumed, reporting it if
+    /// it isn't declared.
+    fn resolve_variable(&mut self, name: &'a str) -> Option<(Segment, usize)> {
+        if self.kind_of(name) == Some(Category::Const) {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    Code::NotAVariable,
+                    self.span,
+                    format!("`{name}` is a constant, not a variable"),
+                )
+                .with_note("constants cannot be assigned or indexed"),
+            );
+            return None;
+        }
+        let segment = self.kind_of(name).map(category_to_segment);
+        let index = self.index_of(name);
+        if let (Some(segment), Some(index)) = (segment, index) {
+            self.used_vars.insert(name);
+            self.check_field(name, self.span);
+            return Some((segment, index));
+        }
+
+        let mut diagnostic = Diagnostic::error(
+            Code::UndeclaredVariable,
+            self.span,
+            format!("cannot find variable `{name}` in this scope"),
+        );
+        let candidates = self
+            .variable_names()
+            .into_iter()
+            .filter(|&candidate| candidate != "this");
+        if let Some(suggestion) = suggest::closest(name, candidates) {
+            diagnostic = diagnostic.with_fix(
+                format!("a variable with a similar name exists: `{suggestion}`"),
+                self.span,
+                suggestion,
+            );
+        }
+        self.diagnostics.push(diagnostic);
+        None
+    }
+
+    /// Pushes the constant `name` of `class`, or the member `name` of the
+    /// enum `class`, that was just consumed.
+    fn compile_constant(&mut self, class: &str, name: &str) {
+        let own = if class == self.class_name {
+            self.class_symbol_table.get(name)
+        } else {
+            self.class_symbol_table.get(&format!("{class}.{name}"))
+        };
+        if let Some(word) = own.and_then(|symbol| symbol.value) {
+            self.write_word(word);
+            return;
+        }
+        let interface = self.declarations.get(class);
+        if let Some(constant) = interface.and_then(|interface| interface.constant(name)) {
+            self.dependencies.insert(class.to_string());
+            self.write_word(constant.value as u16);
+            return;
+        }
+
+        let mut diagnostic = Diagnostic::error(
+            Code::UnknownConstant,
+            self.span,
+            format!("cannot find constant `{name}` in `{class}`"),
+        );
+        let candidates: Vec<&str> = if class == self.class_name {
+            self.class_symbol_table
+                .symbols()
+                .into_iter()
+                .filter(|symbol| symbol.kind == Category::Const)
+                .map(|symbol| symbol.name.as_str())
+                .collect()
+        } else {
+            interface
+                .iter()
+                .flat_map(|interface| &interface.constants)
+                .map(|constant| constant.name.as_str())
+                .collect()
+        };
+        if let Some(suggestion) = suggest::closest(name, candidates) {
+            diagnostic = diagnostic.with_fix(
+                format!("a constant with a similar name exists: `{suggestion}`"),
+                self.span,
+                suggestion,
+            );
+        } else if interface.is_none() && class != self.class_name {
+            diagnostic = diagnostic.with_note(format!(
+                "the constants of `{class}` are unknown, only those declared in source or interface files can be used"
+            ));
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Writes a call to the OS subroutine `name` the language relies on, such
+    /// as `Math.multiply` for `*`, recording it like the calls in the source.
+    fn write_runtime_call(&mut self, name: &str, n_args: usize) {
+        self.vm_writer.write_call(name, n_args);
+        let (class, subroutine) = name.split_once('.').expect("Expected a qualified name");
+        let kind = self
+            .declarations
+            .get(class)
+            .and_then(|interface| interface.subroutine(subroutine))
+            .map_or(SubroutineKind::Function, |signature| signature.kind);
+        self.calls.push(CallSite {
+            caller: format!("{}.{}", self.class_name, self.subroutine_name),
+            callee: name.to_string(),
+            kind,
+        });
+        if class != self.class_name {
+            self.references.insert(class.to_string());
+        }
+    }
+
+    /// Checks `call` against the declaration of the subroutine called, when
+    /// the interface of its class is known.
+    fn check_call(&mut self, call: Call<'a>, n_arguments: usize) {
+        if let CallStyle::Method(variable, _) = call.style {
+            if matches!(call.class.as_str(), "int" | "char" | "boolean") {
+                self.diagnostics.push(Diagnostic::error(
+                    Code::NotAnObject,
+                    call.span,
+                    format!(
+                        "`{variable}` is of type `{}`, which has no subroutines to call",
+                        call.class
+                    ),
+                ));
+                return;
+            }
+        }
+
+        if call.class == self.class_name || self.declarations.contains(&call.class) {
+            let declared = self
+                .declarations
+                .get(&call.class)
+                .and_then(|interface| interface.subroutine(call.name));
+            let kind = match (declared, call.style) {
+                (Some(signature), _) => signature.kind,
+                (None, CallStyle::Function) => SubroutineKind::Function,
+                (None, _) => SubroutineKind::Method,
+            };
+            self.calls.push(CallSite {
+                caller: format!("{}.{}", self.class_name, self.subroutine_name),
+                callee: format!("{}.{}", call.class, call.name),
+                kind,
+            });
+        }
+
+        let Some(interface) = self.declarations.get(&call.class) else {
+            // an unknown class is reported where it is named
+            return;
+        };
+        if call.class != self.class_name {
+            self.dependencies.insert(call.class.clone());
+            self.references.insert(call.class.clone());
+        }
+        let qualified = format!("{}.{}", call.class, call.name);
+        let Some(signature) = interface.subroutine(call.name) else {
+            let mut diagnostic = Diagnostic::error(
+                Code::UnknownSubroutine,
+                call.span,
+                format!(
+                    "cannot find subroutine `{}` in class `{}`",
+                    call.name, call.class
+                ),
+            );
+            let candidates = interface
+                .subroutines
+                .iter()
+                .map(|subroutine| subroutine.name.as_str());
+            if let Some(suggestion) = suggest::closest(call.name, candidates) {
+                diagnostic = diagnostic.with_fix(
+                    format!("a subroutine with a similar name exists: `{suggestion}`"),
+                    call.span,
+                    suggestion,
+                );
+            }
+            self.diagnostics.push(diagnostic);
+            return;
+        };
+        let declared = format!("`{qualified}` is declared as `{signature}`");
+
+        match (call.style, signature.kind) {
+            (CallStyle::Function, SubroutineKind::Method) => {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        Code::SubroutineKind,
+                        call.span,
+                        format!("`{qualified}` is a method and must be called on an object"),
+                    )
+                    .with_note(declared.as_str()),
+                );
+            }
+            (CallStyle::Method(variable, variable_span), SubroutineKind::Function)
+            | (CallStyle::Method(variable, variable_span), SubroutineKind::Constructor) => {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        Code::SubroutineKind,
+                        call.span,
+                        format!(
+                            "`{qualified}` is a {}, it cannot be called on `{variable}`",
+                            signature.kind
+                        ),
+                    )
+                    .with_note(declared.as_str())
+                    .with_fix(
+                        format!("call it through its class: `{qualified}`"),
+                        variable_span,
+                        &call.class,
+                    )
+                    .maybe_incorrect(),
+                );
+            }
+            (CallStyle::Implicit, SubroutineKind::Function | SubroutineKind::Constructor) => {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        Code::SubroutineKind,
+                        call.span,
+                        format!(
+                            "`{qualified}` is a {}, it cannot be called on `this`",
+                            signature.kind
+                        ),
+                    )
+                    .with_note(declared.as_str())
+                    .with_fix(
+                        format!("call it through its class: `{qualified}`"),
+                        call.span,
+                        &qualified,
+                    )
+                    .maybe_incorrect(),
+                );
+            }
+            (CallStyle::Implicit, SubroutineKind::Method)
+                if self.subroutine_category == "function" =>
+            {
+                self.no_this(
+                    call.span,
+                    format!(
+                        "cannot call method `{}` from function `{}.{}`",
+                        call.name, self.class_name, self.subroutine_name
+                    ),
+                );
+            }
+            _ => {}
+        }
+
+        let n_parameters = signature.parameters.len();
+        if n_arguments != n_parameters {
+            self.diagnostics.push(
+                Diagnostic::error(
+                    Code::ArgumentCount,
+                    call.span,
+                    format!(
+                        "`{qualified}` takes {} but {} {} supplied",
+                        count(n_parameters, "argument"),
+                        count(n_arguments, "argument"),
+                        if n_arguments == 1 { "was" } else { "were" }
+                    ),
+                )
+                .with_note(declared),
+            );
+        }
+    }
+
+    /// Names of the variables the current subroutine can refer to, fields
+    /// excepted in a function. Constants are not variables.
+    fn variable_names(&self) -> Vec<&str> {
+        let function = self.subroutine_category == "function";
+        self.subroutine_symbol_table
+            .names()
+            .chain(
+                self.class_symbol_table
+                    .symbols()
+                    .into_iter()
+                    .filter(|symbol| symbol.kind != Category::Const)
+                    .filter(|symbol| !(function && symbol.kind == Category::Field))
+                    .map(|symbol| symbol.name.as_str()),
+            )
+            .collect()
+    }
+
+    /// Reports the variable `name` at `span` if it is a field used in a
+    /// function.
+    fn check_field(&mut self, name: &str, span: Span) {
+        if self.subroutine_category == "function" && self.kind_of(name) == Some(Category::Field) {
+            self.no_this(
+                span,
+                format!(
+                    "field `{name}` cannot be used in function `{}.{}`",
+                    self.class_name, self.subroutine_name
+                ),
+            );
+        }
+    }
+
+    /// Reports a use of `this`, explicit or through a method call, in a
+    /// function.
+    fn no_this(&mut self, span: Span, message: String) {
+        self.diagnostics.push(
+            Diagnostic::error(Code::NoThis, span, message)
+                .with_note("functions are not called on an object, so there is no `this`"),
+        );
+    }
+
+    /// Checks that the class `name` that was just consumed exists, suggesting
+    /// a similarly named class, variable or one of `keywords` otherwise.
+    fn check_class(&mut self, name: &str, keywords: &[&str]) {
+        let own_enum = self
+            .class_symbol_table
+            .symbols()
+            .iter()
+            .any(|symbol| symbol.kind == Category::Const && symbol._type == name);
+        if name == self.class_name || own_enum {
+            return;
+        }
+        if self.declarations.contains(name) {
+            // the class has to be compiled again should `name` disappear
+            self.dependencies.insert(name.to_string());
+            self.references.insert(name.to_string());
+            return;
+        }
+
+        let mut diagnostic = Diagnostic::error(
+            Code::UnknownClass,
+            self.span,
+            format!("cannot find class `{name}`"),
+        );
+        let variables: Vec<&str> = if keywords.is_empty() {
+            self.variable_names()
+        } else {
+            Vec::new()
+        };
+        let candidates = self
+            .declarations
+            .class_names()
+            .chain([self.class_name.as_str()])
+            .chain(keywords.iter().copied())
+            .chain(variables);
+        if let Some(suggestion) = suggest::closest(name, candidates) {
+            diagnostic = diagnostic.with_fix(
+                format!("did you mean `{suggestion}`?"),
+                self.span,
+                suggestion,
+            );
+        } else {
+            diagnostic = diagnostic.with_note(
+                "classes must be in the same directory as the class using them, part of the OS or declared in an interface file",
+            );
+        }
+        self.diagnostics.push(diagnostic);
     }
 
     fn kind_of(&self, name: &str) -> Option<Category> {
@@ -552,6 +2012,12 @@ where
             .index_of(name)
             .or_else(|| self.class_symbol_table.index_of(name))
     }
+
+    fn value_of(&self, name: &str) -> Option<u16> {
+        self.subroutine_symbol_table
+            .value_of(name)
+            .or_else(|| self.class_symbol_table.value_of(name))
+    }
 }
 fn get_category(str: &str) -> Option<Category> {
     match str {
@@ -565,9 +2031,871 @@ fn get_category(str: &str) -> Option<Category> {
 
 fn category_to_segment(category: Category) -> Segment {
     match category {
+        Category::Const => Segment::CONSTANT,
         Category::Static => Segment::STATIC,
         Category::Field => Segment::THIS,
         Category::Arg => Segment::ARGUMENT,
         Category::Var => Segment::LOCAL,
     }
 }
+
+/// The binary operator `token` stands for, if any.
+fn binary_operator(token: Token) -> Option<&'static str> {
+    match token {
+        Token::Symbol('+') => Some("+"),
+        Token::Symbol('-') => Some("-"),
+        Token::Symbol('*') => Some("*"),
+        Token::Symbol('/') => Some("/"),
+        Token::Symbol('&') => Some("&"),
+        Token::Symbol('|') => Some("|"),
+        Token::Symbol('<') => Some("<"),
+        Token::Symbol('>') => Some(">"),
+        Token::Symbol('=') => Some("="),
+        Token::Operator("%") => Some("%"),
+        Token::Operator("<<") => Some("<<"),
+        Token::Operator(">>") => Some(">>"),
+        Token::Operator("<=") => Some("<="),
+        Token::Operator(">=") => Some(">="),
+        Token::Operator("!=") => Some("!="),
+        Token::Operator("&&") => Some("&&"),
+        Token::Operator("||") => Some("||"),
+        _ => None,
+    }
+}
+
+/// How tightly `op` binds under conventional precedence, the higher the
+/// tighter.
+fn conventional_precedence(op: &str) -> u8 {
+    match op {
+        "*" | "/" | "%" => 7,
+        "+" | "-" => 6,
+        "<<" | ">>" => 5,
+        "<" | ">" | "=" | "<=" | ">=" | "!=" => 4,
+        "&" | "|" => 3,
+        "&&" => 2,
+        _ => 1,
+    }
+}
+
+fn starts_term(token: Token) -> bool {
+    matches!(
+        token,
+        Token::StringConst(_)
+            | Token::CharConst(_)
+            | Token::IntConstant(_)
+            | Token::Identifier(_)
+            | Token::Symbol('-' | '~' | '(')
+            | Token::Keyword("true" | "false" | "null" | "this")
+    )
+}
+
+/// The result of compiling a class.
+pub struct Compilation {
+    /// The VM code, unless an error was reported.
+    pub output: Option<String>,
+    /// Every diagnostic, in source order.
+    pub diagnostics: Vec<Diagnostic>,
+    /// The other classes whose interface calls were checked against or used
+    /// as a type, so the class is compiled again when one of them changes.
+    pub dependencies: Vec<String>,
+    /// Every call to a subroutine of a known class, in source order, those
+    /// generated for `*`, `/`, string constants and constructors included.
+    pub calls: Vec<CallSite>,
+    /// The other classes named in the class, as types or in calls.
+    pub references: Vec<String>,
+    /// The variables of the class and of its subroutines.
+    pub symbols: ClassSymbols,
+}
+
+/// A call from a subroutine of the compiled class.
+pub struct CallSite {
+    /// `Class.subroutine` making the call.
+    pub caller: String,
+    /// `Class.subroutine` called.
+    pub callee: String,
+    /// Kind of the subroutine called, as declared or, for classes only
+    /// available compiled, as called.
+    pub kind: SubroutineKind,
+}
+
+/// A subroutine call, kept to check it against the declaration of the
+/// subroutine once its arguments are counted.
+struct Call<'a> {
+    class: String,
+    name: &'a str,
+    // span of the subroutine name
+    span: Span,
+    style: CallStyle<'a>,
+}
+
+#[derive(Copy, Clone)]
+enum CallStyle<'a> {
+    /// `Class.subroutine()`
+    Function,
+    /// `variable.subroutine()`, along with the variable and its span
+    Method(&'a str, Span),
+    /// `subroutine()`, a method called on `this`
+    Implicit,
+}
+
+/// A `var` declaration, kept to point at unused variables.
+struct VarDec<'a> {
+    span: Span,
+    names: Vec<(&'a str, Span)>,
+}
+
+/// Slot of the `temp` segment holding the value of a `switch` while it is
+/// looked up among the cases; `temp 0` is used by array assignments.
+const SWITCH_TEMP: usize = 1;
+
+/// Number of cases up to which a `switch` compares its value to each of them
+/// in turn rather than searching by halves.
+const LINEAR_CASES: usize = 4;
+
+const STATEMENT_KEYWORDS: &[&str] = &["let", "if", "else", "while", "do", "return", "var"];
+
+const CLASS_KEYWORDS: &[&str] = &["constructor", "function", "method", "static", "field"];
+
+/// Suggests one of `keywords` for `name`, an identifier found where a keyword
+/// was expected.
+fn suggest_keyword(diagnostic: &mut Diagnostic, name: &str, span: Span, keywords: &[&str]) {
+    if let Some(keyword) = suggest::closest(name, keywords.iter().copied()) {
+        diagnostic.fix = Some(Fix {
+            message: format!("did you mean the keyword `{keyword}`?"),
+            span,
+            replacement: String::from(keyword),
+            applicability: Applicability::MachineApplicable,
+        });
+    }
+}
+
+/// Formats `n` followed by `noun`, pluralized unless `n` is one.
+fn count(n: usize, noun: &str) -> String {
+    match n {
+        1 => format!("1 {noun}"),
+        n => format!("{n} {noun}s"),
+    }
+}
+
+fn describe(token: Option<Token>) -> String {
+    match token {
+        Some(token) => token.to_string(),
+        None => String::from("end of file"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interface::ClassInterface, os_api};
+
+    fn compile(source: &str) -> Compilation {
+        compile_in(Dialect::Standard, source)
+    }
+
+    fn compile_in(dialect: Dialect, source: &str) -> Compilation {
+        compile_with(dialect, Precedence::Jack, source, &os_api::declarations())
+    }
+
+    fn compile_with(
+        dialect: Dialect,
+        precedence: Precedence,
+        source: &str,
+        declarations: &Declarations,
+    ) -> Compilation {
+        let tokenizer = tokenizer::Tokenizer::build(source)
+            .unwrap()
+            .with_dialect(dialect)
+            .spanned()
+            .filter(|(_, x)| {
+                !matches!(
+                    x,
+                    Token::Whitespace(_) | Token::SingleLineComment(_) | Token::BlockComment(_)
+                )
+            });
+        let mut compilation_engine = CompilationEngine::new(source, tokenizer, declarations)
+            .with_dialect(dialect)
+            .with_precedence(precedence);
+        compilation_engine.compile_class();
+        compilation_engine.finish()
+    }
+
+    fn counter(kind: &str, body: &str) -> String {
+        format!(
+            "class Counter {{\n    field int count;\n    static int total;\n    {kind} void f() {{\n        {body}\n        return;\n    }}\n}}\n"
+        )
+    }
+
+    #[test]
+    fn suggests_fields_only_where_they_can_be_used() {
+        let compilation = compile(&counter("method", "let cuont = 1;"));
+        let fix = compilation.diagnostics[0].fix.as_ref().unwrap();
+        assert_eq!(fix.replacement, "count");
+
+        let compilation = compile(&counter("function", "let cuont = 1;"));
+        assert_eq!(compilation.diagnostics[0].code, Code::UndeclaredVariable);
+        assert!(compilation.diagnostics[0].fix.is_none());
+
+        let compilation = compile(&counter("function", "let totl = 1;"));
+        let fix = compilation.diagnostics[0].fix.as_ref().unwrap();
+        assert_eq!(fix.replacement, "total");
+    }
+
+    #[test]
+    fn marks_fixes_changing_behaviour_as_maybe_incorrect() {
+        let source = counter(
+            "function",
+            "if ((total = 0) & (Counter.g() = 0)) { let total = 1; }",
+        );
+        let source = source.replace(
+            "}\n}\n",
+            "}\n    function int g() {\n        return 0;\n    }\n}\n",
+        );
+        let compilation = compile_in(Dialect::Extended, &source);
+        let diagnostic = &compilation.diagnostics[0];
+        assert_eq!(diagnostic.code, Code::EagerOperator);
+        let fix = diagnostic.fix.as_ref().unwrap();
+        assert_eq!(fix.replacement, "&&");
+        assert_eq!(fix.applicability, Applicability::MaybeIncorrect);
+
+        let compilation = compile(&counter("function", "let total = 1\n"));
+        let fix = compilation.diagnostics[0].fix.as_ref().unwrap();
+        assert_eq!(fix.replacement, ";");
+        assert_eq!(fix.applicability, Applicability::MachineApplicable);
+    }
+
+    #[test]
+    fn does_not_suggest_constants_as_variables() {
+        let source = counter("method", "let MAXX = 1;").replace(
+            "static int total;",
+            "static int total;\n    const int MAX = 9;\n    enum Dir { UP, DOWN }",
+        );
+        let compilation = compile_in(Dialect::Extended, &source);
+        assert_eq!(compilation.diagnostics[0].code, Code::UndeclaredVariable);
+        assert!(compilation.diagnostics[0].fix.is_none());
+    }
+
+    #[test]
+    fn forgets_the_variables_of_previous_subroutines() {
+        let source = counter("method", "var int step;\n        let step = 1;").replace(
+            "}\n}\n",
+            "}\n    method void g() {\n        let step = 2;\n        return;\n    }\n}\n",
+        );
+        let compilation = compile(&source);
+        assert_eq!(compilation.diagnostics.len(), 1);
+        assert_eq!(compilation.diagnostics[0].code, Code::UndeclaredVariable);
+        assert!(compilation.diagnostics[0].message.contains("`step`"));
+        let g = &compilation.symbols.subroutines[1];
+        assert!(g.symbols.iter().all(|symbol| symbol.name != "step"));
+    }
+
+    #[test]
+    fn reports_fields_used_in_functions() {
+        for body in [
+            "let count = 1;",
+            "let total = count;",
+            "let total = count[0];",
+            "do count.dispose();",
+        ] {
+            let source = counter("function", body).replace("int count", "Array count");
+            let compilation = compile(&source);
+            let codes: Vec<_> = compilation.diagnostics.iter().map(|x| x.code).collect();
+            assert_eq!(codes, [Code::NoThis], "{body}");
+        }
+        let compilation = compile(&counter("method", "let total = count;"));
+        assert!(compilation.diagnostics.is_empty());
+    }
+
+    /// Compiles `body` in a method of the extended dialect, returning the
+    /// VM code after the method's receiver is set.
+    fn method_code(body: &str) -> String {
+        let compilation = compile_in(Dialect::Extended, &counter("method", body));
+        assert!(compilation.diagnostics.is_empty());
+        let output = compilation.output.unwrap();
+        let start = output.find("pop pointer 0\n").unwrap() + "pop pointer 0\n".len();
+        output[start..].to_string()
+    }
+
+    #[test]
+    fn continues_for_loops_at_their_step() {
+        let code = method_code(
+            "var int i;\n        for (let i = 0; i < 3; let i = i + 1) { if (i = 1) { continue; } let total = i; }",
+        );
+        let expected = [
+            "push constant 0",
+            "pop local 0",
+            "label CounterL0",
+            "push local 0",
+            "push constant 3",
+            "lt",
+            "not",
+            "if-goto CounterL2",
+            "push local 0",
+            "push constant 1",
+            "eq",
+            "not",
+            "if-goto CounterL3",
+            "goto CounterL1",
+            "goto CounterL4",
+            "label CounterL3",
+            "label CounterL4",
+            "push local 0",
+            "pop static 0",
+            "label CounterL1",
+            "push local 0",
+            "push constant 1",
+            "add",
+            "pop local 0",
+            "goto CounterL0",
+            "label CounterL2",
+            "push constant 0",
+            "return",
+        ];
+        assert_eq!(code.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn notes_that_for_variables_are_declared_beforehand() {
+        let compilation = compile_in(
+            Dialect::Extended,
+            &counter("method", "for (let i = 0; i < 3; let i = i + 1) { }"),
+        );
+        let diagnostic = &compilation.diagnostics[0];
+        assert_eq!(diagnostic.code, Code::UndeclaredVariable);
+        assert!(diagnostic.notes[0].contains("declared beforehand"));
+        assert!(compilation.diagnostics[1..]
+            .iter()
+            .all(|d| d.notes.is_empty()));
+    }
+
+    #[test]
+    fn breaks_and_continues_the_innermost_loop() {
+        let code = method_code("while (true) { while (false) { break; } continue; }");
+        let expected = [
+            "label CounterL0",
+            "push constant 0",
+            "not",
+            "not",
+            "if-goto CounterL1",
+            "label CounterL3",
+            "push constant 0",
+            "not",
+            "if-goto CounterL4",
+            "goto CounterL4",
+            "goto CounterL3",
+            "label CounterL4",
+            "goto CounterL0",
+            "goto CounterL0",
+            "label CounterL1",
+            "push constant 0",
+            "return",
+        ];
+        assert_eq!(code.lines().collect::<Vec<_>>(), expected);
+
+        // `continue` in a `switch` continues the loop around it
+        let code = method_code("while (true) { switch (total) { case 1: { continue; } } }");
+        let lines: Vec<&str> = code.lines().collect();
+        assert_eq!(lines[0], "label CounterL0");
+        assert!(lines.contains(&"goto CounterL0"));
+        let case = lines
+            .iter()
+            .position(|&line| line == "label CounterL4")
+            .unwrap();
+        assert_eq!(lines[case + 1], "goto CounterL0");
+    }
+
+    #[test]
+    fn reports_break_and_continue_outside_loops() {
+        for body in [
+            "break;",
+            "continue;",
+            "if (true) { break; }",
+            "switch (total) { case 1: { continue; } }",
+        ] {
+            let compilation = compile_in(Dialect::Extended, &counter("method", body));
+            let codes: Vec<Code> = compilation.diagnostics.iter().map(|d| d.code).collect();
+            assert_eq!(codes, [Code::OutsideLoop], "{body}");
+        }
+        let compilation = compile_in(
+            Dialect::Extended,
+            &counter("method", "switch (total) { case 1: { break; } }"),
+        );
+        assert!(compilation.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn compiles_character_escapes() {
+        let code = method_code(r"let total = 'A' + '\n' + '\b' + '\'' + '\\';");
+        let pushes: Vec<&str> = code
+            .lines()
+            .filter(|line| line.starts_with("push"))
+            .collect();
+        assert_eq!(
+            pushes[..5],
+            [
+                "push constant 65",
+                "push constant 128",
+                "push constant 129",
+                "push constant 39",
+                "push constant 92",
+            ]
+        );
+
+        let code = method_code(r#"do Output.printString("a\"\n");"#);
+        let expected = [
+            "push constant 3",
+            "call String.new 1",
+            "push constant 97",
+            "call String.appendChar 2",
+            "push constant 34",
+            "call String.appendChar 2",
+            "push constant 128",
+            "call String.appendChar 2",
+        ];
+        assert_eq!(code.lines().take(8).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn reports_invalid_characters() {
+        for (body, code) in [
+            (r#"do Output.printString("\t");"#, Code::UnknownEscape),
+            (r"let total = '\0';", Code::UnknownEscape),
+            ("do Output.printString(\"é\");", Code::UnsupportedCharacter),
+            ("let total = 'ab';", Code::CharacterCount),
+            ("let total = '';", Code::CharacterCount),
+        ] {
+            let compilation = compile_in(Dialect::Extended, &counter("method", body));
+            let codes: Vec<Code> = compilation.diagnostics.iter().map(|d| d.code).collect();
+            assert_eq!(codes, [code], "{body}");
+        }
+
+        // the standard dialect keeps backslashes as they are
+        let compilation = compile(&counter("method", r#"do Output.printString("\n");"#));
+        assert!(compilation.diagnostics.is_empty());
+        let output = compilation.output.unwrap();
+        assert!(output.contains("push constant 2\ncall String.new 1\npush constant 92\n"));
+    }
+
+    #[test]
+    fn compiles_hexadecimal_and_binary_constants() {
+        let code = method_code("let total = 0x7FFF + 0b1010 + 0x8000 + 0xFFFF;");
+        let expected = [
+            "push constant 32767",
+            "push constant 10",
+            "add",
+            "push constant 32767",
+            "not",
+            "add",
+            "push constant 0",
+            "not",
+            "add",
+            "pop static 0",
+        ];
+        assert_eq!(code.lines().take(10).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn reports_constants_out_of_range() {
+        for (dialect, constant, note) in [
+            (Dialect::Standard, "32768", "from 0 to 32767"),
+            (Dialect::Extended, "32768", "`0x8000`"),
+            (Dialect::Extended, "0x10000", "16 bits"),
+            (Dialect::Extended, "0b10000000000000000", "16 bits"),
+        ] {
+            let body = format!("let total = {constant};");
+            let compilation = compile_in(dialect, &counter("method", &body));
+            let diagnostic = &compilation.diagnostics[0];
+            assert_eq!(diagnostic.code, Code::IntegerOverflow, "{constant}");
+            assert!(diagnostic.message.contains(constant));
+            assert!(diagnostic.notes[0].contains(note), "{constant}");
+            assert_eq!(compilation.diagnostics.len(), 1);
+        }
+    }
+
+    #[test]
+    fn compiles_constants_and_enum_members() {
+        let point = "class Point {
+    const int ORIGIN = -1;
+    enum Axis { X, Y = 4, Z }
+    function int z() {
+        return Axis.Z + Point.ORIGIN + ORIGIN;
+    }
+}
+";
+        let compilation = compile_in(Dialect::Extended, point);
+        assert!(compilation.diagnostics.is_empty());
+        let output = compilation.output.unwrap();
+        assert!(output.contains(
+            "push constant 5\npush constant 0\nnot\nadd\npush constant 0\nnot\nadd\nreturn\n"
+        ));
+
+        let mut declarations = os_api::declarations();
+        let interface = ClassInterface::scan(point, Dialect::Extended).unwrap();
+        declarations.add(interface).unwrap();
+        let main = "class Main {
+    function int main() {
+        return Axis.Y - Point.ORIGIN;
+    }
+}
+";
+        let compilation = compile_with(Dialect::Extended, Precedence::Jack, main, &declarations);
+        assert!(compilation.diagnostics.is_empty());
+        let output = compilation.output.unwrap();
+        assert!(output.contains("push constant 4\npush constant 0\nnot\nsub\nreturn\n"));
+        assert!(compilation.dependencies.contains(&String::from("Axis")));
+        assert!(compilation.dependencies.contains(&String::from("Point")));
+
+        let main = main.replace("Axis.Y", "Axis.W");
+        let compilation = compile_with(Dialect::Extended, Precedence::Jack, &main, &declarations);
+        assert_eq!(compilation.diagnostics[0].code, Code::UnknownConstant);
+    }
+
+    #[test]
+    fn short_circuits_and() {
+        let code = method_code("let total = (total = 0) && Counter.g();");
+        let expected = [
+            "push static 0",
+            "push constant 0",
+            "eq",
+            // only a true left operand evaluates the right one
+            "if-goto CounterL0",
+            "push constant 0",
+            "goto CounterL1",
+            "label CounterL0",
+            "call Counter.g 0",
+            "push constant 0",
+            "eq",
+            "not",
+            "label CounterL1",
+            "pop static 0",
+        ];
+        assert_eq!(code.lines().take(13).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn short_circuits_or() {
+        let code = method_code("let total = (total = 0) || (Counter.g() = 1);");
+        let expected = [
+            "push static 0",
+            "push constant 0",
+            "eq",
+            // a true left operand skips the right one
+            "if-goto CounterL0",
+            "call Counter.g 0",
+            "push constant 1",
+            "eq",
+            "goto CounterL1",
+            "label CounterL0",
+            "push constant 0",
+            "not",
+            "label CounterL1",
+            "pop static 0",
+        ];
+        assert_eq!(code.lines().take(13).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn marks_the_short_circuit_fix_as_maybe_incorrect() {
+        for (op, fixed) in [("&", "&&"), ("|", "||")] {
+            let body = format!("let total = (total = 0) {op} (Counter.g() = 1);");
+            let compilation = compile_in(Dialect::Extended, &counter("method", &body));
+            let diagnostic = &compilation.diagnostics[0];
+            assert_eq!(diagnostic.code, Code::EagerOperator);
+            let fix = diagnostic.fix.as_ref().unwrap();
+            assert_eq!(fix.replacement, fixed);
+            assert_eq!(fix.applicability, Applicability::MaybeIncorrect);
+        }
+
+        // nothing to warn about without side effects, or in the standard
+        // dialect which has no `&&`
+        let body = "let total = (total = 0) & (total = 1);";
+        let compilation = compile_in(Dialect::Extended, &counter("method", body));
+        assert!(compilation.diagnostics.is_empty());
+        let body = "let total = (total = 0) & (Counter.g() = 1);";
+        assert!(compile(&counter("method", body)).diagnostics.is_empty());
+    }
+
+    #[test]
+    fn applies_the_chosen_precedence() {
+        let source = counter("method", "let total = 1 + 2 * 3;");
+        let declarations = os_api::declarations();
+        let code = |precedence| {
+            let compilation = compile_with(Dialect::Standard, precedence, &source, &declarations);
+            let output = compilation.output.unwrap();
+            let start = output.find("pop pointer 0\n").unwrap() + "pop pointer 0\n".len();
+            let code: Vec<String> = output[start..].lines().take(6).map(String::from).collect();
+            (code, compilation.diagnostics)
+        };
+
+        let (jack, diagnostics) = code(Precedence::Jack);
+        let expected = [
+            "push constant 1",
+            "push constant 2",
+            "add",
+            "push constant 3",
+            "call Math.multiply 2",
+            "pop static 0",
+        ];
+        assert_eq!(jack, expected);
+        let codes: Vec<Code> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, [Code::EvaluationOrder]);
+        assert_eq!(
+            diagnostics[0].message,
+            "`*` applies to the result of `1 + 2`"
+        );
+
+        let (conventional, diagnostics) = code(Precedence::Conventional);
+        let expected = [
+            "push constant 1",
+            "push constant 2",
+            "push constant 3",
+            "call Math.multiply 2",
+            "add",
+            "pop static 0",
+        ];
+        assert_eq!(conventional, expected);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn warns_about_evaluation_order_once_per_expression() {
+        for (body, warnings) in [
+            ("let total = 2 * 3 + 1;", 0),
+            ("let total = (1 + 2) * 3;", 0),
+            ("let total = 1 + 2 * 3 - 4 * 5;", 1),
+            ("let total = 1 < 2 + 3;", 1),
+        ] {
+            let compilation = compile(&counter("method", body));
+            let codes: Vec<Code> = compilation.diagnostics.iter().map(|d| d.code).collect();
+            assert_eq!(codes, vec![Code::EvaluationOrder; warnings], "{body}");
+        }
+    }
+
+    #[test]
+    fn calls_the_runtime_for_operators_without_vm_commands() {
+        let code = method_code("let total = total % 3;");
+        let expected = [
+            "push static 0",
+            "push constant 3",
+            "call JackRuntime.mod 2",
+            "pop static 0",
+        ];
+        assert_eq!(code.lines().take(4).collect::<Vec<_>>(), expected);
+
+        for op in ["<<", ">>"] {
+            let code = method_code(&format!("let total = total {op} count;"));
+            let function = if op == "<<" {
+                "shiftLeft"
+            } else {
+                "shiftRight"
+            };
+            let expected = [
+                "push static 0",
+                "push this 0",
+                &format!("call JackRuntime.{function} 2"),
+                "pop static 0",
+            ];
+            assert_eq!(code.lines().take(4).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn shifts_by_constants_in_place() {
+        let code = method_code("let total = total << 2;");
+        let expected = [
+            "push static 0",
+            "pop temp 0",
+            "push temp 0",
+            "push temp 0",
+            "add",
+            "pop temp 0",
+            "push temp 0",
+            "push temp 0",
+            "add",
+            "pop static 0",
+        ];
+        assert_eq!(code.lines().take(10).collect::<Vec<_>>(), expected);
+
+        // every bit is shifted out
+        let code = method_code("let total = total << 16;");
+        let expected = [
+            "push static 0",
+            "pop temp 0",
+            "push constant 0",
+            "pop static 0",
+        ];
+        assert_eq!(code.lines().take(4).collect::<Vec<_>>(), expected);
+
+        let code = method_code("let total = total >> 0;");
+        assert_eq!(
+            code.lines().take(2).collect::<Vec<_>>(),
+            ["push static 0", "pop static 0"]
+        );
+
+        // only the sign bit is left
+        let code = method_code("let total = total >> 15;");
+        let expected = ["push static 0", "push constant 0", "lt", "pop static 0"];
+        assert_eq!(code.lines().take(4).collect::<Vec<_>>(), expected);
+
+        let code = method_code("let total = total >> 3;");
+        let expected = [
+            "push static 0",
+            "push constant 3",
+            "call JackRuntime.shiftRight 2",
+            "pop static 0",
+        ];
+        assert_eq!(code.lines().take(4).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn looks_up_few_cases_in_turn() {
+        let code = method_code(
+            "switch (total) { case 3: { let count = 1; } case 1: { let count = 2; break; } default: { let count = 3; } }",
+        );
+        let expected = [
+            "push static 0",
+            "pop temp 1",
+            // cases are compared by value, then `default`
+            "push temp 1",
+            "push constant 1",
+            "eq",
+            "if-goto CounterL2",
+            "push temp 1",
+            "push constant 3",
+            "eq",
+            "if-goto CounterL1",
+            "goto CounterL3",
+            // blocks in source order, none falling through to the next
+            "label CounterL1",
+            "push constant 1",
+            "pop this 0",
+            "goto CounterL0",
+            "label CounterL2",
+            "push constant 2",
+            "pop this 0",
+            "goto CounterL0",
+            "goto CounterL0",
+            "label CounterL3",
+            "push constant 3",
+            "pop this 0",
+            "goto CounterL0",
+            "label CounterL0",
+            "push constant 0",
+            "return",
+        ];
+        assert_eq!(code.lines().collect::<Vec<_>>(), expected);
+
+        // without a `default`, a value matching no case skips the blocks
+        let code = method_code("switch (total) { case 1: { let count = 2; } }");
+        let lines: Vec<&str> = code.lines().collect();
+        assert_eq!(lines[6..8], ["goto CounterL0", "label CounterL1"]);
+    }
+
+    #[test]
+    fn searches_many_cases_by_halves() {
+        let code = method_code(
+            "switch (total) { case 1: { } case 2: { } case 3: { } case 4: { } case 5: { } case -1: { } }",
+        );
+        let lines: Vec<&str> = code.lines().collect();
+        let expected = [
+            "push static 0",
+            "pop temp 1",
+            "push temp 1",
+            "push constant 3",
+            "lt",
+            "if-goto CounterL7",
+            "push temp 1",
+            "push constant 3",
+            "eq",
+            "if-goto CounterL3",
+            "push temp 1",
+            "push constant 4",
+            "eq",
+            "if-goto CounterL4",
+            "push temp 1",
+            "push constant 5",
+            "eq",
+            "if-goto CounterL5",
+            "goto CounterL0",
+            "label CounterL7",
+            "push temp 1",
+            "push constant 0",
+            "not",
+            "eq",
+            "if-goto CounterL6",
+            "push temp 1",
+            "push constant 1",
+            "eq",
+            "if-goto CounterL1",
+            "push temp 1",
+            "push constant 2",
+            "eq",
+            "if-goto CounterL2",
+            "goto CounterL0",
+            "label CounterL1",
+        ];
+        assert_eq!(lines[..expected.len()], expected);
+
+        // each half is searched again once it has more than LINEAR_CASES
+        let cases: String = (0..20).map(|i| format!("case {i}: {{ }} ")).collect();
+        let code = method_code(&format!("switch (total) {{ {cases}}}"));
+        let comparisons = code.lines().filter(|&line| line == "lt").count();
+        assert_eq!(comparisons, 7);
+        assert_eq!(code.lines().filter(|&line| line == "eq").count(), 20);
+    }
+
+    #[test]
+    fn breaks_out_of_switches_inside_loops() {
+        let code = method_code("while (true) { switch (total) { case 1: { break; } } }");
+        let lines: Vec<&str> = code.lines().collect();
+        let case = lines
+            .iter()
+            .position(|&line| line == "label CounterL4")
+            .unwrap();
+        // `break` leaves the `switch`, not the loop
+        assert_eq!(lines[case + 1], "goto CounterL3");
+        assert_eq!(lines[case + 3], "label CounterL3");
+        assert_eq!(lines[case + 4], "goto CounterL0");
+    }
+
+    #[test]
+    fn reports_duplicate_and_non_constant_cases() {
+        let switch = |cases: &str| {
+            let source = counter("method", &format!("switch (total) {{ {cases} }}")).replace(
+                "static int total;",
+                "static int total;\n    const int ONE = 1;",
+            );
+            compile_in(Dialect::Extended, &source).diagnostics
+        };
+
+        let diagnostics = switch("case 1: { }\n        case 1: { }");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, Code::DuplicateCase);
+        assert_eq!(diagnostics[0].message, "`case 1` is already handled");
+        assert!(diagnostics[0].notes[0].contains("first handled on line 6"));
+
+        let diagnostics = switch("case ONE: { } case 0x1: { }");
+        assert_eq!(diagnostics[0].code, Code::DuplicateCase);
+        assert!(diagnostics[0].notes[0].starts_with("`ONE` on line 6 has the same value, 1,"));
+
+        let diagnostics = switch("default: { } default: { }");
+        assert_eq!(diagnostics[0].code, Code::DuplicateCase);
+        assert_eq!(
+            diagnostics[0].message,
+            "`switch` already has a `default` case"
+        );
+
+        let diagnostics = switch("case 1: { } case 'a': { } case true: { } case -2: { }");
+        assert!(diagnostics.is_empty());
+
+        for case in ["count", "1 + 1", "Counter.g()"] {
+            let diagnostics = switch(&format!("case {case}: {{ }}"));
+            let codes: Vec<Code> = diagnostics.iter().map(|d| d.code).collect();
+            assert_eq!(codes, [Code::NonConstantCase], "{case}");
+            assert_eq!(
+                diagnostics[0].message,
+                format!("`case` value `{case}` is not a constant")
+            );
+        }
+    }
+}
@@ -0,0 +1,528 @@
+use std::fmt;
+
+use crate::{json, tokenizer::Span};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Stable identifier of a kind of diagnostic.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Code {
+    InvalidToken,
+    UnterminatedString,
+    UnterminatedComment,
+    UnknownEscape,
+    UnsupportedCharacter,
+    CharacterCount,
+    IntegerOverflow,
+    UnexpectedToken,
+    UnknownClass,
+    UndeclaredVariable,
+    UnknownSubroutine,
+    UnknownConstant,
+    NotAVariable,
+    ArgumentCount,
+    SubroutineKind,
+    NotAnObject,
+    NoThis,
+    MissingReturn,
+    OutsideLoop,
+    DuplicateCase,
+    NonConstantCase,
+    UnusedVariable,
+    EagerOperator,
+    EvaluationOrder,
+}
+
+impl Code {
+    pub const ALL: &'static [Code] = &[
+        Code::InvalidToken,
+        Code::UnterminatedString,
+        Code::UnterminatedComment,
+        Code::UnknownEscape,
+        Code::UnsupportedCharacter,
+        Code::CharacterCount,
+        Code::IntegerOverflow,
+        Code::UnexpectedToken,
+        Code::UnknownClass,
+        Code::UndeclaredVariable,
+        Code::UnknownSubroutine,
+        Code::UnknownConstant,
+        Code::NotAVariable,
+        Code::ArgumentCount,
+        Code::SubroutineKind,
+        Code::NotAnObject,
+        Code::NoThis,
+        Code::MissingReturn,
+        Code::OutsideLoop,
+        Code::DuplicateCase,
+        Code::NonConstantCase,
+        Code::UnusedVariable,
+        Code::EagerOperator,
+        Code::EvaluationOrder,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::InvalidToken => "J0001",
+            Code::UnterminatedString => "J0002",
+            Code::UnterminatedComment => "J0003",
+            Code::UnknownEscape => "J0004",
+            Code::UnsupportedCharacter => "J0005",
+            Code::CharacterCount => "J0006",
+            Code::IntegerOverflow => "J0007",
+            Code::UnexpectedToken => "J0101",
+            Code::UnknownClass => "J0202",
+            Code::UndeclaredVariable => "J0203",
+            Code::UnknownSubroutine => "J0204",
+            Code::UnknownConstant => "J0205",
+            Code::NotAVariable => "J0206",
+            Code::ArgumentCount => "J0310",
+            Code::SubroutineKind => "J0311",
+            Code::NotAnObject => "J0312",
+            Code::NoThis => "J0313",
+            Code::MissingReturn => "J0401",
+            Code::OutsideLoop => "J0402",
+            Code::DuplicateCase => "J0403",
+            Code::NonConstantCase => "J0404",
+            Code::UnusedVariable => "J0501",
+            Code::EagerOperator => "J0502",
+            Code::EvaluationOrder => "J0503",
+        }
+    }
+
+    /// Looks up a code by its identifier, e.g. `J0203`.
+    pub fn parse(code: &str) -> Option<Code> {
+        Code::ALL
+            .iter()
+            .copied()
+            .find(|known| known.as_str().eq_ignore_ascii_case(code))
+    }
+
+    /// One line description of the code.
+    pub fn summary(self) -> &'static str {
+        match self {
+            Code::InvalidToken => "invalid token",
+            Code::UnterminatedString => "unterminated string or character constant",
+            Code::UnterminatedComment => "unterminated block comment",
+            Code::UnknownEscape => "unknown escape sequence",
+            Code::UnsupportedCharacter => "character outside of the Hack character set",
+            Code::CharacterCount => "character constant not made of exactly one character",
+            Code::IntegerOverflow => "integer constant out of range",
+            Code::UnexpectedToken => "unexpected token",
+            Code::UnknownClass => "unknown class",
+            Code::UndeclaredVariable => "undeclared variable",
+            Code::UnknownSubroutine => "unknown subroutine",
+            Code::UnknownConstant => "unknown constant",
+            Code::NotAVariable => "constant assigned or indexed like a variable",
+            Code::ArgumentCount => "wrong number of arguments",
+            Code::SubroutineKind => "method called as a function or function called as a method",
+            Code::NotAnObject => "subroutine called on a value that is not an object",
+            Code::NoThis => "`this` used in a function",
+            Code::MissingReturn => "missing return statement",
+            Code::OutsideLoop => "`break` or `continue` outside of a loop",
+            Code::DuplicateCase => "`case` value already handled by the same `switch`",
+            Code::NonConstantCase => "`case` value that is not a constant",
+            Code::UnusedVariable => "unused variable",
+            Code::EagerOperator => "`&` or `|` evaluating a condition with side effects",
+            Code::EvaluationOrder => {
+                "expression evaluated differently than under conventional precedence"
+            }
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Whether a fix can be applied without review.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Applicability {
+    /// The fix is what the code meant, e.g. a missing `;` or a misspelling
+    /// with a single close match: `fix` applies it.
+    MachineApplicable,
+    /// The fix changes what the code does and may not be what was meant:
+    /// `fix` only applies it with `--unsafe-fixes`.
+    MaybeIncorrect,
+}
+
+impl Applicability {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Applicability::MachineApplicable => "machine-applicable",
+            Applicability::MaybeIncorrect => "maybe-incorrect",
+        }
+    }
+
+    pub fn parse(applicability: &str) -> Option<Applicability> {
+        [
+            Applicability::MachineApplicable,
+            Applicability::MaybeIncorrect,
+        ]
+        .into_iter()
+        .find(|known| known.as_str() == applicability)
+    }
+}
+
+/// An edit replacing `span` with `replacement`.
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+    pub applicability: Applicability,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+    pub notes: Vec<String>,
+    pub fix: Option<Fix>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            code,
+            severity: Severity::Error,
+            span,
+            message: message.into(),
+            notes: Vec::new(),
+            fix: None,
+        }
+    }
+
+    pub fn warning(code: Code, span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(code, span, message)
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Adds a machine-applicable fix.
+    pub fn with_fix(
+        mut self,
+        message: impl Into<String>,
+        span: Span,
+        replacement: &str,
+    ) -> Diagnostic {
+        self.fix = Some(Fix {
+            message: message.into(),
+            span,
+            replacement: String::from(replacement),
+            applicability: Applicability::MachineApplicable,
+        });
+        self
+    }
+
+    /// Marks the fix as one that may change what the code does.
+    pub fn maybe_incorrect(mut self) -> Diagnostic {
+        if let Some(fix) = &mut self.fix {
+            fix.applicability = Applicability::MaybeIncorrect;
+        }
+        self
+    }
+}
+
+/// 1-based line and column, counted in characters.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A source file along with the offsets of its lines, used to turn spans
+/// into positions.
+pub struct SourceFile<'a> {
+    pub path: &'a str,
+    pub text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceFile<'a> {
+    pub fn new(path: &'a str, text: &'a str) -> SourceFile<'a> {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceFile {
+            path,
+            text,
+            line_starts,
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        Position {
+            line: line + 1,
+            column: self.text[line_start..offset].chars().count() + 1,
+        }
+    }
+
+    fn line(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.text.len(), |&end| end);
+        self.text[start..end].trim_end_matches(['\r', '\n'])
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MessageFormat {
+    Human,
+    Json,
+    Sarif,
+}
+
+/// Renders `diagnostic` for a terminal, quoting the offending line.
+pub fn render_human(diagnostic: &Diagnostic, file: &SourceFile) -> String {
+    let start = file.position(diagnostic.span.start);
+    let end = file.position(diagnostic.span.end);
+    let mut rendered = format!(
+        "{}:{}:{}: {}[{}]: {}\n",
+        file.path,
+        start.line,
+        start.column,
+        diagnostic.severity,
+        diagnostic.code,
+        diagnostic.message
+    );
+
+    let line = file.line(start.line);
+    let width = if end.line == start.line {
+        end.column.saturating_sub(start.column).max(1)
+    } else {
+        1
+    };
+    let gutter = " ".repeat(start.line.to_string().len());
+    rendered.push_str(&format!("{gutter} |\n{} | {line}\n", start.line));
+    rendered.push_str(&format!(
+        "{gutter} | {}{}\n",
+        " ".repeat(start.column - 1),
+        "^".repeat(width)
+    ));
+
+    for note in &diagnostic.notes {
+        rendered.push_str(&format!("{gutter} = note: {note}\n"));
+    }
+    if let Some(fix) = &diagnostic.fix {
+        rendered.push_str(&format!("{gutter} = help: {}\n", fix.message));
+    }
+    rendered
+}
+
+/// Renders `diagnostic` as a single line JSON object.
+pub fn render_json(diagnostic: &Diagnostic, file: &SourceFile) -> String {
+    let fix = match &diagnostic.fix {
+        Some(fix) => format!(
+            "{{\"message\":{},\"range\":{},\"replacement\":{},\"applicability\":{}}}",
+            json::string(&fix.message),
+            json_range(fix.span, file),
+            json::string(&fix.replacement),
+            json::string(fix.applicability.as_str())
+        ),
+        None => String::from("null"),
+    };
+    format!(
+        "{{\"code\":{},\"severity\":{},\"file\":{},\"range\":{},\"message\":{},\"notes\":{},\"fix\":{}}}",
+        json::string(diagnostic.code.as_str()),
+        json::string(&diagnostic.severity.to_string()),
+        json::string(file.path),
+        json_range(diagnostic.span, file),
+        json::string(&diagnostic.message),
+        json::array(diagnostic.notes.iter().map(|note| json::string(note))),
+        fix
+    )
+}
+
+fn json_range(span: Span, file: &SourceFile) -> String {
+    let start = file.position(span.start);
+    let end = file.position(span.end);
+    format!(
+        "{{\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}}}}",
+        start.line, start.column, end.line, end.column
+    )
+}
+
+/// Renders the diagnostics of a whole compilation as a SARIF 2.1.0 log.
+pub fn render_sarif(files: &[(SourceFile, &[Diagnostic])]) -> String {
+    let rules = json::array(Code::ALL.iter().map(|code| {
+        format!(
+            "{{\"id\":{},\"shortDescription\":{{\"text\":{}}}}}",
+            json::string(code.as_str()),
+            json::string(code.summary())
+        )
+    }));
+
+    let results = json::array(files.iter().flat_map(|(file, diagnostics)| {
+        diagnostics.iter().map(move |diagnostic| {
+            let fixes = match &diagnostic.fix {
+                Some(fix) => format!(
+                    ",\"fixes\":[{{\"description\":{{\"text\":{}}},\"artifactChanges\":[{{\"artifactLocation\":{{\"uri\":{}}},\"replacements\":[{{\"deletedRegion\":{},\"insertedContent\":{{\"text\":{}}}}}]}}]}}]",
+                    json::string(&fix.message),
+                    json::string(file.path),
+                    sarif_region(fix.span, file),
+                    json::string(&fix.replacement)
+                ),
+                None => String::new(),
+            };
+            let level = match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            format!(
+                "{{\"ruleId\":{},\"ruleIndex\":{},\"level\":{},\"message\":{{\"text\":{}}},\"locations\":[{{\"physicalLocation\":{{\"artifactLocation\":{{\"uri\":{}}},\"region\":{}}}}}],\"properties\":{{\"notes\":{}}}{}}}",
+                json::string(diagnostic.code.as_str()),
+                Code::ALL
+                    .iter()
+                    .position(|&code| code == diagnostic.code)
+                    .expect("Expected every code to be listed in Code::ALL"),
+                json::string(level),
+                json::string(&diagnostic.message),
+                json::string(file.path),
+                sarif_region(diagnostic.span, file),
+                json::array(diagnostic.notes.iter().map(|note| json::string(note))),
+                fixes
+            )
+        })
+    }));
+
+    format!(
+        "{{\"$schema\":\"https://json.schemastore.org/sarif-2.1.0.json\",\"version\":\"2.1.0\",\"runs\":[{{\"tool\":{{\"driver\":{{\"name\":\"jack-compiler\",\"version\":{},\"rules\":{}}}}},\"columnKind\":\"unicodeCodePoints\",\"results\":{}}}]}}",
+        json::string(env!("CARGO_PKG_VERSION")),
+        rules,
+        results
+    )
+}
+
+fn sarif_region(span: Span, file: &SourceFile) -> String {
+    let start = file.position(span.start);
+    let end = file.position(span.end);
+    format!(
+        "{{\"startLine\":{},\"startColumn\":{},\"endLine\":{},\"endColumn\":{}}}",
+        start.line, start.column, end.line, end.column
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "class Main {\n    function void main() {\n        let é = x;\n    }\n}\n";
+
+    fn undeclared() -> Diagnostic {
+        let start = SOURCE.find("x;").unwrap();
+        Diagnostic::error(
+            Code::UndeclaredVariable,
+            Span::new(start, start + 1),
+            "cannot find variable `x` in this scope",
+        )
+        .with_note("a note")
+        .with_fix("did you mean `y`?", Span::new(start, start + 1), "y")
+    }
+
+    #[test]
+    fn positions_count_characters() {
+        let file = SourceFile::new("Main.jack", SOURCE);
+        assert_eq!(file.position(0), Position { line: 1, column: 1 });
+        assert_eq!(
+            file.position(12),
+            Position {
+                line: 1,
+                column: 13
+            }
+        );
+        assert_eq!(file.position(13), Position { line: 2, column: 1 });
+        // `é` takes two bytes but a single column
+        let x = SOURCE.find("x;").unwrap();
+        assert_eq!(
+            file.position(x),
+            Position {
+                line: 3,
+                column: 17
+            }
+        );
+        // past the end
+        assert_eq!(file.position(1000), Position { line: 6, column: 1 });
+    }
+
+    #[test]
+    fn renders_human() {
+        let file = SourceFile::new("Main.jack", SOURCE);
+        assert_eq!(
+            render_human(&undeclared(), &file),
+            "Main.jack:3:17: error[J0203]: cannot find variable `x` in this scope\n  |\n3 |         let é = x;\n  |                 ^\n  = note: a note\n  = help: did you mean `y`?\n"
+        );
+        let span = Span::new(SOURCE.find("let").unwrap(), SOURCE.find("é").unwrap());
+        let warning = Diagnostic::warning(Code::UnusedVariable, span, "unused");
+        assert_eq!(
+            render_human(&warning, &file),
+            "Main.jack:3:9: warning[J0501]: unused\n  |\n3 |         let é = x;\n  |         ^^^^\n"
+        );
+    }
+
+    #[test]
+    fn renders_json() {
+        let file = SourceFile::new("Main.jack", SOURCE);
+        assert_eq!(
+            render_json(&undeclared(), &file),
+            "{\"code\":\"J0203\",\"severity\":\"error\",\"file\":\"Main.jack\",\"range\":{\"start\":{\"line\":3,\"column\":17},\"end\":{\"line\":3,\"column\":18}},\"message\":\"cannot find variable `x` in this scope\",\"notes\":[\"a note\"],\"fix\":{\"message\":\"did you mean `y`?\",\"range\":{\"start\":{\"line\":3,\"column\":17},\"end\":{\"line\":3,\"column\":18}},\"replacement\":\"y\",\"applicability\":\"machine-applicable\"}}"
+        );
+        let warning = Diagnostic::warning(Code::UnusedVariable, Span::new(0, 5), "unused");
+        assert!(render_json(&warning, &file).ends_with("\"notes\":[],\"fix\":null}"));
+    }
+
+    #[test]
+    fn renders_sarif() {
+        let file = SourceFile::new("Main.jack", SOURCE);
+        let diagnostics = [undeclared()];
+        let sarif = render_sarif(&[(file, &diagnostics)]);
+        assert!(sarif.starts_with(
+            "{\"$schema\":\"https://json.schemastore.org/sarif-2.1.0.json\",\"version\":\"2.1.0\""
+        ));
+        let index = Code::ALL
+            .iter()
+            .position(|&code| code == Code::UndeclaredVariable)
+            .unwrap();
+        assert!(sarif.contains(&format!(
+            "{{\"ruleId\":\"J0203\",\"ruleIndex\":{index},\"level\":\"error\""
+        )));
+        assert!(sarif.contains(
+            "\"region\":{\"startLine\":3,\"startColumn\":17,\"endLine\":3,\"endColumn\":18}"
+        ));
+        assert!(sarif.contains("\"insertedContent\":{\"text\":\"y\"}"));
+        assert_eq!(
+            sarif.matches("\"shortDescription\"").count(),
+            Code::ALL.len()
+        );
+        let empty = render_sarif(&[]);
+        assert!(empty.ends_with("\"results\":[]}]}"));
+    }
+}
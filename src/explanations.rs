@@ -0,0 +1,193 @@
+use crate::diagnostic::Code;
+
+impl Code {
+    /// Long-form explanation of the code, with an example of erroneous code
+    /// and how to fix it, as shown by `--explain`.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            Code::InvalidToken => include_str!("explanations/J0001.md"),
+            Code::UnterminatedString => include_str!("explanations/J0002.md"),
+            Code::UnterminatedComment => include_str!("explanations/J0003.md"),
+            Code::UnknownEscape => include_str!("explanations/J0004.md"),
+            Code::UnsupportedCharacter => include_str!("explanations/J0005.md"),
+            Code::CharacterCount => include_str!("explanations/J0006.md"),
+            Code::IntegerOverflow => include_str!("explanations/J0007.md"),
+            Code::UnexpectedToken => include_str!("explanations/J0101.md"),
+            Code::UnknownClass => include_str!("explanations/J0202.md"),
+            Code::UndeclaredVariable => include_str!("explanations/J0203.md"),
+            Code::UnknownSubroutine => include_str!("explanations/J0204.md"),
+            Code::UnknownConstant => include_str!("explanations/J0205.md"),
+            Code::NotAVariable => include_str!("explanations/J0206.md"),
+            Code::ArgumentCount => include_str!("explanations/J0310.md"),
+            Code::SubroutineKind => include_str!("explanations/J0311.md"),
+            Code::NotAnObject => include_str!("explanations/J0312.md"),
+            Code::NoThis => include_str!("explanations/J0313.md"),
+            Code::MissingReturn => include_str!("explanations/J0401.md"),
+            Code::OutsideLoop => include_str!("explanations/J0402.md"),
+            Code::DuplicateCase => include_str!("explanations/J0403.md"),
+            Code::NonConstantCase => include_str!("explanations/J0404.md"),
+            Code::UnusedVariable => include_str!("explanations/J0501.md"),
+            Code::EagerOperator => include_str!("explanations/J0502.md"),
+            Code::EvaluationOrder => include_str!("explanations/J0503.md"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compilation_engine::CompilationEngine,
+        diagnostic::Code,
+        os_api,
+        tokenizer::{Dialect, Token, Tokenizer},
+    };
+
+    /// Index of `code` in `Code::ALL`. The match is exhaustive, so a variant
+    /// added to `Code` has to be placed here, and then in `Code::ALL` for
+    /// `all_lists_every_code` to pass.
+    fn index(code: Code) -> usize {
+        match code {
+            Code::InvalidToken => 0,
+            Code::UnterminatedString => 1,
+            Code::UnterminatedComment => 2,
+            Code::UnknownEscape => 3,
+            Code::UnsupportedCharacter => 4,
+            Code::CharacterCount => 5,
+            Code::IntegerOverflow => 6,
+            Code::UnexpectedToken => 7,
+            Code::UnknownClass => 8,
+            Code::UndeclaredVariable => 9,
+            Code::UnknownSubroutine => 10,
+            Code::UnknownConstant => 11,
+            Code::NotAVariable => 12,
+            Code::ArgumentCount => 13,
+            Code::SubroutineKind => 14,
+            Code::NotAnObject => 15,
+            Code::NoThis => 16,
+            Code::MissingReturn => 17,
+            Code::OutsideLoop => 18,
+            Code::DuplicateCase => 19,
+            Code::NonConstantCase => 20,
+            Code::UnusedVariable => 21,
+            Code::EagerOperator => 22,
+            Code::EvaluationOrder => 23,
+        }
+    }
+
+    #[test]
+    fn all_lists_every_code() {
+        for (i, &code) in Code::ALL.iter().enumerate() {
+            assert_eq!(index(code), i, "{code} is out of place in Code::ALL");
+        }
+        assert_eq!(Code::ALL.len(), index(Code::EvaluationOrder) + 1);
+    }
+
+    #[test]
+    fn every_code_parses_and_is_explained() {
+        for &code in Code::ALL {
+            assert_eq!(Code::parse(code.as_str()), Some(code));
+            assert_eq!(Code::parse(&code.as_str().to_lowercase()), Some(code));
+            assert!(!code.summary().is_empty(), "{code} has no summary");
+            let explanation = code.explanation();
+            assert!(!explanation.trim().is_empty(), "{code} has no explanation");
+            assert!(explanation.contains("    "), "{code} has no example");
+        }
+        assert_eq!(Code::parse("J9999"), None);
+    }
+
+    /// Wraps `body` in a function of a class `Main`, along with a variable `x`.
+    fn in_function(body: &str) -> String {
+        format!(
+            "class Main {{\n    function int f(int a) {{\n        var int x;\n        let x = a;\n        {body}\n        return x;\n    }}\n}}\n"
+        )
+    }
+
+    fn codes(source: &str, dialect: Dialect) -> Vec<Code> {
+        let declarations = os_api::declarations();
+        let tokenizer = Tokenizer::build(source)
+            .unwrap()
+            .with_dialect(dialect)
+            .spanned()
+            .filter(|(_, x)| {
+                !matches!(
+                    x,
+                    Token::Whitespace(_) | Token::SingleLineComment(_) | Token::BlockComment(_)
+                )
+            });
+        let mut compilation_engine =
+            CompilationEngine::new(source, tokenizer, &declarations).with_dialect(dialect);
+        compilation_engine.compile_class();
+        compilation_engine
+            .finish()
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect()
+    }
+
+    #[test]
+    fn emitted_codes_are_listed() {
+        use Dialect::{Extended, Standard};
+        let cases = [
+            (Code::InvalidToken, Standard, in_function("let x = 2x;")),
+            (Code::UnterminatedString, Standard, in_function("do Output.printString(\"abc);")),
+            (Code::UnterminatedComment, Standard, in_function("/* never closed")),
+            (Code::UnknownEscape, Extended, in_function("do Output.printString(\"\\q\");")),
+            (Code::UnsupportedCharacter, Extended, in_function("do Output.printString(\"é\");")),
+            (Code::CharacterCount, Extended, in_function("let x = 'ab';")),
+            (Code::IntegerOverflow, Standard, in_function("let x = 40000;")),
+            (Code::UnexpectedToken, Standard, in_function("let x = ;")),
+            (Code::UnknownClass, Standard, in_function("do Foo.bar();")),
+            (Code::UndeclaredVariable, Standard, in_function("let y = 1;")),
+            (Code::UnknownSubroutine, Standard, in_function("do Output.prnt(1);")),
+            (Code::UnknownConstant, Extended, in_function("let x = Main.NOPE;")),
+            (
+                Code::NotAVariable,
+                Extended,
+                String::from("class Main {\n    const int K = 1;\n    function void f() {\n        let K = 2;\n        return;\n    }\n}\n"),
+            ),
+            (Code::ArgumentCount, Standard, in_function("do Output.printInt();")),
+            (Code::SubroutineKind, Standard, in_function("let x = String.length();")),
+            (Code::NotAnObject, Standard, in_function("do x.foo();")),
+            (Code::NoThis, Standard, in_function("let x = this;")),
+            (
+                Code::MissingReturn,
+                Standard,
+                String::from("class Main {\n    function void f() {\n        do Output.println();\n    }\n}\n"),
+            ),
+            (Code::OutsideLoop, Extended, in_function("break;")),
+            (
+                Code::DuplicateCase,
+                Extended,
+                in_function("switch (x) { case 1: {} case 1: {} }"),
+            ),
+            (Code::NonConstantCase, Extended, in_function("switch (x) { case a: {} }")),
+            (
+                Code::UnusedVariable,
+                Standard,
+                String::from("class Main {\n    function void f() {\n        var int unused;\n        return;\n    }\n}\n"),
+            ),
+            (
+                Code::EagerOperator,
+                Extended,
+                in_function("if ((x = 0) & (Main.f(x) = 0)) { let x = 1; }"),
+            ),
+            (Code::EvaluationOrder, Standard, in_function("let x = 1 + 2 * 3;")),
+        ];
+        let mut triggered = Vec::new();
+        for (expected, dialect, source) in &cases {
+            let codes = codes(source, *dialect);
+            assert!(
+                codes.contains(expected),
+                "{expected} not reported for:\n{source}\ngot {codes:?}"
+            );
+            for code in codes {
+                assert!(Code::ALL.contains(&code), "{code} is not in Code::ALL");
+            }
+            triggered.push(*expected);
+        }
+        for code in Code::ALL {
+            assert!(triggered.contains(code), "no source triggers {code}");
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt::Write;
+
+use crate::diagnostic::Fix;
+
+/// Applies `fixes` to `source`, returning the fixed text and the number of
+/// fixes applied.
+///
+/// Fixes are applied from the start of the file; a fix overlapping one
+/// already applied, or inserting at the same position, is skipped so that it
+/// can be reconsidered once the file is compiled again. A removal leaving its
+/// line blank removes the whole line.
+pub fn apply(source: &str, fixes: &[Fix]) -> (String, usize) {
+    let mut fixes: Vec<&Fix> = fixes.iter().collect();
+    fixes.sort_by_key(|fix| (fix.span.start, fix.span.end));
+
+    let mut fixed = String::with_capacity(source.len());
+    let mut cursor = 0;
+    let mut applied = 0;
+    let mut last_insertion = None;
+    for fix in fixes {
+        let (start, end) = (fix.span.start, fix.span.end);
+        if start < cursor || last_insertion == Some(start) || end > source.len() {
+            continue;
+        }
+
+        fixed.push_str(&source[cursor..start]);
+        fixed.push_str(&fix.replacement);
+        cursor = end;
+        applied += 1;
+        last_insertion = (start == end).then_some(start);
+
+        if fix.replacement.is_empty() {
+            let line_start = fixed.rfind('\n').map_or(0, |i| i + 1);
+            let rest = &source[cursor..];
+            let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+            if fixed[line_start..].trim().is_empty() && rest[..line_end].trim().is_empty() {
+                fixed.truncate(line_start);
+                cursor += line_end;
+            }
+        }
+    }
+    fixed.push_str(&source[cursor..]);
+    (fixed, applied)
+}
+
+/// Returns a unified diff turning `old` into `new`, with three lines of
+/// context around each change.
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    const CONTEXT: usize = 3;
+
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // longest common subsequence of lines, from the end
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    enum Line<'a> {
+        Same(&'a str),
+        Removed(&'a str),
+        Added(&'a str),
+    }
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+
+    let mut diff = format!("--- {path}\n+++ {path}\n");
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+    let mut k = 0;
+    while k < changed.len() {
+        // extend the hunk while the next change is close enough
+        let start = changed[k].saturating_sub(CONTEXT);
+        let mut end = changed[k] + 1;
+        while k + 1 < changed.len() && changed[k + 1] <= end + 2 * CONTEXT {
+            k += 1;
+            end = changed[k] + 1;
+        }
+        end = (end + CONTEXT).min(lines.len());
+        k += 1;
+
+        let old_start = lines[..start]
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let new_start = lines[..start]
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+        let old_len = lines[start..end]
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let new_len = lines[start..end]
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+        let _ = writeln!(
+            diff,
+            "@@ -{},{} +{},{} @@",
+            old_start + 1,
+            old_len,
+            new_start + 1,
+            new_len
+        );
+        for line in &lines[start..end] {
+            let _ = match line {
+                Line::Same(line) => writeln!(diff, " {line}"),
+                Line::Removed(line) => writeln!(diff, "-{line}"),
+                Line::Added(line) => writeln!(diff, "+{line}"),
+            };
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{diagnostic::Applicability, tokenizer::Span};
+
+    fn fix(start: usize, end: usize, replacement: &str) -> Fix {
+        Fix {
+            message: String::from("fix"),
+            span: Span::new(start, end),
+            replacement: String::from(replacement),
+            applicability: Applicability::MachineApplicable,
+        }
+    }
+
+    #[test]
+    fn applies_fixes_in_order() {
+        let source = "let x = 1\nlet y = 2;";
+        let fixes = [fix(14, 15, "z"), fix(9, 9, ";")];
+        assert_eq!(
+            apply(source, &fixes),
+            (String::from("let x = 1;\nlet z = 2;"), 2)
+        );
+        assert_eq!(apply(source, &[]), (String::from(source), 0));
+    }
+
+    #[test]
+    fn skips_overlapping_and_conflicting_fixes() {
+        let source = "abcdef";
+        // the second fix overlaps the first one
+        assert_eq!(
+            apply(source, &[fix(1, 4, "X"), fix(3, 5, "Y")]),
+            (String::from("aXef"), 1)
+        );
+        // two insertions at the same position
+        assert_eq!(
+            apply(source, &[fix(2, 2, "X"), fix(2, 2, "Y")]),
+            (String::from("abXcdef"), 1)
+        );
+        // an insertion right after a replacement does not overlap it
+        assert_eq!(
+            apply(source, &[fix(0, 2, "X"), fix(2, 2, "Y")]),
+            (String::from("XYcdef"), 2)
+        );
+        // out of range
+        assert_eq!(apply(source, &[fix(4, 10, "")]), (String::from(source), 0));
+    }
+
+    #[test]
+    fn removes_lines_left_blank() {
+        let source = "var int x;\n    var int y;\nlet x = 1;\n";
+        assert_eq!(
+            apply(source, &[fix(15, 25, "")]),
+            (String::from("var int x;\nlet x = 1;\n"), 1)
+        );
+        // a line with something left on it stays
+        assert_eq!(
+            apply(source, &[fix(0, 4, "")]),
+            (String::from("int x;\n    var int y;\nlet x = 1;\n"), 1)
+        );
+    }
+
+    #[test]
+    fn diffs_lines() {
+        assert_eq!(
+            unified_diff("A.jack", "a\nb\n", "a\nb\n"),
+            "--- A.jack\n+++ A.jack\n"
+        );
+        assert_eq!(
+            unified_diff("A.jack", "a\nb\nc\n", "a\nB\nc\nd\n"),
+            "--- A.jack\n+++ A.jack\n@@ -1,3 +1,4 @@\n a\n-b\n+B\n c\n+d\n"
+        );
+    }
+
+    #[test]
+    fn diffs_distant_changes_in_separate_hunks() {
+        let old: String = (1..=20).map(|i| format!("{i}\n")).collect();
+        let new = old
+            .replacen("2\n", "two\n", 1)
+            .replace("\n19\n", "\nnineteen\n");
+        assert_eq!(
+            unified_diff("A.jack", &old, &new),
+            "--- A.jack\n+++ A.jack\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -16,5 +16,5 @@\n 16\n 17\n 18\n-19\n+nineteen\n 20\n"
+        );
+        // changes up to twice the context apart share a hunk
+        let new = old.replacen("2\n", "two\n", 1).replace("\n9\n", "\nnine\n");
+        assert!(unified_diff("A.jack", &old, &new).contains("@@ -1,12 +1,12 @@\n"));
+    }
+}
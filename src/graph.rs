@@ -0,0 +1,333 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::{
+    compilation_engine::Compilation,
+    interface::{ClassInterface, SubroutineKind},
+    json, os_api,
+};
+
+/// The calls between the subroutines of a program and the dependencies
+/// between its classes, gathered from the compilation of each class.
+pub struct ProgramGraph {
+    /// Every subroutine declared by the program or called, by
+    /// `Class.subroutine`.
+    subroutines: BTreeMap<String, SubroutineKind>,
+    /// Number of calls from a subroutine to another, with the kind of the
+    /// subroutine called.
+    calls: BTreeMap<(String, String), (SubroutineKind, usize)>,
+    /// Classes each class of the program refers to.
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+    os: BTreeSet<String>,
+}
+
+impl Default for ProgramGraph {
+    fn default() -> Self {
+        ProgramGraph::new()
+    }
+}
+
+impl ProgramGraph {
+    pub fn new() -> ProgramGraph {
+        ProgramGraph {
+            subroutines: BTreeMap::new(),
+            calls: BTreeMap::new(),
+            dependencies: BTreeMap::new(),
+            os: os_api::declarations()
+                .class_names()
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    /// Adds the class `interface`, compiled to `compilation`.
+    pub fn add(&mut self, interface: &ClassInterface, compilation: &Compilation) {
+        for subroutine in &interface.subroutines {
+            self.subroutines.insert(
+                format!("{}.{}", interface.name, subroutine.name),
+                subroutine.kind,
+            );
+        }
+        for call in &compilation.calls {
+            self.subroutines
+                .entry(call.callee.clone())
+                .or_insert(call.kind);
+            let key = (call.caller.clone(), call.callee.clone());
+            self.calls.entry(key).or_insert((call.kind, 0)).1 += 1;
+        }
+        self.dependencies.insert(
+            interface.name.clone(),
+            compilation.references.iter().cloned().collect(),
+        );
+    }
+
+    fn is_os(&self, class: &str) -> bool {
+        self.os.contains(class) && !self.dependencies.contains_key(class)
+    }
+
+    /// Every class of the program along with the classes it depends on.
+    fn classes(&self) -> BTreeSet<&str> {
+        self.dependencies
+            .iter()
+            .flat_map(|(class, dependencies)| {
+                std::iter::once(class)
+                    .chain(dependencies)
+                    .map(String::as_str)
+            })
+            .collect()
+    }
+
+    /// The call graph in Graphviz DOT, one cluster per class. Calls to
+    /// methods are solid, to functions dashed and to constructors bold.
+    pub fn callgraph_dot(&self) -> String {
+        let mut dot = String::from("digraph callgraph {\n    rankdir=LR;\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        let mut classes: BTreeMap<&str, Vec<(&str, SubroutineKind)>> = BTreeMap::new();
+        for (name, kind) in &self.subroutines {
+            let (class, subroutine) = name.split_once('.').unwrap_or(("", name));
+            classes.entry(class).or_default().push((subroutine, *kind));
+        }
+        for (class, subroutines) in classes {
+            let _ = writeln!(
+                dot,
+                "    subgraph {} {{",
+                dot_id(&format!("cluster_{class}"))
+            );
+            let _ = writeln!(dot, "        label={};", dot_id(class));
+            if self.is_os(class) {
+                dot.push_str("        style=filled;\n        color=lightgrey;\n");
+            }
+            for (subroutine, kind) in subroutines {
+                let _ = writeln!(
+                    dot,
+                    "        {} [label={}];",
+                    dot_id(&format!("{class}.{subroutine}")),
+                    dot_id(&format!("{kind} {subroutine}"))
+                );
+            }
+            dot.push_str("    }\n");
+        }
+        for ((caller, callee), (kind, count)) in &self.calls {
+            let style = match kind {
+                SubroutineKind::Method => "solid",
+                SubroutineKind::Function => "dashed",
+                SubroutineKind::Constructor => "bold",
+            };
+            let label = if *count > 1 {
+                format!(", label=\"{count}\"")
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                dot,
+                "    {} -> {} [style={style}{label}];",
+                dot_id(caller),
+                dot_id(callee)
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The call graph in JSON: the subroutines with their class and kind,
+    /// and the calls between them with their number.
+    pub fn callgraph_json(&self) -> String {
+        let subroutines = json::array(self.subroutines.iter().map(|(name, kind)| {
+            let class = name.split_once('.').map_or("", |(class, _)| class);
+            format!(
+                "{{\"name\":{},\"class\":{},\"kind\":{},\"os\":{}}}",
+                json::string(name),
+                json::string(class),
+                json::string(&kind.to_string()),
+                self.is_os(class)
+            )
+        }));
+        let calls = json::array(self.calls.iter().map(|((caller, callee), (kind, count))| {
+            format!(
+                "{{\"caller\":{},\"callee\":{},\"kind\":{},\"count\":{}}}",
+                json::string(caller),
+                json::string(callee),
+                json::string(&kind.to_string()),
+                count
+            )
+        }));
+        format!("{{\"subroutines\":{subroutines},\"calls\":{calls}}}\n")
+    }
+
+    /// The dependencies between classes in Graphviz DOT, OS classes in grey.
+    pub fn deps_dot(&self) -> String {
+        let mut dot = String::from("digraph deps {\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for class in self.classes() {
+            let style = if self.is_os(class) {
+                " [style=filled, color=lightgrey]"
+            } else {
+                ""
+            };
+            let _ = writeln!(dot, "    {}{style};", dot_id(class));
+        }
+        for (class, dependencies) in &self.dependencies {
+            for dependency in dependencies {
+                let _ = writeln!(dot, "    {} -> {};", dot_id(class), dot_id(dependency));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// The dependencies between classes in JSON.
+    pub fn deps_json(&self) -> String {
+        let classes = json::array(self.classes().into_iter().map(|class| {
+            format!(
+                "{{\"name\":{},\"os\":{}}}",
+                json::string(class),
+                self.is_os(class)
+            )
+        }));
+        let dependencies =
+            json::array(self.dependencies.iter().flat_map(|(class, dependencies)| {
+                dependencies.iter().map(move |dependency| {
+                    format!(
+                        "{{\"from\":{},\"to\":{}}}",
+                        json::string(class),
+                        json::string(dependency)
+                    )
+                })
+            }));
+        format!("{{\"classes\":{classes},\"dependencies\":{dependencies}}}\n")
+    }
+}
+
+/// Returns `id` quoted as a DOT identifier.
+fn dot_id(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compilation_engine::CompilationEngine,
+        interface::Declarations,
+        tokenizer::{Dialect, Token, Tokenizer},
+    };
+
+    const MAIN: &str = "class Main {
+    function void main() {
+        var Point p;
+        let p = Point.new();
+        do Output.printInt(p.getX());
+        do Output.printInt(p.getX());
+        return;
+    }
+}
+";
+
+    const POINT: &str = "class Point {
+    field int x;
+    constructor Point new() {
+        let x = 0;
+        return this;
+    }
+    method int getX() {
+        return x;
+    }
+}
+";
+
+    fn graph() -> ProgramGraph {
+        let sources = [MAIN, POINT];
+        let interfaces: Vec<ClassInterface> = sources
+            .iter()
+            .map(|source| ClassInterface::scan(source, Dialect::Standard).unwrap())
+            .collect();
+        let mut declarations: Declarations = os_api::declarations();
+        for interface in &interfaces {
+            declarations.add(interface.clone()).unwrap();
+        }
+        let mut graph = ProgramGraph::new();
+        for (source, interface) in sources.iter().zip(&interfaces) {
+            let tokenizer = Tokenizer::build(source)
+                .unwrap()
+                .spanned()
+                .filter(|(_, x)| {
+                    !matches!(
+                        x,
+                        Token::Whitespace(_) | Token::SingleLineComment(_) | Token::BlockComment(_)
+                    )
+                });
+            let mut compilation_engine = CompilationEngine::new(source, tokenizer, &declarations);
+            compilation_engine.compile_class();
+            graph.add(interface, &compilation_engine.finish());
+        }
+        graph
+    }
+
+    #[test]
+    fn draws_the_call_graph() {
+        let dot = graph().callgraph_dot();
+        assert!(dot.starts_with("digraph callgraph {\n"));
+        assert!(dot.contains("    subgraph \"cluster_Point\" {\n        label=\"Point\";\n"));
+        assert!(dot.contains("        \"Point.new\" [label=\"constructor new\"];\n"));
+        assert!(dot.contains("    \"Main.main\" -> \"Point.new\" [style=bold];\n"));
+        assert!(dot.contains("    \"Main.main\" -> \"Point.getX\" [style=solid, label=\"2\"];\n"));
+        assert!(
+            dot.contains("    \"Main.main\" -> \"Output.printInt\" [style=dashed, label=\"2\"];\n")
+        );
+        // only the OS classes are grey
+        let output = &dot[dot.find("cluster_Output").unwrap()..];
+        assert!(output[..output.find('}').unwrap()].contains("color=lightgrey"));
+        let point = &dot[dot.find("cluster_Point").unwrap()..];
+        assert!(!point[..point.find('}').unwrap()].contains("color=lightgrey"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn lists_the_call_graph_in_json() {
+        let json = graph().callgraph_json();
+        assert!(json.contains(
+            "{\"name\":\"Output.printInt\",\"class\":\"Output\",\"kind\":\"function\",\"os\":true}"
+        ));
+        assert!(json.contains(
+            "{\"name\":\"Point.getX\",\"class\":\"Point\",\"kind\":\"method\",\"os\":false}"
+        ));
+        assert!(json.contains(
+            "{\"caller\":\"Main.main\",\"callee\":\"Point.getX\",\"kind\":\"method\",\"count\":2}"
+        ));
+    }
+
+    #[test]
+    fn draws_class_dependencies() {
+        let graph = graph();
+        // constructors allocate through Memory
+        assert_eq!(
+            graph.deps_dot(),
+            "digraph deps {
+    node [shape=box, fontname=\"monospace\"];
+    \"Main\";
+    \"Memory\" [style=filled, color=lightgrey];
+    \"Output\" [style=filled, color=lightgrey];
+    \"Point\";
+    \"Main\" -> \"Output\";
+    \"Main\" -> \"Point\";
+    \"Point\" -> \"Memory\";
+}
+"
+        );
+        assert_eq!(
+            graph.deps_json(),
+            "{\"classes\":[{\"name\":\"Main\",\"os\":false},{\"name\":\"Memory\",\"os\":true},\
+             {\"name\":\"Output\",\"os\":true},{\"name\":\"Point\",\"os\":false}],\
+             \"dependencies\":[{\"from\":\"Main\",\"to\":\"Output\"},\
+             {\"from\":\"Main\",\"to\":\"Point\"},{\"from\":\"Point\",\"to\":\"Memory\"}]}\n"
+        );
+    }
+
+    #[test]
+    fn quotes_dot_identifiers() {
+        assert_eq!(dot_id("Main.main"), "\"Main.main\"");
+        assert_eq!(dot_id("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}
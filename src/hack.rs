@@ -0,0 +1,477 @@
+use std::fmt::Write;
+
+use crate::{
+    vm::{Command, Function},
+    vm_writer::{Arithmetic, Segment},
+};
+
+/// Number of instructions the Hack ROM holds.
+pub const ROM_SIZE: usize = 32768;
+
+/// Largest constant an A-instruction can load, and so a `push constant`.
+pub const MAX_CONSTANT: usize = 32767;
+
+/// Character codes of the Hack character set beyond printable ASCII.
+pub const NEW_LINE: usize = 128;
+pub const BACKSPACE: usize = 129;
+
+/// Returns the code of the character written `\c` in string and character
+/// constants of the extended dialect.
+pub fn escaped(c: char) -> Option<usize> {
+    match c {
+        'n' => Some(NEW_LINE),
+        'b' => Some(BACKSPACE),
+        '"' | '\'' | '\\' => Some(c as usize),
+        _ => None,
+    }
+}
+
+/// Translates `functions` to Hack assembly, preceded by the bootstrap code
+/// setting up the stack and calling `Sys.init`.
+pub fn translate(functions: &[Function]) -> String {
+    let mut translator = Translator::default();
+    translator.bootstrap();
+    for function in functions {
+        translator.function(function);
+    }
+    translator.output
+}
+
+/// Returns the number of instructions of the bootstrap code.
+pub fn bootstrap_size() -> usize {
+    let mut translator = Translator::default();
+    translator.bootstrap();
+    translator.instructions
+}
+
+/// Returns the number of instructions `function` translates to.
+pub fn instruction_count(function: &Function) -> usize {
+    let mut translator = Translator::default();
+    translator.function(function);
+    translator.instructions
+}
+
+#[derive(Default)]
+struct Translator {
+    output: String,
+    instructions: usize,
+    // makes the labels of comparisons and return addresses unique
+    counter: usize,
+}
+
+impl Translator {
+    fn emit(&mut self, instructions: &[&str]) {
+        for instruction in instructions {
+            if !instruction.starts_with('(') {
+                self.instructions += 1;
+            }
+            self.output.push_str(instruction);
+            self.output.push('\n');
+        }
+    }
+
+    fn label(&mut self, label: &str) {
+        let _ = writeln!(self.output, "({label})");
+    }
+
+    fn unique_label(&mut self, prefix: &str) -> String {
+        self.counter += 1;
+        format!("{prefix}${}", self.counter)
+    }
+
+    fn bootstrap(&mut self) {
+        self.emit(&["@256", "D=A", "@SP", "M=D"]);
+        self.call("Sys.init", 0, "bootstrap");
+    }
+
+    fn function(&mut self, function: &Function) {
+        let _ = writeln!(
+            self.output,
+            "// function {} {}",
+            function.name, function.n_locals
+        );
+        self.label(&function.name);
+        for _ in 0..function.n_locals {
+            self.emit(&["@SP", "A=M", "M=0", "@SP", "M=M+1"]);
+        }
+        for command in &function.body {
+            self.command(command, function);
+        }
+    }
+
+    fn command(&mut self, command: &Command, function: &Function) {
+        match command {
+            Command::Push(Segment::CONSTANT, index) => {
+                self.emit(&[&format!("@{index}"), "D=A"]);
+                self.push_d();
+            }
+            Command::Push(segment, index) => {
+                match base(*segment) {
+                    Some(base) => {
+                        self.emit(&[&format!("@{index}"), "D=A", base, "A=D+M", "D=M"]);
+                    }
+                    None => {
+                        let address = address(*segment, *index, function);
+                        self.emit(&[&address, "D=M"]);
+                    }
+                }
+                self.push_d();
+            }
+            Command::Pop(segment, index) => match base(*segment) {
+                Some(base) => {
+                    self.emit(&[&format!("@{index}"), "D=A", base, "D=D+M", "@R13", "M=D"]);
+                    self.pop_d();
+                    self.emit(&["@R13", "A=M", "M=D"]);
+                }
+                None => {
+                    let address = address(*segment, *index, function);
+                    self.pop_d();
+                    self.emit(&[&address, "M=D"]);
+                }
+            },
+            Command::Arithmetic(command) => self.arithmetic(*command),
+            Command::Label(label) => self.label(&format!("{}${label}", function.name)),
+            Command::Goto(label) => {
+                self.emit(&[&format!("@{}${label}", function.name), "0;JMP"]);
+            }
+            Command::IfGoto(label) => {
+                self.pop_d();
+                self.emit(&[&format!("@{}${label}", function.name), "D;JNE"]);
+            }
+            Command::Call(name, n_args) => self.call(name, *n_args, &function.name),
+            Command::Return => self.r#return(),
+        }
+    }
+
+    fn push_d(&mut self) {
+        self.emit(&["@SP", "A=M", "M=D", "@SP", "M=M+1"]);
+    }
+
+    fn pop_d(&mut self) {
+        self.emit(&["@SP", "AM=M-1", "D=M"]);
+    }
+
+    fn arithmetic(&mut self, command: Arithmetic) {
+        let binary = |operation| ["@SP", "AM=M-1", "D=M", "A=A-1", operation];
+        match command {
+            Arithmetic::ADD => self.emit(&binary("M=D+M")),
+            Arithmetic::SUB => self.emit(&binary("M=M-D")),
+            Arithmetic::AND => self.emit(&binary("M=D&M")),
+            Arithmetic::OR => self.emit(&binary("M=D|M")),
+            Arithmetic::NEG => self.emit(&["@SP", "A=M-1", "M=-M"]),
+            Arithmetic::NOT => self.emit(&["@SP", "A=M-1", "M=!M"]),
+            Arithmetic::EQ => {
+                let is_true = self.unique_label("TRUE");
+                let end = self.unique_label("END");
+                // x - y wraps around but is only 0 when x = y
+                self.emit(&binary("D=M-D"));
+                self.emit(&[&format!("@{is_true}"), "D;JEQ"]);
+                self.result(&is_true, &end);
+            }
+            Arithmetic::GT | Arithmetic::LT => {
+                let is_true = self.unique_label("TRUE");
+                let is_false = self.unique_label("FALSE");
+                let x_negative = self.unique_label("XNEG");
+                let same_sign = self.unique_label("SAME");
+                let end = self.unique_label("END");
+                // with x and y of opposite signs x - y may overflow, but
+                // the negative one is the lesser
+                let (y_negative, y_positive, jump) = if command == Arithmetic::GT {
+                    (&is_true, &is_false, "D;JGT")
+                } else {
+                    (&is_false, &is_true, "D;JLT")
+                };
+                self.emit(&["@SP", "AM=M-1", "D=M", "@R13", "M=D", "@SP", "A=M-1", "D=M"]);
+                self.emit(&[&format!("@{x_negative}"), "D;JLT"]);
+                self.emit(&["@R13", "D=M", &format!("@{y_negative}"), "D;JLT"]);
+                self.emit(&[&format!("@{same_sign}"), "0;JMP"]);
+                self.label(&x_negative);
+                self.emit(&["@R13", "D=M", &format!("@{y_positive}"), "D;JGE"]);
+                self.label(&same_sign);
+                self.emit(&["@R13", "D=M", "@SP", "A=M-1", "D=M-D"]);
+                self.emit(&[&format!("@{is_true}"), jump]);
+                self.label(&is_false);
+                self.result(&is_true, &end);
+            }
+        }
+    }
+
+    /// Sets the top of the stack to false, or to true when jumping to
+    /// `is_true`, and carries on from `end`.
+    fn result(&mut self, is_true: &str, end: &str) {
+        self.emit(&["@SP", "A=M-1", "M=0", &format!("@{end}"), "0;JMP"]);
+        self.label(is_true);
+        self.emit(&["@SP", "A=M-1", "M=-1"]);
+        self.label(end);
+    }
+
+    fn call(&mut self, name: &str, n_args: usize, caller: &str) {
+        let return_address = self.unique_label(&format!("{caller}$ret"));
+        self.emit(&[&format!("@{return_address}"), "D=A"]);
+        self.push_d();
+        for pointer in ["@LCL", "@ARG", "@THIS", "@THAT"] {
+            self.emit(&[pointer, "D=M"]);
+            self.push_d();
+        }
+        self.emit(&[
+            "@SP",
+            "D=M",
+            &format!("@{}", n_args + 5),
+            "D=D-A",
+            "@ARG",
+            "M=D",
+            "@SP",
+            "D=M",
+            "@LCL",
+            "M=D",
+            &format!("@{name}"),
+            "0;JMP",
+        ]);
+        self.label(&return_address);
+    }
+
+    fn r#return(&mut self) {
+        self.emit(&[
+            // frame and return address, before argument 0 is overwritten
+            "@LCL", "D=M", "@R13", "M=D", "@5", "A=D-A", "D=M", "@R14", "M=D",
+        ]);
+        self.pop_d();
+        self.emit(&["@ARG", "A=M", "M=D", "@ARG", "D=M+1", "@SP", "M=D"]);
+        for pointer in ["@THAT", "@THIS", "@ARG", "@LCL"] {
+            self.emit(&["@R13", "AM=M-1", "D=M", pointer, "M=D"]);
+        }
+        self.emit(&["@R14", "A=M", "0;JMP"]);
+    }
+}
+
+/// Returns the register holding the base address of `segment`, for the
+/// segments located through a pointer.
+fn base(segment: Segment) -> Option<&'static str> {
+    match segment {
+        Segment::LOCAL => Some("@LCL"),
+        Segment::ARGUMENT => Some("@ARG"),
+        Segment::THIS => Some("@THIS"),
+        Segment::THAT => Some("@THAT"),
+        _ => None,
+    }
+}
+
+/// Returns the A-instruction addressing `index` in one of the segments at a
+/// fixed address.
+fn address(segment: Segment, index: usize, function: &Function) -> String {
+    match segment {
+        Segment::POINTER => format!("@{}", 3 + index),
+        Segment::TEMP => format!("@{}", 5 + index),
+        _ => format!("@{}.{index}", function.class),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::vm;
+
+    /// Runs the Hack assembly of the program `vm` until `Sys.init` reaches
+    /// its `HALT` label, returning the RAM and the address of every symbol.
+    fn run(vm: &str) -> (Vec<i16>, HashMap<String, usize>) {
+        let functions = vm::parse("Sys", vm).unwrap();
+        let assembly = translate(&functions);
+        let mut symbols: HashMap<String, usize> = [
+            ("SP", 0),
+            ("LCL", 1),
+            ("ARG", 2),
+            ("THIS", 3),
+            ("THAT", 4),
+            ("R13", 13),
+            ("R14", 14),
+        ]
+        .into_iter()
+        .map(|(name, address)| (name.to_string(), address))
+        .collect();
+        let mut program = Vec::new();
+        for line in assembly.lines() {
+            let line = line.split("//").next().unwrap().trim();
+            if let Some(label) = line.strip_prefix('(') {
+                symbols.insert(label.trim_end_matches(')').to_string(), program.len());
+            } else if !line.is_empty() {
+                program.push(line);
+            }
+        }
+        let halt = symbols["Sys.init$HALT"];
+
+        let mut ram = vec![0i16; 32768];
+        let (mut a, mut d, mut pc) = (0i16, 0i16, 0);
+        let mut variables = 16;
+        for _ in 0..100_000 {
+            if pc == halt {
+                return (ram, symbols);
+            }
+            let instruction = program[pc];
+            pc += 1;
+            if let Some(symbol) = instruction.strip_prefix('@') {
+                a = match symbol.parse() {
+                    Ok(value) => value,
+                    Err(_) => *symbols.entry(symbol.to_string()).or_insert_with(|| {
+                        variables += 1;
+                        variables - 1
+                    }) as i16,
+                };
+                continue;
+            }
+            let (dest, rest) = instruction.split_once('=').unwrap_or(("", instruction));
+            let (comp, jump) = rest.split_once(';').unwrap_or((rest, ""));
+            let m = ram[a as u16 as usize];
+            let value = match comp {
+                "0" => 0,
+                "-1" => -1,
+                "D" => d,
+                "A" => a,
+                "M" => m,
+                "!M" => !m,
+                "-M" => m.wrapping_neg(),
+                "M+1" => m.wrapping_add(1),
+                "D+1" => d.wrapping_add(1),
+                "M-1" => m.wrapping_sub(1),
+                "A-1" => a.wrapping_sub(1),
+                "D+M" => d.wrapping_add(m),
+                "D+A" => d.wrapping_add(a),
+                "D-A" => d.wrapping_sub(a),
+                "M-D" => m.wrapping_sub(d),
+                "D&M" => d & m,
+                "D|M" => d | m,
+                _ => panic!("unexpected instruction {instruction}"),
+            };
+            if dest.contains('M') {
+                ram[a as u16 as usize] = value;
+            }
+            if dest.contains('A') {
+                a = value;
+            }
+            if dest.contains('D') {
+                d = value;
+            }
+            let jumps = match jump {
+                "" => false,
+                "JMP" => true,
+                "JEQ" => value == 0,
+                "JNE" => value != 0,
+                "JGT" => value > 0,
+                "JGE" => value >= 0,
+                "JLT" => value < 0,
+                _ => panic!("unexpected jump {jump}"),
+            };
+            if jumps {
+                pc = a as usize;
+            }
+        }
+        panic!("Sys.init did not halt");
+    }
+
+    fn push(value: i16) -> String {
+        match value {
+            i16::MIN => String::from("push constant 32767\nneg\npush constant 1\nsub\n"),
+            value if value < 0 => format!("push constant {}\nneg\n", -value),
+            value => format!("push constant {value}\n"),
+        }
+    }
+
+    #[test]
+    fn compares_across_the_whole_range() {
+        let pairs: [(i16, i16); 10] = [
+            (1, i16::MIN),
+            (i16::MIN, 1),
+            (i16::MAX, -1),
+            (-1, i16::MAX),
+            (i16::MAX, i16::MIN),
+            (i16::MIN, i16::MAX),
+            (i16::MIN, i16::MIN),
+            (-3, -7),
+            (5, 5),
+            (0, -1),
+        ];
+        let mut vm = String::from("function Sys.init 0\n");
+        for (i, &(x, y)) in pairs.iter().enumerate() {
+            for (j, command) in ["eq", "gt", "lt"].iter().enumerate() {
+                vm += &format!(
+                    "{}{}{command}\npop static {}\n",
+                    push(x),
+                    push(y),
+                    i * 3 + j
+                );
+            }
+        }
+        vm += "label HALT\ngoto HALT\n";
+
+        let (ram, symbols) = run(&vm);
+        for (i, &(x, y)) in pairs.iter().enumerate() {
+            let result = |j: usize| ram[symbols[&format!("Sys.{}", i * 3 + j)]];
+            let expected = |b: bool| if b { -1 } else { 0 };
+            assert_eq!(result(0), expected(x == y), "{x} eq {y}");
+            assert_eq!(result(1), expected(x > y), "{x} gt {y}");
+            assert_eq!(result(2), expected(x < y), "{x} lt {y}");
+        }
+    }
+
+    #[test]
+    fn calls_functions_and_returns() {
+        let vm = "function Sys.init 0
+push constant 30
+push constant 12
+call Sys.combine 2
+pop static 0
+push static 0
+push constant 4000
+pop pointer 1
+pop that 2
+label HALT
+goto HALT
+function Sys.combine 1
+push argument 0
+push argument 1
+sub
+pop local 0
+push local 0
+push local 0
+add
+neg
+not
+push argument 0
+and
+push argument 1
+or
+return
+";
+        let (ram, symbols) = run(vm);
+        // !(-(18 + 18)) & 30 | 12
+        let expected = (!(-36i16) & 30) | 12;
+        assert_eq!(ram[symbols["Sys.0"]], expected);
+        assert_eq!(ram[4002], expected);
+        // back to the stack of Sys.init, after the frame of its caller
+        assert_eq!(ram[0], 261);
+    }
+
+    #[test]
+    fn counts_instructions() {
+        let functions =
+            vm::parse("Main", "function Main.f 2\npush constant 1\ngt\nreturn\n").unwrap();
+        let assembly = translate(&functions);
+        let instructions = assembly
+            .lines()
+            .filter(|line| !line.starts_with('(') && !line.starts_with("//"))
+            .count();
+        assert_eq!(
+            instructions,
+            bootstrap_size() + instruction_count(&functions[0])
+        );
+    }
+
+    #[test]
+    fn escapes_characters() {
+        assert_eq!(escaped('n'), Some(NEW_LINE));
+        assert_eq!(escaped('b'), Some(BACKSPACE));
+        assert_eq!(escaped('\''), Some(39));
+        assert_eq!(escaped('x'), None);
+    }
+}
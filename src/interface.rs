@@ -0,0 +1,727 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    diagnostic::{Code, Diagnostic},
+    hack,
+    tokenizer::{self, Dialect, Span, Token, Tokenizer},
+};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SubroutineKind {
+    Constructor,
+    Function,
+    Method,
+}
+
+impl SubroutineKind {
+    fn parse(keyword: &str) -> Option<SubroutineKind> {
+        match keyword {
+            "constructor" => Some(SubroutineKind::Constructor),
+            "function" => Some(SubroutineKind::Function),
+            "method" => Some(SubroutineKind::Method),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SubroutineKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubroutineKind::Constructor => write!(f, "constructor"),
+            SubroutineKind::Function => write!(f, "function"),
+            SubroutineKind::Method => write!(f, "method"),
+        }
+    }
+}
+
+/// A typed name, a parameter or a field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub _type: String,
+    pub name: String,
+}
+
+/// A constant of a class or a member of an enum, whose value other classes
+/// compile in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constant {
+    pub _type: String,
+    pub name: String,
+    pub value: i16,
+}
+
+/// An enum declared in a class. Its name is a type of its own, through which
+/// the whole program refers to its members, e.g. `Direction.UP`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enum {
+    pub name: String,
+    pub members: Vec<Constant>,
+}
+
+/// Declaration of a subroutine, as other classes see it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub kind: SubroutineKind,
+    pub return_type: String,
+    pub name: String,
+    pub parameters: Vec<Variable>,
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}(", self.kind, self.return_type, self.name)?;
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}", parameter._type, parameter.name)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The subroutines and constants a class makes available to other classes,
+/// along with its fields, which make up the layout of its objects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassInterface {
+    pub name: String,
+    pub constants: Vec<Constant>,
+    pub enums: Vec<Enum>,
+    pub fields: Vec<Variable>,
+    pub subroutines: Vec<Signature>,
+}
+
+impl ClassInterface {
+    pub fn subroutine(&self, name: &str) -> Option<&Signature> {
+        self.subroutines
+            .iter()
+            .find(|subroutine| subroutine.name == name)
+    }
+
+    pub fn constant(&self, name: &str) -> Option<&Constant> {
+        self.constants.iter().find(|constant| constant.name == name)
+    }
+
+    /// Reads the interface of the class declared in a `.jack` source,
+    /// skipping over the subroutine bodies. Returns `None` when the
+    /// declarations are malformed, compiling the class reports why.
+    pub fn scan(source: &str, dialect: Dialect) -> Option<ClassInterface> {
+        Parser::new(source, dialect).class(true).ok()
+    }
+}
+
+/// Formats the interface in the syntax of interface files.
+impl fmt::Display for ClassInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "class {} {{", self.name)?;
+        for constant in &self.constants {
+            writeln!(
+                f,
+                "    const {} {} = {};",
+                constant._type, constant.name, constant.value
+            )?;
+        }
+        for declaration in &self.enums {
+            write!(f, "    enum {} {{", declaration.name)?;
+            for (i, member) in declaration.members.iter().enumerate() {
+                let separator = if i > 0 { "," } else { "" };
+                write!(f, "{separator} {} = {}", member.name, member.value)?;
+            }
+            writeln!(f, " }}")?;
+        }
+        for field in &self.fields {
+            writeln!(f, "    field {} {};", field._type, field.name)?;
+        }
+        for subroutine in &self.subroutines {
+            writeln!(f, "    {};", subroutine)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// Parses an interface file: class declarations whose subroutines are
+/// declared without a body, e.g.
+///
+/// ```text
+/// class Point {
+///     field int x;
+///     field int y;
+///     constructor Point new(int ax, int ay);
+///     method int getX();
+/// }
+/// ```
+pub fn parse(source: &str) -> Result<Vec<ClassInterface>, Box<Diagnostic>> {
+    let mut parser = Parser::new(source, Dialect::Standard);
+    let mut classes = Vec::new();
+    while parser.peek().is_some() {
+        classes.push(parser.class(false)?);
+    }
+    Ok(classes)
+}
+
+/// Every class a program can call into: the OS, libraries described by
+/// interface files and the classes of the program itself.
+#[derive(Debug, Default, Clone)]
+pub struct Declarations {
+    // classes whose interface is unknown, like those only available as `.vm`,
+    // map to `None` and calls into them aren't checked
+    classes: HashMap<String, Option<ClassInterface>>,
+    // the class declaring each enum
+    enums: HashMap<String, String>,
+}
+
+impl Declarations {
+    pub fn new() -> Declarations {
+        Declarations::default()
+    }
+
+    /// Adds `interface`, replacing any class of the same name. Its enums
+    /// are added as classes of their own, only made of constants. Fails,
+    /// adding nothing, when a name would then stand for two classes or
+    /// enums.
+    pub fn add(&mut self, interface: ClassInterface) -> Result<(), String> {
+        if let Some(owner) = self.enums.get(&interface.name) {
+            return Err(format!(
+                "class `{}` has the name of an enum of class `{owner}`",
+                interface.name
+            ));
+        }
+        for declaration in &interface.enums {
+            let name = &declaration.name;
+            match self.enums.get(name) {
+                Some(owner) if *owner != interface.name => {
+                    return Err(format!(
+                        "enum `{name}` of class `{}` is also declared by class `{owner}`",
+                        interface.name
+                    ));
+                }
+                None if *name == interface.name || self.classes.contains_key(name) => {
+                    return Err(format!(
+                        "enum `{name}` of class `{}` has the name of a class",
+                        interface.name
+                    ));
+                }
+                _ => {}
+            }
+        }
+        for declaration in &interface.enums {
+            self.enums
+                .insert(declaration.name.clone(), interface.name.clone());
+            let members = ClassInterface {
+                name: declaration.name.clone(),
+                constants: declaration.members.clone(),
+                enums: Vec::new(),
+                fields: Vec::new(),
+                subroutines: Vec::new(),
+            };
+            self.classes.insert(declaration.name.clone(), Some(members));
+        }
+        self.classes.insert(interface.name.clone(), Some(interface));
+        Ok(())
+    }
+
+    /// Adds a class whose interface is unknown, unless it is already known.
+    pub fn declare(&mut self, class_name: &str) {
+        self.classes.entry(class_name.to_string()).or_default();
+    }
+
+    pub fn contains(&self, class_name: &str) -> bool {
+        self.classes.contains_key(class_name)
+    }
+
+    pub fn get(&self, class_name: &str) -> Option<&ClassInterface> {
+        self.classes.get(class_name).and_then(Option::as_ref)
+    }
+
+    pub fn class_names(&self) -> impl Iterator<Item = &str> {
+        self.classes.keys().map(String::as_str)
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<(Span, Token<'a>)>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str, dialect: Dialect) -> Parser<'a> {
+        let tokens = Tokenizer::build(source)
+            .unwrap()
+            .with_dialect(dialect)
+            .spanned()
+            .filter(|(_, x)| {
+                !matches!(
+                    x,
+                    Token::Whitespace(_) | Token::SingleLineComment(_) | Token::BlockComment(_)
+                )
+            })
+            .collect();
+        Parser {
+            source,
+            tokens,
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).map(|&(_, token)| token)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<(Span, Token<'a>), Box<Diagnostic>> {
+        match self.tokens.get(self.position) {
+            Some(&(span, Token::Invalid(token))) => Err(Box::new(Diagnostic::error(
+                Code::InvalidToken,
+                span,
+                format!("invalid token `{token}`"),
+            ))),
+            Some(&next) => {
+                self.position += 1;
+                Ok(next)
+            }
+            None => {
+                let end = self.tokens.last().map_or(0, |(span, _)| span.end);
+                Err(Box::new(Diagnostic::error(
+                    Code::UnexpectedToken,
+                    Span::new(end, end),
+                    format!("expected {expected}, found end of file"),
+                )))
+            }
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), Box<Diagnostic>> {
+        let expected = token.to_string();
+        match self.next(&expected)? {
+            (_, found) if found == token => Ok(()),
+            (span, found) => Err(unexpected(span, &expected, found)),
+        }
+    }
+
+    fn identifier(&mut self, expected: &str) -> Result<&'a str, Box<Diagnostic>> {
+        match self.next(expected)? {
+            (_, Token::Identifier(name)) => Ok(name),
+            (span, found) => Err(unexpected(span, expected, found)),
+        }
+    }
+
+    /// Parses a type, a class name or one of `keywords`.
+    fn _type(&mut self, expected: &str, keywords: &[&str]) -> Result<&'a str, Box<Diagnostic>> {
+        match self.next(expected)? {
+            (_, Token::Identifier(name)) => Ok(name),
+            (_, Token::Keyword(keyword)) if keywords.contains(&keyword) => Ok(keyword),
+            (span, found) => Err(unexpected(span, expected, found)),
+        }
+    }
+
+    /// Parses a class declaration, with subroutine bodies when `bodies`, as
+    /// in `.jack` sources, or without as in interface files.
+    fn class(&mut self, bodies: bool) -> Result<ClassInterface, Box<Diagnostic>> {
+        self.expect(Token::Keyword("class"))?;
+        let name = self.identifier("a class name")?;
+        self.expect(Token::Symbol('{'))?;
+
+        let mut constants = Vec::new();
+        let mut enums = Vec::new();
+        let mut fields = Vec::new();
+        let mut subroutines = Vec::new();
+        loop {
+            match self.next("a subroutine declaration or `}`")? {
+                (_, Token::Symbol('}')) => break,
+                // interface files are read in the standard dialect, where
+                // `const` and `enum` are not keywords
+                (_, Token::Keyword("const") | Token::Identifier("const")) => {
+                    let _type = self._type("a type", &["int", "char", "boolean"])?;
+                    let name = self.identifier("a constant name")?;
+                    self.expect(Token::Symbol('='))?;
+                    constants.push(Constant {
+                        _type: _type.to_string(),
+                        name: name.to_string(),
+                        value: self.constant_value()?,
+                    });
+                    self.expect(Token::Symbol(';'))?;
+                }
+                (_, Token::Keyword("enum") | Token::Identifier("enum")) => {
+                    enums.push(self.enum_members()?);
+                }
+                (_, Token::Keyword("static")) => while self.next("`;`")?.1 != Token::Symbol(';') {},
+                (_, Token::Keyword("field")) => {
+                    let _type = self._type("a type", &["int", "char", "boolean"])?;
+                    loop {
+                        let name = self.identifier("a field name")?;
+                        fields.push(Variable {
+                            _type: _type.to_string(),
+                            name: name.to_string(),
+                        });
+                        match self.next("`,` or `;`")? {
+                            (_, Token::Symbol(',')) => {}
+                            (_, Token::Symbol(';')) => break,
+                            (span, found) => return Err(unexpected(span, "`,` or `;`", found)),
+                        }
+                    }
+                }
+                (_, Token::Keyword(keyword)) if SubroutineKind::parse(keyword).is_some() => {
+                    let kind = SubroutineKind::parse(keyword).expect("Expected a subroutine kind");
+                    let return_type =
+                        self._type("a return type", &["void", "int", "char", "boolean"])?;
+                    let subroutine_name = self.identifier("a subroutine name")?;
+                    self.expect(Token::Symbol('('))?;
+                    let parameters = self.parameters()?;
+                    if bodies {
+                        self.skip_body()?;
+                    } else {
+                        self.expect(Token::Symbol(';'))?;
+                    }
+                    subroutines.push(Signature {
+                        kind,
+                        return_type: return_type.to_string(),
+                        name: subroutine_name.to_string(),
+                        parameters,
+                    });
+                }
+                (span, found) => {
+                    return Err(unexpected(span, "a subroutine declaration or `}`", found))
+                }
+            }
+        }
+        Ok(ClassInterface {
+            name: name.to_string(),
+            constants,
+            enums,
+            fields,
+            subroutines,
+        })
+    }
+
+    /// Parses the name and members of an enum, up to its closing `}`.
+    /// Members without a value follow the previous one, starting from 0.
+    fn enum_members(&mut self) -> Result<Enum, Box<Diagnostic>> {
+        let name = self.identifier("an enum name")?;
+        self.expect(Token::Symbol('{'))?;
+        let mut members = Vec::new();
+        let mut value: i16 = 0;
+        loop {
+            if self.peek() == Some(Token::Symbol('}')) {
+                self.position += 1;
+                break;
+            }
+            let member = self.identifier("an enum member")?;
+            if self.peek() == Some(Token::Symbol('=')) {
+                self.position += 1;
+                value = self.constant_value()?;
+            }
+            members.push(Constant {
+                _type: name.to_string(),
+                name: member.to_string(),
+                value,
+            });
+            value = value.wrapping_add(1);
+            match self.next("`,` or `}`")? {
+                (_, Token::Symbol(',')) => {}
+                (_, Token::Symbol('}')) => break,
+                (span, found) => return Err(unexpected(span, "`,` or `}`", found)),
+            }
+        }
+        Ok(Enum {
+            name: name.to_string(),
+            members,
+        })
+    }
+
+    /// Parses the value of a constant: an integer, character or boolean
+    /// constant, possibly negated.
+    fn constant_value(&mut self) -> Result<i16, Box<Diagnostic>> {
+        let expected = "a constant value";
+        let negate = self.peek() == Some(Token::Symbol('-'));
+        if negate {
+            self.position += 1;
+        }
+        let word = match self.next(expected)? {
+            (span, Token::IntConstant(c)) => self.int_constant_word(span, c, negate)?,
+            (_, Token::CharConst(c)) => {
+                let mut chars = c.chars();
+                match (chars.next(), chars.next(), chars.next()) {
+                    (Some('\\'), Some(escaped), None) => match hack::escaped(escaped) {
+                        Some(code) => code as u16,
+                        None => return Err(self.invalid_constant()),
+                    },
+                    (Some(c), None, _) if c.is_ascii() => c as u16,
+                    _ => return Err(self.invalid_constant()),
+                }
+            }
+            (_, Token::Keyword("true")) => u16::MAX,
+            (_, Token::Keyword("false" | "null")) => 0,
+            (span, found) => return Err(unexpected(span, expected, found)),
+        };
+        let value = word as i16;
+        Ok(if negate { value.wrapping_neg() } else { value })
+    }
+
+    /// Returns the word of the integer constant `value` at `span`, in the
+    /// range the compiler accepts: up to 32767, or 16 bits in hexadecimal
+    /// or binary. Interface files having no hexadecimal, -32768 is written
+    /// as such, so 32768 is accepted when `negated`.
+    fn int_constant_word(
+        &self,
+        span: Span,
+        value: usize,
+        negated: bool,
+    ) -> Result<u16, Box<Diagnostic>> {
+        let text = &self.source[span.start..span.end];
+        if value <= hack::MAX_CONSTANT
+            || negated && value == hack::MAX_CONSTANT + 1
+            || tokenizer::has_radix_prefix(text) && value <= usize::from(u16::MAX)
+        {
+            Ok(value as u16)
+        } else {
+            Err(Box::new(Diagnostic::error(
+                Code::IntegerOverflow,
+                span,
+                format!("integer constant `{text}` is out of range"),
+            )))
+        }
+    }
+
+    fn invalid_constant(&self) -> Box<Diagnostic> {
+        let (span, found) = self.tokens[self.position - 1];
+        unexpected(span, "a constant value", found)
+    }
+
+    /// Parses a parameter list, along with its closing `)`.
+    fn parameters(&mut self) -> Result<Vec<Variable>, Box<Diagnostic>> {
+        let mut parameters = Vec::new();
+        if self.peek() == Some(Token::Symbol(')')) {
+            self.position += 1;
+            return Ok(parameters);
+        }
+        loop {
+            let _type = self._type("a parameter type", &["int", "char", "boolean"])?;
+            let name = self.identifier("a parameter name")?;
+            parameters.push(Variable {
+                _type: _type.to_string(),
+                name: name.to_string(),
+            });
+            match self.next("`,` or `)`")? {
+                (_, Token::Symbol(',')) => {}
+                (_, Token::Symbol(')')) => return Ok(parameters),
+                (span, found) => return Err(unexpected(span, "`,` or `)`", found)),
+            }
+        }
+    }
+
+    fn skip_body(&mut self) -> Result<(), Box<Diagnostic>> {
+        self.expect(Token::Symbol('{'))?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next("`}`")?.1 {
+                Token::Symbol('{') => depth += 1,
+                Token::Symbol('}') => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn unexpected(span: Span, expected: &str, found: Token) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error(
+        Code::UnexpectedToken,
+        span,
+        format!("expected {expected}, found {found}"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINT: &str = "class Point {
+    const int ORIGIN = -1;
+    enum Axis { X, Y = 4, Z }
+    field int x, y;
+    constructor Point new(int ax, int ay);
+    method int getX();
+    function void swap(Point a, Point b);
+}
+";
+
+    #[test]
+    fn parses_interfaces() {
+        let classes = parse(&format!("// points\n{POINT}class Empty {{\n}}\n")).unwrap();
+        assert_eq!(classes.len(), 2);
+        let point = &classes[0];
+        assert_eq!(point.name, "Point");
+        assert_eq!(point.constant("ORIGIN").unwrap().value, -1);
+        let values: Vec<_> = point.enums[0].members.iter().map(|x| x.value).collect();
+        assert_eq!(values, [0, 4, 5]);
+        assert_eq!(point.enums[0].members[2]._type, "Axis");
+        let fields: Vec<_> = point.fields.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(fields, ["x", "y"]);
+        let new = point.subroutine("new").unwrap();
+        assert_eq!(new.kind, SubroutineKind::Constructor);
+        assert_eq!(new.to_string(), "constructor Point new(int ax, int ay)");
+        assert_eq!(
+            point.subroutine("swap").unwrap().parameters[1]._type,
+            "Point"
+        );
+        assert!(point.subroutine("getY").is_none());
+        assert_eq!(classes[1].subroutines, []);
+        assert_eq!(parse("").unwrap(), []);
+    }
+
+    #[test]
+    fn formats_what_it_parses() {
+        let point = parse(POINT).unwrap().remove(0);
+        let formatted = point.to_string();
+        assert!(formatted.contains("    enum Axis { X = 0, Y = 4, Z = 5 }\n"));
+        assert_eq!(parse(&formatted).unwrap(), [point]);
+    }
+
+    #[test]
+    fn reads_constant_values() {
+        let source = "class C {
+    const boolean YES = true;
+    const int MIN = -32768;
+    const int MAX = 32767;
+}
+";
+        let class = parse(source).unwrap().remove(0);
+        let values: Vec<_> = class.constants.iter().map(|x| x.value).collect();
+        assert_eq!(values, [-1, i16::MIN, i16::MAX]);
+        for big in ["32768", "65535", "-32769"] {
+            let error = parse(&format!("class C {{ const int BIG = {big}; }}")).unwrap_err();
+            assert_eq!(error.code, Code::IntegerOverflow, "{big}");
+        }
+
+        // as in the compiler, hexadecimal and binary constants hold 16 bits
+        let source = "class C {
+    const int HIGH = 0x8000;
+    const int MASK = 0b1111111111111111;
+}
+";
+        let class = ClassInterface::scan(source, Dialect::Extended).unwrap();
+        let values: Vec<_> = class.constants.iter().map(|x| x.value).collect();
+        assert_eq!(values, [i16::MIN, -1]);
+        let source = "class C { const int BIG = 0x10000; }";
+        assert!(ClassInterface::scan(source, Dialect::Extended).is_none());
+
+        // character constants only exist in the extended dialect
+        let source = "class C {
+    const char A = 'a';
+    const char NEWLINE = '\\n';
+}
+";
+        let class = ClassInterface::scan(source, Dialect::Extended).unwrap();
+        let values: Vec<_> = class.constants.iter().map(|x| x.value).collect();
+        assert_eq!(values, [97, 128]);
+        assert!(parse(source).is_err());
+        let source = "class C { const char AB = 'ab'; }";
+        assert!(ClassInterface::scan(source, Dialect::Extended).is_none());
+    }
+
+    #[test]
+    fn reports_malformed_interfaces() {
+        let source = "class Point {\n    method int getX() {\n        return x;\n    }\n}\n";
+        let error = parse(source).unwrap_err();
+        assert_eq!(error.code, Code::UnexpectedToken);
+        assert_eq!(&source[error.span.start..error.span.end], "{");
+        assert!(
+            error.message.starts_with("expected `;`"),
+            "{}",
+            error.message
+        );
+
+        let error = parse("class Point {\n    method int getX(;\n").unwrap_err();
+        assert!(
+            error.message.starts_with("expected a parameter type"),
+            "{}",
+            error.message
+        );
+
+        let source = "class Point {\n    field int x;\n";
+        let error = parse(source).unwrap_err();
+        assert_eq!(
+            error.message,
+            "expected a subroutine declaration or `}`, found end of file"
+        );
+        assert_eq!(error.span, Span::new(source.len() - 1, source.len() - 1));
+
+        let error = parse("class Point { field int x # }").unwrap_err();
+        assert_eq!(error.code, Code::InvalidToken);
+    }
+
+    #[test]
+    fn scans_sources() {
+        let source = "class Main {
+    static int count;
+    field int x;
+    method int get() {
+        if (x) { return 1; }
+        return 0;
+    }
+    function void main() {
+        return;
+    }
+}
+";
+        let main = ClassInterface::scan(source, Dialect::Standard).unwrap();
+        let names: Vec<_> = main.subroutines.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, ["get", "main"]);
+        assert_eq!(main.fields.len(), 1);
+        assert!(ClassInterface::scan("class Main { method }", Dialect::Standard).is_none());
+    }
+
+    #[test]
+    fn declares_classes() {
+        let mut declarations = Declarations::new();
+        declarations.add(parse(POINT).unwrap().remove(0)).unwrap();
+        declarations.declare("Library");
+        // an unknown interface does not replace a known one
+        declarations.declare("Point");
+
+        assert!(declarations.contains("Library"));
+        assert!(declarations.get("Library").is_none());
+        assert!(declarations
+            .get("Point")
+            .unwrap()
+            .subroutine("new")
+            .is_some());
+        let axis = declarations.get("Axis").unwrap();
+        assert_eq!(axis.constant("Z").unwrap().value, 5);
+        assert!(!declarations.contains("Missing"));
+        let mut names: Vec<_> = declarations.class_names().collect();
+        names.sort();
+        assert_eq!(names, ["Axis", "Library", "Point"]);
+    }
+
+    #[test]
+    fn reports_conflicting_enums() {
+        let mut declarations = Declarations::new();
+        declarations.add(parse(POINT).unwrap().remove(0)).unwrap();
+        // adding a class again replaces it along with its enums
+        declarations.add(parse(POINT).unwrap().remove(0)).unwrap();
+
+        let grid = parse("class Grid { enum Axis { ROW, COLUMN } }").unwrap();
+        let error = declarations.add(grid[0].clone()).unwrap_err();
+        assert_eq!(
+            error,
+            "enum `Axis` of class `Grid` is also declared by class `Point`"
+        );
+        assert!(!declarations.contains("Grid"));
+        assert_eq!(declarations.get("Axis").unwrap().constants.len(), 3);
+
+        let error = declarations.add(parse("class Axis { }").unwrap().remove(0));
+        assert_eq!(
+            error.unwrap_err(),
+            "class `Axis` has the name of an enum of class `Point`"
+        );
+
+        declarations.declare("Color");
+        let palette = parse("class Palette { enum Color { RED } }").unwrap();
+        let error = declarations.add(palette[0].clone()).unwrap_err();
+        assert_eq!(
+            error,
+            "enum `Color` of class `Palette` has the name of a class"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt::Write;
+
+/// Returns `str` as a quoted JSON string.
+pub fn string(str: &str) -> String {
+    let mut json = String::with_capacity(str.len() + 2);
+    json.push('"');
+    for c in str.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Returns the JSON array of the already serialized `items`.
+pub fn array<I: IntoIterator<Item = String>>(items: I) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(string("plain"), "\"plain\"");
+        assert_eq!(string("a \"b\" \\ c"), "\"a \\\"b\\\" \\\\ c\"");
+        assert_eq!(string("\n\r\t\u{1}é"), "\"\\n\\r\\t\\u0001é\"");
+    }
+
+    #[test]
+    fn joins_arrays() {
+        assert_eq!(array(Vec::new()), "[]");
+        assert_eq!(array([string("a"), String::from("1")]), "[\"a\",1]");
+    }
+}
@@ -1,4 +1,22 @@
+pub mod cache;
 pub mod compilation_engine;
+pub mod diagnostic;
+pub mod explanations;
+pub mod fix;
+pub mod graph;
+pub mod hack;
+pub mod interface;
+pub mod json;
+pub mod linker;
+pub mod manifest;
+pub mod os;
+pub mod os_api;
+pub mod output;
+pub mod runtime;
+pub mod size;
+pub mod stack;
+pub mod suggest;
 pub mod symbol_table;
 pub mod tokenizer;
+pub mod vm;
 pub mod vm_writer;
@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    hack,
+    vm::{Command, Function},
+    vm_writer::Segment,
+};
+
+/// A program reduced to the functions reachable from its entry point, ready
+/// to be written as a single file.
+pub struct Linked {
+    /// The functions kept, in their original order, with their static
+    /// variables renumbered to share the namespace of the bundle.
+    pub functions: Vec<Function>,
+    /// Functions called but not part of the program, such as the OS classes
+    /// the VM emulator implements itself.
+    pub external: Vec<String>,
+    /// How much of each class was kept, by class name.
+    pub classes: BTreeMap<String, ClassSize>,
+}
+
+#[derive(Default)]
+pub struct ClassSize {
+    pub functions: usize,
+    pub kept_functions: usize,
+    pub commands: usize,
+    pub kept_commands: usize,
+    /// Hack instructions the functions translate to.
+    pub instructions: usize,
+    pub kept_instructions: usize,
+}
+
+/// Returns the functions a program starts from: `Sys.init` when the OS is
+/// part of the program, `Main.main` otherwise.
+pub fn entry_points(functions: &[Function]) -> Vec<&str> {
+    if functions.iter().any(|function| function.name == "Sys.init") {
+        vec!["Sys.init"]
+    } else {
+        vec!["Main.main"]
+    }
+}
+
+/// Returns the names of the functions reachable from `roots` through calls.
+pub fn reachable<'a>(functions: &'a [Function], roots: &[&'a str]) -> HashSet<&'a str> {
+    let by_name: HashMap<&str, &Function> = functions
+        .iter()
+        .map(|function| (function.name.as_str(), function))
+        .collect();
+    let mut reached: HashSet<&str> = HashSet::new();
+    let mut pending = roots.to_vec();
+    while let Some(name) = pending.pop() {
+        if !reached.insert(name) {
+            continue;
+        }
+        if let Some(function) = by_name.get(name) {
+            pending.extend(function.calls());
+        }
+    }
+    reached
+}
+
+/// Links `functions`, the whole program, into the functions reachable from
+/// its entry point, renaming the static variables of every class apart
+/// since the bundle is a single file named `bundle`.
+pub fn link(functions: Vec<Function>, bundle: &str) -> Result<Linked, String> {
+    let mut names = HashSet::new();
+    for function in &functions {
+        if !names.insert(function.name.as_str()) {
+            return Err(format!("function `{}` is defined twice", function.name));
+        }
+    }
+    let roots = entry_points(&functions);
+    if !names.contains(roots[0]) {
+        return Err(format!("entry point `{}` not found", roots[0]));
+    }
+
+    let reached = reachable(&functions, &roots);
+    let mut external: Vec<String> = reached
+        .iter()
+        .filter(|name| !names.contains(*name))
+        .map(|name| name.to_string())
+        .collect();
+    external.sort();
+
+    let mut classes: BTreeMap<String, ClassSize> = BTreeMap::new();
+    for function in &functions {
+        let class = classes.entry(function.class.clone()).or_default();
+        let instructions = hack::instruction_count(function);
+        class.functions += 1;
+        class.commands += function.body.len() + 1;
+        class.instructions += instructions;
+        if reached.contains(function.name.as_str()) {
+            class.kept_functions += 1;
+            class.kept_commands += function.body.len() + 1;
+            class.kept_instructions += instructions;
+        }
+    }
+
+    // static variables of each class are moved after those of the previous
+    // classes
+    let mut statics: BTreeMap<&str, usize> = BTreeMap::new();
+    for function in functions
+        .iter()
+        .filter(|function| reached.contains(function.name.as_str()))
+    {
+        let count = statics.entry(function.class.as_str()).or_default();
+        for command in &function.body {
+            if let Command::Push(Segment::STATIC, i) | Command::Pop(Segment::STATIC, i) = command {
+                *count = (*count).max(i + 1);
+            }
+        }
+    }
+    let mut offsets = HashMap::new();
+    let mut offset = 0;
+    for (class, count) in statics {
+        offsets.insert(class.to_string(), offset);
+        offset += count;
+    }
+
+    let kept = functions
+        .iter()
+        .filter(|function| reached.contains(function.name.as_str()))
+        .map(|function| {
+            let offset = offsets[&function.class];
+            let body = function
+                .body
+                .iter()
+                .map(|command| match command {
+                    Command::Push(Segment::STATIC, i) => Command::Push(Segment::STATIC, i + offset),
+                    Command::Pop(Segment::STATIC, i) => Command::Pop(Segment::STATIC, i + offset),
+                    command => command.clone(),
+                })
+                .collect();
+            Function {
+                name: function.name.clone(),
+                class: bundle.to_string(),
+                n_locals: function.n_locals,
+                body,
+            }
+        })
+        .collect();
+
+    Ok(Linked {
+        functions: kept,
+        external,
+        classes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm;
+
+    fn functions(classes: &[(&str, &str)]) -> Vec<Function> {
+        classes
+            .iter()
+            .flat_map(|(class, text)| vm::parse(class, text).unwrap())
+            .collect()
+    }
+
+    const MAIN: &str = "function Main.main 0
+push static 1
+call Main.used 1
+call Output.printInt 1
+return
+function Main.used 0
+pop static 0
+call Math.multiply 2
+return
+function Main.unused 0
+call Screen.clearScreen 0
+return
+";
+
+    const MATH: &str = "function Math.multiply 0
+push static 0
+return
+function Math.divide 0
+return
+";
+
+    #[test]
+    fn keeps_the_functions_reachable_from_main() {
+        let linked = link(functions(&[("Main", MAIN), ("Math", MATH)]), "bundle").unwrap();
+        let names: Vec<&str> = linked.functions.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, ["Main.main", "Main.used", "Math.multiply"]);
+        assert!(linked.functions.iter().all(|x| x.class == "bundle"));
+        assert_eq!(linked.external, ["Output.printInt"]);
+
+        let main = &linked.classes["Main"];
+        assert_eq!((main.functions, main.kept_functions), (3, 2));
+        assert_eq!((main.commands, main.kept_commands), (12, 9));
+        assert!(main.kept_instructions < main.instructions);
+        let math = &linked.classes["Math"];
+        assert_eq!((math.functions, math.kept_functions), (2, 1));
+    }
+
+    #[test]
+    fn renumbers_static_variables() {
+        let linked = link(functions(&[("Main", MAIN), ("Math", MATH)]), "bundle").unwrap();
+        // Main uses statics 0 and 1, so those of Math follow them
+        assert!(linked.functions[0].body[0] == Command::Push(Segment::STATIC, 1));
+        assert!(linked.functions[1].body[0] == Command::Pop(Segment::STATIC, 0));
+        assert!(linked.functions[2].body[0] == Command::Push(Segment::STATIC, 2));
+    }
+
+    #[test]
+    fn starts_from_sys_init_with_the_os() {
+        let sys = "function Sys.init 0\ncall Main.main 0\nreturn\n";
+        let program = functions(&[("Main", MAIN), ("Sys", sys)]);
+        assert_eq!(entry_points(&program), ["Sys.init"]);
+        let reached = reachable(&program, &entry_points(&program));
+        assert!(reached.contains("Main.used"));
+        assert!(!reached.contains("Main.unused"));
+        assert_eq!(entry_points(&functions(&[("Main", MAIN)])), ["Main.main"]);
+    }
+
+    #[test]
+    fn reports_invalid_programs() {
+        let error = link(functions(&[("Math", MATH)]), "bundle").err().unwrap();
+        assert_eq!(error, "entry point `Main.main` not found");
+        let program = functions(&[("Main", MAIN), ("Other", "function Main.used 0\nreturn\n")]);
+        let error = link(program, "bundle").err().unwrap();
+        assert_eq!(error, "function `Main.used` is defined twice");
+    }
+}
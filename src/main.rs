@@ -1,62 +1,1578 @@
-use std::{fs, path::PathBuf};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, SystemTime},
+};
 
 use jack_compiler::{
-    compilation_engine::CompilationEngine,
-    tokenizer::{Token, Tokenizer},
+    cache::BuildCache,
+    compilation_engine::{Compilation, CompilationEngine, Precedence},
+    diagnostic::{
+        render_human, render_json, render_sarif, Applicability, Code, Diagnostic, MessageFormat,
+        Severity, SourceFile,
+    },
+    fix,
+    graph::ProgramGraph,
+    hack,
+    interface::{self, ClassInterface, Declarations, SubroutineKind},
+    json, linker,
+    manifest::{Manifest, Os, MANIFEST},
+    os, os_api,
+    output::{self, StagedOutput},
+    runtime, size, stack,
+    tokenizer::{Dialect, Token, Tokenizer},
+    vm,
 };
 
+struct Options {
+    all_or_nothing: bool,
+    verbose: bool,
+    jobs: usize,
+    watch: bool,
+    run: Option<String>,
+    message_format: MessageFormat,
+    dry_run: bool,
+    unsafe_fixes: bool,
+    externs: Vec<PathBuf>,
+    emit: Vec<Emit>,
+    link: Vec<PathBuf>,
+    size_report: bool,
+    stack_report: bool,
+    dialect: Dialect,
+    precedence: Precedence,
+}
+
+/// Additional outputs written along with the `.vm` files.
+#[derive(PartialEq, Eq)]
+enum Emit {
+    /// `<Class>.jacki`, the interface other programs check their calls
+    /// against when using the class without its source.
+    Interface,
+    /// `callgraph.dot` and `callgraph.json`, the calls between the
+    /// subroutines of the program.
+    CallGraph,
+    /// `deps.dot` and `deps.json`, the dependencies between its classes.
+    Deps,
+    /// `symbols.txt` and `symbols.json`, the variables of every class and
+    /// subroutine along with the layout of objects.
+    Symbols,
+}
+
+/// How many times `fix` recompiles a class to look for more fixes, as fixing
+/// one error often reveals the next.
+const MAX_FIX_ROUNDS: usize = 10;
+
+/// How many string literals `--size-report` lists.
+const STRING_REPORT_LENGTH: usize = 5;
+
+/// How long `--watch` waits between polls of the sources' modification times.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Default)]
+struct Summary {
+    compiled: usize,
+    fresh: usize,
+    failed: usize,
+    errors: usize,
+    warnings: usize,
+}
+
+impl Summary {
+    /// The summary of a build that stopped before compiling any of its
+    /// `classes`.
+    fn unbuilt(classes: usize) -> Summary {
+        Summary {
+            failed: classes,
+            errors: 1,
+            ..Summary::default()
+        }
+    }
+
+    fn is_success(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} compiled, {} up to date, {} failed ({} errors, {} warnings)",
+            self.compiled, self.fresh, self.failed, self.errors, self.warnings
+        )
+    }
+}
+
+/// A class that compiled successfully and whose output is ready to be written.
+struct Compiled {
+    file_name: PathBuf,
+    // where the `.vm` goes
+    output_path: PathBuf,
+    key: u64,
+    output: String,
+    dependencies: Vec<String>,
+    // reported again when the class is found fresh in the build cache
+    warnings: Vec<Diagnostic>,
+    // contents of the `.jacki` to write along with the `.vm`
+    interface: Option<String>,
+}
+
+/// The diagnostics reported for a class, along with its source to point into.
+struct Report {
+    source: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+enum Outcome {
+    /// The build cache says the existing output is up to date, reporting
+    /// the warnings it was compiled with.
+    Fresh(Report),
+    Compiled(Compiled, Report),
+    Failed(Report),
+    Unreadable(io::Error),
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            all_or_nothing: false,
+            verbose: false,
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            watch: false,
+            run: None,
+            message_format: MessageFormat::Human,
+            dry_run: false,
+            unsafe_fixes: false,
+            externs: Vec::new(),
+            emit: Vec::new(),
+            link: Vec::new(),
+            size_report: false,
+            stack_report: false,
+            dialect: Dialect::Standard,
+            precedence: Precedence::Jack,
+        }
+    }
+}
+
 fn main() {
-    let mut args = std::env::args();
-    args.next();
-    let path = args
-        .next()
-        .map_or_else(|| PathBuf::from("."), |arg| PathBuf::from(arg));
-
-    if path.is_dir() {
-        let iter = path
-            .read_dir()
-            .expect("Expected to read_dir() successfully")
-            .filter_map(|x| x.ok())
-            .filter(|x| {
-                x.path().is_file() && x.path().extension().and_then(|x| x.to_str()) == Some("jack")
-            })
-            .map(|x| x.path());
+    let mut args = std::env::args().skip(1).peekable();
+    let fix_mode = args.next_if_eq("fix").is_some();
+    let project_mode = !fix_mode && args.next_if_eq("build").is_some();
+    let args: Vec<String> = args.collect();
+    let mut options = Options::default();
+    let path = parse_args(args.iter().cloned(), &mut options).unwrap_or_else(|| PathBuf::from("."));
+
+    if fix_mode {
+        if !fix(&path, &options) {
+            std::process::exit(1);
+        }
+        return;
+    }
 
-        for file_name in iter {
-            compile(file_name);
+    if project_mode {
+        let manifest_path = if path.is_dir() {
+            path.join(MANIFEST)
+        } else {
+            path
+        };
+        let manifest = match Manifest::load(&manifest_path) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                eprintln!("ERROR: {}", err);
+                std::process::exit(1);
+            }
+        };
+        // flags of the command line come last to override the manifest's
+        let mut options = Options::default();
+        parse_args(manifest.flags.iter().cloned().chain(args), &mut options);
+        if options.watch {
+            watch(
+                || project_sources(&manifest).unwrap_or_default().file_names,
+                || build_project(&manifest, &options),
+                &options,
+            );
         }
+        let summary = build_project(&manifest, &options);
+        if !summary.is_success() || !run_command(&options) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if options.watch {
+        watch(|| source_files(&path), || build(&path, &options), &options);
+    }
+
+    let summary = build(&path, &options);
+    if !summary.is_success() || !run_command(&options) {
+        std::process::exit(1);
+    }
+}
+
+/// Reads the options in `args` into `options`, returning the path to
+/// compile, if any. Exits on invalid options.
+fn parse_args(mut args: impl Iterator<Item = String>, options: &mut Options) -> Option<PathBuf> {
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--jobs" | "-j" => {
+                options.jobs = match args.next().map(|n| n.parse()) {
+                    Some(Ok(n)) if n > 0 => n,
+                    _ => {
+                        eprintln!("ERROR: {} expects a positive number", arg);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            "--run" => {
+                options.run = match args.next() {
+                    Some(command) => Some(command),
+                    None => {
+                        eprintln!("ERROR: {} expects a command", arg);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            "--extern" => match args.next() {
+                Some(path) => options.externs.push(PathBuf::from(path)),
+                None => {
+                    eprintln!("ERROR: {} expects an interface file or a directory", arg);
+                    std::process::exit(2);
+                }
+            },
+            "--link" => match args.next() {
+                Some(path) => options.link.push(PathBuf::from(path)),
+                None => {
+                    eprintln!("ERROR: {} expects an output file", arg);
+                    std::process::exit(2);
+                }
+            },
+            "--emit" => match args.next().as_deref() {
+                Some("interface") => options.emit.push(Emit::Interface),
+                Some("callgraph") => options.emit.push(Emit::CallGraph),
+                Some("deps") => options.emit.push(Emit::Deps),
+                Some("symbols") => options.emit.push(Emit::Symbols),
+                _ => {
+                    eprintln!(
+                        "ERROR: {} expects one of: interface, callgraph, deps, symbols",
+                        arg
+                    );
+                    std::process::exit(2);
+                }
+            },
+            "--dialect=standard" => options.dialect = Dialect::Standard,
+            "--dialect=extended" => options.dialect = Dialect::Extended,
+            "--precedence=jack" => options.precedence = Precedence::Jack,
+            "--precedence=conventional" => options.precedence = Precedence::Conventional,
+            "--message-format=human" => options.message_format = MessageFormat::Human,
+            "--message-format=json" => options.message_format = MessageFormat::Json,
+            "--message-format=sarif" => options.message_format = MessageFormat::Sarif,
+            "--explain" => {
+                let code = args.next().unwrap_or_default();
+                match Code::parse(&code) {
+                    Some(code) => {
+                        print!("{}", code.explanation());
+                        std::process::exit(0);
+                    }
+                    None => {
+                        eprintln!("ERROR: {:?} is not a valid error code", code);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            "--all-or-nothing" => options.all_or_nothing = true,
+            "--watch" => options.watch = true,
+            "--dry-run" => options.dry_run = true,
+            "--unsafe-fixes" => options.unsafe_fixes = true,
+            "--verbose" => options.verbose = true,
+            "--size-report" => options.size_report = true,
+            "--stack-report" => options.stack_report = true,
+            flag if flag.starts_with("--") => {
+                eprintln!("ERROR: unknown option {}", flag);
+                std::process::exit(2);
+            }
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+    path
+}
+
+/// Polls the modification times of `sources` and calls `build` whenever one
+/// of them changes.
+fn watch(sources: impl Fn() -> Vec<PathBuf>, build: impl Fn() -> Summary, options: &Options) -> ! {
+    let mut watcher = Watcher::default();
+    loop {
+        if let Some(summary) = watcher.poll(sources(), &build) {
+            println!("[watch] {}", summary);
+            if summary.is_success() {
+                run_command(options);
+            }
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// The modification times of the sources of a watched build when it last
+/// ran.
+#[derive(Default)]
+struct Watcher {
+    last_seen: Option<Vec<(PathBuf, Option<SystemTime>)>>,
+}
+
+impl Watcher {
+    /// Runs `build` on the first poll and whenever `sources` were added,
+    /// removed or modified since the previous one, returning its summary.
+    /// The build cache makes sure only the changed classes, and those
+    /// calling into them, are actually recompiled.
+    fn poll(&mut self, sources: Vec<PathBuf>, build: impl FnOnce() -> Summary) -> Option<Summary> {
+        let modified: Vec<_> = sources
+            .into_iter()
+            .map(|file_name| {
+                let modified = fs::metadata(&file_name).and_then(|x| x.modified()).ok();
+                (file_name, modified)
+            })
+            .collect();
+        if self.last_seen.as_ref() == Some(&modified) {
+            return None;
+        }
+        self.last_seen = Some(modified);
+        Some(build())
+    }
+}
+
+/// Runs the `--run` command, if any, returning whether it succeeded.
+fn run_command(options: &Options) -> bool {
+    let Some(command) = &options.run else {
+        return true;
+    };
+    let status = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).status()
     } else {
-        compile(path);
+        Command::new("sh").args(["-c", command]).status()
+    };
+    match status {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            eprintln!("ERROR: `{}` exited with {}", command, status);
+            false
+        }
+        Err(err) => {
+            eprintln!("ERROR: could not run `{}`: {}", command, err);
+            false
+        }
     }
 }
 
-fn compile(file_name: PathBuf) {
-    let file_path = file_name.to_str().expect("Expected to_str() successfully");
-    let file_name = file_name
-        .file_name()
-        .and_then(|x| x.to_str())
-        .expect("Expected file_name() successfully")
-        .strip_suffix(".jack")
-        .expect("Expected strip successfully");
+/// Returns `path` itself, or the `.jack` files directly inside it sorted by
+/// name when `path` is a directory.
+fn source_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let mut file_names: Vec<PathBuf> = path
+        .read_dir()
+        .expect("Expected to read_dir() successfully")
+        .filter_map(|x| x.ok())
+        .filter(|x| {
+            x.path().is_file() && x.path().extension().and_then(|x| x.to_str()) == Some("jack")
+        })
+        .map(|x| x.path())
+        .collect();
+    file_names.sort();
+    file_names
+}
+
+/// Returns the directory holding the classes of the program `path` is part
+/// of.
+fn source_dir(path: &Path) -> &Path {
+    match path.parent() {
+        _ if path.is_dir() => path,
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Returns the classes a program can use: the OS, the libraries given with
+/// `--extern`, the compiled classes in `libraries` and the classes compiled
+/// from `file_names`. Returns `None` when an interface file cannot be read.
+fn program_declarations(
+    libraries: &[&Path],
+    file_names: &[PathBuf],
+    options: &Options,
+) -> Option<Declarations> {
+    let mut declarations = os_api::declarations();
+    let mut success = true;
+    for path in &options.externs {
+        success &= if path.is_dir() {
+            add_library(&mut declarations, path)
+        } else {
+            add_interface_file(&mut declarations, path)
+        };
+    }
+    for library in libraries {
+        success &= add_library(&mut declarations, library);
+    }
+
+    for path in file_names {
+        // the runtime class written next to the program would replace it
+        if options.dialect == Dialect::Extended && class_name(path) == runtime::CLASS {
+            eprintln!(
+                "ERROR: {}: class `{}` is reserved for the operators of the extended dialect",
+                path.display(),
+                runtime::CLASS
+            );
+            success = false;
+            continue;
+        }
+        let interface = fs::read_to_string(path)
+            .ok()
+            .and_then(|source| ClassInterface::scan(&source, options.dialect));
+        match interface {
+            Some(interface) => {
+                if let Err(err) = declarations.add(interface) {
+                    eprintln!("ERROR: {}: {}", path.display(), err);
+                    success = false;
+                }
+            }
+            None => declarations.declare(&class_name(path)),
+        }
+    }
+    success.then_some(declarations)
+}
+
+/// Adds the compiled classes in `dir`: those with a `.jacki` interface file
+/// are checked, those with only a `.vm` are merely known to exist.
+fn add_library(declarations: &mut Declarations, dir: &Path) -> bool {
+    let mut paths: Vec<PathBuf> = match dir.read_dir() {
+        Ok(entries) => entries.filter_map(|x| x.ok()).map(|x| x.path()).collect(),
+        Err(err) => {
+            eprintln!("ERROR: {}: {}", dir.display(), err);
+            return false;
+        }
+    };
+    // interfaces come before the `.vm` they describe
+    paths.sort();
+    let mut success = true;
+    for path in paths {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("jacki") => success &= add_interface_file(declarations, &path),
+            Some("vm") => declarations.declare(&class_name(&path)),
+            _ => {}
+        }
+    }
+    success
+}
+
+fn add_interface_file(declarations: &mut Declarations, path: &Path) -> bool {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("ERROR: {}: {}", path.display(), err);
+            return false;
+        }
+    };
+    match interface::parse(&text) {
+        Ok(classes) => classes.into_iter().all(|x| match declarations.add(x) {
+            Ok(()) => true,
+            Err(err) => {
+                eprintln!("ERROR: {}: {}", path.display(), err);
+                false
+            }
+        }),
+        Err(diagnostic) => {
+            let path = path.display().to_string();
+            eprint!(
+                "{}",
+                render_human(&diagnostic, &SourceFile::new(&path, &text))
+            );
+            false
+        }
+    }
+}
+
+/// Returns the interface of the class compiled from `file_name` to write
+/// next to its `.vm`, if `--emit interface` was given.
+fn emitted_interface(
+    file_name: &Path,
+    declarations: &Declarations,
+    options: &Options,
+) -> Option<String> {
+    if !options.emit.contains(&Emit::Interface) {
+        return None;
+    }
+    declarations
+        .get(&class_name(file_name))
+        .map(ClassInterface::to_string)
+}
+
+/// Compiles `path`, a `.jack` file or a directory of them, and writes the
+/// outputs of every class that compiled next to its source.
+fn build(path: &Path, options: &Options) -> Summary {
+    let source_dir = source_dir(path);
+    // classes only available compiled are checked against their interface
+    let declarations = program_declarations(&[source_dir], &source_files(source_dir), options);
+    let Some(declarations) = declarations else {
+        return Summary::unbuilt(source_files(path).len());
+    };
+    let cache = BuildCache::open(
+        source_dir,
+        &[options.dialect.as_str(), options.precedence.as_str()],
+    );
+    let file_names = source_files(path);
+    let mut summary = compile_program(&file_names, None, &declarations, &cache, options);
+    if summary.is_success() && !process_program(source_dir, &file_names, &declarations, options) {
+        summary.failed += 1;
+    }
+    summary
+}
+
+/// Compiles `file_names` and writes the outputs of every class that
+/// compiled to `output_dir`, or next to their source.
+fn compile_program(
+    file_names: &[PathBuf],
+    output_dir: Option<&Path>,
+    declarations: &Declarations,
+    cache: &BuildCache,
+    options: &Options,
+) -> Summary {
+    let mut summary = Summary::default();
+    let results = compile_all(file_names, output_dir, declarations, cache, options);
+
+    let mut outputs = Vec::new();
+    let mut reports = Vec::new();
+    for (file_name, result) in file_names.iter().zip(results) {
+        let report = match &result {
+            Outcome::Compiled(_, report) | Outcome::Failed(report) | Outcome::Fresh(report) => {
+                Some(report)
+            }
+            Outcome::Unreadable(_) => None,
+        };
+        if let Some(report) = report {
+            for diagnostic in &report.diagnostics {
+                match diagnostic.severity {
+                    Severity::Error => summary.errors += 1,
+                    Severity::Warning => summary.warnings += 1,
+                }
+            }
+            let path = file_name.display().to_string();
+            let file = SourceFile::new(&path, &report.source);
+            for diagnostic in &report.diagnostics {
+                match options.message_format {
+                    MessageFormat::Human => eprint!("{}", render_human(diagnostic, &file)),
+                    MessageFormat::Json => println!("{}", render_json(diagnostic, &file)),
+                    MessageFormat::Sarif => {}
+                }
+            }
+        }
+
+        match result {
+            Outcome::Fresh(report) => {
+                reports.push((file_name, report));
+                if options.verbose {
+                    println!("cache hit: {}", file_name.display());
+                }
+                summary.fresh += 1;
+                // the interface may not have been asked for last time
+                if let Some(interface) = emitted_interface(file_name, declarations, options) {
+                    let path = output_path(file_name, output_dir).with_extension("jacki");
+                    if fs::read_to_string(&path).ok().as_ref() != Some(&interface) {
+                        let written =
+                            StagedOutput::stage(&path, &interface).and_then(StagedOutput::commit);
+                        if let Err(err) = written {
+                            eprintln!("ERROR: {}: {}", path.display(), err);
+                            summary.errors += 1;
+                        }
+                    }
+                }
+            }
+            Outcome::Compiled(mut compiled, report) => {
+                compiled.interface = emitted_interface(file_name, declarations, options);
+                reports.push((file_name, report));
+                if options.verbose {
+                    println!("cache miss: {}", file_name.display());
+                }
+                summary.compiled += 1;
+                if options.all_or_nothing {
+                    outputs.push(compiled);
+                } else if let Err(err) = write_outputs(vec![compiled], cache, declarations) {
+                    eprintln!("ERROR: {}", err);
+                    summary.compiled -= 1;
+                    summary.failed += 1;
+                    summary.errors += 1;
+                }
+            }
+            Outcome::Failed(report) => {
+                reports.push((file_name, report));
+                summary.failed += 1;
+            }
+            Outcome::Unreadable(err) => {
+                eprintln!("ERROR: {}: {}", file_name.display(), err);
+                summary.failed += 1;
+                summary.errors += 1;
+            }
+        }
+    }
+
+    let diagnosed = reports
+        .iter()
+        .any(|(_, report)| !report.diagnostics.is_empty());
+    if options.message_format == MessageFormat::Human && diagnosed {
+        eprintln!("For more information about a diagnostic, try `jack-compiler --explain <code>`.");
+    }
+
+    if options.message_format == MessageFormat::Sarif {
+        let paths: Vec<_> = reports
+            .iter()
+            .map(|(file_name, _)| file_name.display().to_string())
+            .collect();
+        let files: Vec<_> = paths
+            .iter()
+            .zip(&reports)
+            .map(|(path, (_, report))| {
+                (
+                    SourceFile::new(path, &report.source),
+                    &report.diagnostics[..],
+                )
+            })
+            .collect();
+        println!("{}", render_sarif(&files));
+    }
+
+    if options.all_or_nothing && summary.is_success() {
+        if let Err(err) = write_outputs(outputs, cache, declarations) {
+            eprintln!("ERROR: {}", err);
+            summary.failed += summary.compiled;
+            summary.compiled = 0;
+            summary.errors += 1;
+        }
+    } else if options.all_or_nothing {
+        summary.compiled = 0;
+    }
+
+    summary
+}
+
+/// The classes a project compiles and the libraries it links against.
+#[derive(Default)]
+struct Project {
+    /// Sources of the project and of its dependencies available as source.
+    file_names: Vec<PathBuf>,
+    /// Directories of the dependencies only available compiled.
+    libraries: Vec<PathBuf>,
+}
+
+/// Collects the sources and libraries of the project described by
+/// `manifest`. Dependencies with a manifest of their own contribute their
+/// sources and dependencies in turn.
+fn project_sources(manifest: &Manifest) -> Result<Project, String> {
+    let mut project = Project::default();
+    add_project(manifest, &mut project, &mut Vec::new())?;
+
+    let mut classes = HashMap::new();
+    for file_name in &project.file_names {
+        if let Some(other) = classes.insert(class_name(file_name), file_name) {
+            return Err(format!(
+                "class `{}` is defined by both {} and {}",
+                class_name(file_name),
+                other.display(),
+                file_name.display()
+            ));
+        }
+    }
+    Ok(project)
+}
+
+/// Adds the sources and libraries of `manifest` to `project`. `seen` holds
+/// the roots of the manifests being added, to stop on cyclic dependencies.
+fn add_project(
+    manifest: &Manifest,
+    project: &mut Project,
+    seen: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    let root = manifest
+        .root
+        .canonicalize()
+        .unwrap_or(manifest.root.clone());
+    if seen.contains(&root) {
+        return Ok(());
+    }
+    seen.push(root);
+
+    for dir in &manifest.sources {
+        if !dir.is_dir() {
+            return Err(format!("{}: source directory not found", dir.display()));
+        }
+        project.file_names.extend(source_files(dir));
+    }
+    for dependency in &manifest.dependencies {
+        if !dependency.path.is_dir() {
+            return Err(format!(
+                "{}: dependency `{}` not found",
+                dependency.path.display(),
+                dependency.name
+            ));
+        }
+        let manifest_path = dependency.path.join(MANIFEST);
+        if manifest_path.is_file() {
+            add_project(&Manifest::load(&manifest_path)?, project, seen)?;
+        } else if source_files(&dependency.path).is_empty() {
+            project.libraries.push(dependency.path.clone());
+        } else {
+            project.file_names.extend(source_files(&dependency.path));
+        }
+    }
+    Ok(())
+}
+
+/// Compiles the project described by `manifest` into its output directory,
+/// then copies the `.vm` of its compiled libraries and of an external or
+/// bundled OS next to them so the directory holds the whole program.
+fn build_project(manifest: &Manifest, options: &Options) -> Summary {
+    let project = match project_sources(manifest) {
+        Ok(project) => project,
+        Err(err) => {
+            eprintln!("ERROR: {}", err);
+            return Summary::unbuilt(0);
+        }
+    };
+    let unbuilt = Summary::unbuilt(project.file_names.len());
+    let libraries: Vec<&Path> = project.libraries.iter().map(PathBuf::as_path).collect();
+    let Some(declarations) = program_declarations(&libraries, &project.file_names, options) else {
+        return unbuilt;
+    };
+
+    if let Err(err) = check_entry(&declarations, &manifest.entry) {
+        eprintln!("ERROR: {}", err);
+        return unbuilt;
+    }
+    if let Err(err) = fs::create_dir_all(&manifest.output) {
+        eprintln!("ERROR: {}: {}", manifest.output.display(), err);
+        return unbuilt;
+    }
+
+    let cache = BuildCache::open(
+        &manifest.root,
+        &[options.dialect.as_str(), options.precedence.as_str()],
+    );
+    let mut summary = compile_program(
+        &project.file_names,
+        Some(&manifest.output),
+        &declarations,
+        &cache,
+        options,
+    );
+    if !summary.is_success() {
+        return summary;
+    }
+
+    let mut compiled = project.libraries;
+    if let Os::External(dir) = &manifest.os {
+        compiled.push(dir.clone());
+    }
+    // classes of the project take precedence, e.g. over an OS class it
+    // implements itself
+    let classes: Vec<String> = project.file_names.iter().map(|x| class_name(x)).collect();
+    for dir in compiled {
+        if let Err(err) = copy_classes(&dir, &manifest.output, &classes) {
+            eprintln!("ERROR: {}", err);
+            summary.errors += 1;
+            summary.failed += 1;
+        }
+    }
+    if manifest.os == Os::Bundled && !write_os(&manifest.output, &manifest.entry, &classes) {
+        summary.errors += 1;
+        summary.failed += 1;
+    }
+    let output = &manifest.output;
+    if summary.is_success() && !process_program(output, &project.file_names, &declarations, options)
+    {
+        summary.failed += 1;
+    }
+    summary
+}
+
+/// Checks that the class `entry` declares the `main` function starting the
+/// program, a function returning nothing and taking no parameters.
+fn check_entry(declarations: &Declarations, entry: &str) -> Result<(), String> {
+    let main = declarations
+        .get(entry)
+        .and_then(|class| class.subroutine("main"));
+    match main {
+        Some(main)
+            if main.kind == SubroutineKind::Function
+                && main.return_type == "void"
+                && main.parameters.is_empty() =>
+        {
+            Ok(())
+        }
+        Some(main) => Err(format!(
+            "entry class `{entry}` must declare `function void main()`, not `{main}`"
+        )),
+        None => Err(format!(
+            "entry class `{entry}` must declare `function void main()`"
+        )),
+    }
+}
+
+/// Writes the VM code of the bundled OS classes to `dir`, except those of
+/// `excluded` classes.
+fn write_os(dir: &Path, entry: &str, excluded: &[String]) -> bool {
+    let mut success = true;
+    for (class, contents) in os::compile(entry) {
+        if excluded.iter().any(|x| x == class) {
+            continue;
+        }
+        let path = dir.join(format!("{class}.vm"));
+        if let Err(err) = StagedOutput::stage(&path, &contents).and_then(StagedOutput::commit) {
+            eprintln!("ERROR: {}: {}", path.display(), err);
+            success = false;
+        }
+    }
+    success
+}
+
+/// Copies the `.vm` files in `dir` to `output_dir`, except those of
+/// `excluded` classes.
+fn copy_classes(dir: &Path, output_dir: &Path, excluded: &[String]) -> Result<(), String> {
+    let entries = dir
+        .read_dir()
+        .map_err(|err| format!("{}: {}", dir.display(), err))?;
+    for path in entries.filter_map(|x| x.ok()).map(|x| x.path()) {
+        if path.extension().and_then(|x| x.to_str()) != Some("vm")
+            || excluded.contains(&class_name(&path))
+        {
+            continue;
+        }
+        let target = output_dir.join(path.file_name().expect("Expected a file name"));
+        fs::copy(&path, &target).map_err(|err| format!("{}: {}", target.display(), err))?;
+    }
+    Ok(())
+}
+
+/// Runs the steps taking the compiled program in `dir` as a whole, the
+/// graphs, reports and linking, once `file_names` all compiled.
+fn process_program(
+    dir: &Path,
+    file_names: &[PathBuf],
+    declarations: &Declarations,
+    options: &Options,
+) -> bool {
+    if options.dialect == Dialect::Extended && !write_runtime(dir, file_names) {
+        return false;
+    }
+    let program_outputs = [Emit::CallGraph, Emit::Deps, Emit::Symbols];
+    if program_outputs
+        .iter()
+        .any(|emit| options.emit.contains(emit))
+        && !emit_program_outputs(dir, file_names, declarations, options)
+    {
+        return false;
+    }
+    if options.link.is_empty() && !options.size_report && !options.stack_report {
+        return true;
+    }
+    let Some(functions) = read_program(dir, options) else {
+        return false;
+    };
+    if options.stack_report {
+        stack_report(&functions);
+    }
+    (!options.size_report || size_report(&functions, file_names, options))
+        && link(&functions, options)
+}
 
-    let file = fs::read_to_string(&file_path).unwrap_or_else(|err| {
-        eprintln!("ERROR: {}: {}", file_path, err);
-        std::process::exit(2);
+/// Writes the runtime class next to the classes of `file_names` when any of
+/// them calls it.
+fn write_runtime(dir: &Path, file_names: &[PathBuf]) -> bool {
+    let used = file_names.iter().any(|file_name| {
+        fs::read_to_string(output_path(file_name, Some(dir)))
+            .is_ok_and(|code| runtime::is_used_by(&code))
     });
+    if !used {
+        return true;
+    }
+    let path = dir.join(format!("{}.vm", runtime::CLASS));
+    let contents = runtime::compile();
+    if let Err(err) = StagedOutput::stage(&path, &contents).and_then(StagedOutput::commit) {
+        eprintln!("ERROR: {}: {}", path.display(), err);
+        return false;
+    }
+    true
+}
 
-    let tokenizer = Tokenizer::build(file.as_str()).unwrap().filter(|x| {
-        !matches!(
-            x,
-            Token::Whitespace(_) | Token::SingleLineComment(_) | Token::BlockComment(_)
-        )
+/// Writes the outputs describing the program made of `file_names` as a
+/// whole to `dir`, as requested by `--emit`. The classes are compiled again,
+/// as the build cache may have skipped some of them.
+fn emit_program_outputs(
+    dir: &Path,
+    file_names: &[PathBuf],
+    declarations: &Declarations,
+    options: &Options,
+) -> bool {
+    let mut graph = ProgramGraph::new();
+    let mut symbols_text = String::new();
+    let mut symbols_json = Vec::new();
+    for file_name in file_names {
+        let source = match fs::read_to_string(file_name) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("ERROR: {}: {}", file_name.display(), err);
+                return false;
+            }
+        };
+        let compilation = compile(&source, declarations, options);
+        if let Some(interface) = declarations.get(&class_name(file_name)) {
+            graph.add(interface, &compilation);
+        }
+        let path = file_name.to_string_lossy();
+        let file = SourceFile::new(&path, &source);
+        if !symbols_text.is_empty() {
+            symbols_text.push('\n');
+        }
+        symbols_text.push_str(&compilation.symbols.to_text(&file));
+        symbols_json.push(compilation.symbols.to_json(&file));
+    }
+
+    let mut outputs = Vec::new();
+    if options.emit.contains(&Emit::CallGraph) {
+        outputs.push(("callgraph.dot", graph.callgraph_dot()));
+        outputs.push(("callgraph.json", graph.callgraph_json()));
+    }
+    if options.emit.contains(&Emit::Deps) {
+        outputs.push(("deps.dot", graph.deps_dot()));
+        outputs.push(("deps.json", graph.deps_json()));
+    }
+    if options.emit.contains(&Emit::Symbols) {
+        outputs.push(("symbols.txt", symbols_text));
+        outputs.push(("symbols.json", json::array(symbols_json) + "\n"));
+    }
+    for (file_name, contents) in outputs {
+        let path = dir.join(file_name);
+        if let Err(err) = StagedOutput::stage(&path, &contents).and_then(StagedOutput::commit) {
+            eprintln!("ERROR: {}: {}", path.display(), err);
+            return false;
+        }
+    }
+    true
+}
+
+/// Reads the functions of the `.vm` files in `dir`, except the `--link`
+/// targets written there by a previous build.
+fn read_program(dir: &Path, options: &Options) -> Option<Vec<vm::Function>> {
+    let targets: Vec<PathBuf> = options
+        .link
+        .iter()
+        .map(|x| x.canonicalize().unwrap_or_else(|_| x.clone()))
+        .collect();
+
+    let mut paths: Vec<PathBuf> = match dir.read_dir() {
+        Ok(entries) => entries.filter_map(|x| x.ok()).map(|x| x.path()).collect(),
+        Err(err) => {
+            eprintln!("ERROR: {}: {}", dir.display(), err);
+            return None;
+        }
+    };
+    paths.sort();
+    let mut functions = Vec::new();
+    for path in paths {
+        let is_target = path
+            .canonicalize()
+            .is_ok_and(|path| targets.contains(&path));
+        if path.extension().and_then(|x| x.to_str()) != Some("vm") || is_target {
+            continue;
+        }
+        let parsed = fs::read_to_string(&path)
+            .map_err(|err| format!(" {}", err))
+            .and_then(|text| vm::parse(&class_name(&path), &text));
+        match parsed {
+            Ok(parsed) => functions.extend(parsed),
+            Err(err) => {
+                eprintln!("ERROR: {}:{}", path.display(), err);
+                return None;
+            }
+        }
+    }
+    Some(functions)
+}
+
+/// Prints the size of every function and class of the program, its largest
+/// string literals and how much of the Hack ROM it fills. A program too
+/// large for the ROM is an error when linked to assembly, a warning
+/// otherwise.
+fn size_report(functions: &[vm::Function], file_names: &[PathBuf], options: &Options) -> bool {
+    let mut sizes = size::function_sizes(functions);
+    sizes.sort_by_key(|size| Reverse(size.instructions));
+
+    let mut classes: Vec<(&str, usize, usize, usize)> = Vec::new();
+    for size in &sizes {
+        match classes.iter_mut().find(|class| class.0 == size.class) {
+            Some(class) => {
+                class.1 += 1;
+                class.2 += size.commands;
+                class.3 += size.instructions;
+            }
+            None => classes.push((&size.class, 1, size.commands, size.instructions)),
+        }
+    }
+    classes.sort_by_key(|class| Reverse(class.3));
+
+    println!(
+        "{:<32} {:>9} {:>11} {:>12}",
+        "class", "functions", "VM commands", "instructions"
+    );
+    for (class, functions, commands, instructions) in &classes {
+        println!(
+            "{:<32} {:>9} {:>11} {:>12}",
+            class, functions, commands, instructions
+        );
+    }
+    println!();
+    println!(
+        "{:<42} {:>11} {:>12}",
+        "function", "VM commands", "instructions"
+    );
+    for size in &sizes {
+        println!(
+            "{:<42} {:>11} {:>12}{}",
+            size.name,
+            size.commands,
+            size.instructions,
+            if size.reachable { "" } else { "  unreachable" }
+        );
+    }
+
+    let sources: Vec<(&PathBuf, String)> = file_names
+        .iter()
+        .filter_map(|file_name| Some((file_name, fs::read_to_string(file_name).ok()?)))
+        .collect();
+    let mut strings: Vec<(String, size::StringLiteral)> = Vec::new();
+    for (file_name, source) in &sources {
+        let path = file_name.to_string_lossy();
+        let file = SourceFile::new(&path, source);
+        for string in size::string_literals(source, options.dialect) {
+            let position = file.position(string.span.start);
+            strings.push((
+                format!("{}:{}:{}", path, position.line, position.column),
+                string,
+            ));
+        }
+    }
+    strings.sort_by_key(|(_, string)| Reverse(string.characters()));
+    if !strings.is_empty() {
+        println!();
+        println!("largest string literals:");
+        for (location, string) in strings.iter().take(STRING_REPORT_LENGTH) {
+            println!(
+                "  {}: \"{}\" {} characters, {} VM commands, {} instructions",
+                location,
+                string.text,
+                string.characters(),
+                string.commands(),
+                string.instructions()
+            );
+        }
+    }
+
+    let rom = size::rom_size(&sizes);
+    println!();
+    print!(
+        "ROM: {} of {} instructions ({}%)",
+        rom,
+        hack::ROM_SIZE,
+        rom * 100 / hack::ROM_SIZE
+    );
+    if functions.iter().any(|function| function.name == "Sys.init") {
+        println!();
+    } else {
+        println!(", not counting the OS classes the VM emulator supplies");
+    }
+    if rom > hack::ROM_SIZE {
+        let to_asm = options
+            .link
+            .iter()
+            .any(|target| target.extension().and_then(|x| x.to_str()) == Some("asm"));
+        let severity = if to_asm { "ERROR" } else { "WARNING" };
+        eprintln!(
+            "{}: the program takes {} instructions, more than the {} the Hack ROM holds",
+            severity,
+            rom,
+            hack::ROM_SIZE
+        );
+        return !to_asm;
+    }
+    true
+}
+
+/// Prints the stack usage of every function of the program and of the
+/// program as a whole, warning when it may overflow the stack region.
+fn stack_report(functions: &[vm::Function]) {
+    let analysis = stack::analyze(functions);
+    println!(
+        "{:<42} {:>5} {:>5} {:>5}",
+        "function", "depth", "frame", "worst"
+    );
+    for function in &analysis.functions {
+        println!(
+            "{:<42} {:>5} {:>5} {:>5}{}",
+            function.name,
+            function.max_depth,
+            function.frame,
+            function.worst,
+            if function.recursive {
+                "  recursive"
+            } else {
+                ""
+            }
+        );
+    }
+    if !analysis.cycles.is_empty() {
+        println!();
+        println!("recursive cycles, not bounded by the worst-case usage:");
+        for cycle in &analysis.cycles {
+            println!("  {}", cycle.join(", "));
+        }
+    }
+
+    println!();
+    let Some((worst, path)) = analysis.worst_path() else {
+        println!("no entry point, the worst-case usage of the program is unknown");
+        return;
+    };
+    println!(
+        "worst-case stack usage: {} of {} words, through {}",
+        worst,
+        stack::STACK_SIZE,
+        path.join(" -> ")
+    );
+    if worst > stack::STACK_SIZE {
+        eprintln!(
+            "WARNING: the stack may grow {} words past its region and overwrite the heap",
+            worst - stack::STACK_SIZE
+        );
+    }
+}
+
+/// Links `functions`, the whole program, into each of the `--link` targets:
+/// a single `.vm`, or Hack assembly when the target ends in `.asm`. Reports
+/// how much of every class was dropped.
+fn link(functions: &[vm::Function], options: &Options) -> bool {
+    for target in &options.link {
+        let linked = match linker::link(functions.to_vec(), &class_name(target)) {
+            Ok(linked) => linked,
+            Err(err) => {
+                eprintln!("ERROR: {}: {}", target.display(), err);
+                return false;
+            }
+        };
+        let contents = if target.extension().and_then(|x| x.to_str()) == Some("asm") {
+            if !linked.functions.iter().any(|x| x.name == "Sys.init") {
+                eprintln!(
+                    "ERROR: {}: `Sys.init` not found, assembly needs the OS classes as `.vm`",
+                    target.display()
+                );
+                return false;
+            }
+            hack::translate(&linked.functions)
+        } else {
+            linked.functions.iter().map(|x| x.to_string()).collect()
+        };
+        if let Err(err) = StagedOutput::stage(target, &contents).and_then(StagedOutput::commit) {
+            eprintln!("ERROR: {}: {}", target.display(), err);
+            return false;
+        }
+        print_link_report(target, &linked);
+    }
+    true
+}
+
+fn print_link_report(target: &Path, linked: &linker::Linked) {
+    let sizes = linked.classes.values();
+    let functions: usize = sizes.clone().map(|x| x.functions).sum();
+    let kept: usize = sizes.clone().map(|x| x.kept_functions).sum();
+    let commands: usize = sizes.clone().map(|x| x.commands - x.kept_commands).sum();
+    let instructions: usize = sizes.map(|x| x.instructions - x.kept_instructions).sum();
+    println!(
+        "linked {} of {} functions into {}, saving {} VM commands ({} instructions)",
+        kept,
+        functions,
+        target.display(),
+        commands,
+        instructions
+    );
+    for (class, size) in &linked.classes {
+        println!(
+            "  {:<16} {:>4}/{:<4} functions  -{:<6} commands  -{} instructions",
+            class,
+            size.kept_functions,
+            size.functions,
+            size.commands - size.kept_commands,
+            size.instructions - size.kept_instructions
+        );
+    }
+    if !linked.external.is_empty() {
+        println!("  left to the VM emulator: {}", linked.external.join(", "));
+    }
+}
+
+/// Applies the machine-applicable fixes suggested for the classes under
+/// `path`, and those that may change what the program does with
+/// `--unsafe-fixes`, or prints them as a diff with `--dry-run`.
+fn fix(path: &Path, options: &Options) -> bool {
+    let source_dir = source_dir(path);
+    let declarations = program_declarations(&[source_dir], &source_files(source_dir), options);
+    let Some(declarations) = declarations else {
+        return false;
+    };
+    let mut success = true;
+    for file_name in source_files(path) {
+        let source = match fs::read_to_string(&file_name) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("ERROR: {}: {}", file_name.display(), err);
+                success = false;
+                continue;
+            }
+        };
+
+        let mut fixed = source.clone();
+        let mut applied = 0;
+        for _ in 0..MAX_FIX_ROUNDS {
+            let fixes: Vec<_> = compile(&fixed, &declarations, options)
+                .diagnostics
+                .into_iter()
+                .filter_map(|x| x.fix)
+                .filter(|fix| {
+                    options.unsafe_fixes || fix.applicability == Applicability::MachineApplicable
+                })
+                .collect();
+            let (text, n) = fix::apply(&fixed, &fixes);
+            if n == 0 {
+                break;
+            }
+            fixed = text;
+            applied += n;
+        }
+        if applied == 0 {
+            continue;
+        }
+
+        if options.dry_run {
+            let path = file_name.display().to_string();
+            print!("{}", fix::unified_diff(&path, &source, &fixed));
+            continue;
+        }
+        match StagedOutput::stage(&file_name, &fixed).and_then(StagedOutput::commit) {
+            Ok(()) => println!("fixed {} ({} fixes)", file_name.display(), applied),
+            Err(err) => {
+                eprintln!("ERROR: {}: {}", file_name.display(), err);
+                success = false;
+            }
+        }
+    }
+    success
+}
+
+/// Compiles `file_names` on up to `--jobs` threads. Each class gets its own
+/// `CompilationEngine`, so classes are independent of one another; results
+/// are returned in the order of `file_names` so diagnostics stay
+/// deterministic.
+fn compile_all(
+    file_names: &[PathBuf],
+    output_dir: Option<&Path>,
+    declarations: &Declarations,
+    cache: &BuildCache,
+    options: &Options,
+) -> Vec<Outcome> {
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Outcome)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.jobs.clamp(1, file_names.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(file_name) = file_names.get(i) else {
+                            break results;
+                        };
+                        let outcome =
+                            compile_file(file_name, output_dir, declarations, cache, options);
+                        results.push((i, outcome));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Expected worker not to panic"))
+            .collect()
     });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, outcome)| outcome).collect()
+}
 
-    let mut compilation_engine =
-        CompilationEngine::build(tokenizer, PathBuf::from(format!("{file_name}.vm")))
-            .unwrap_or_else(|err| {
-                eprintln!("ERROR: {}: {}", file_path, err);
-                std::process::exit(2);
-            });
+fn compile_file(
+    file_name: &Path,
+    output_dir: Option<&Path>,
+    declarations: &Declarations,
+    cache: &BuildCache,
+    options: &Options,
+) -> Outcome {
+    let source = match fs::read_to_string(file_name) {
+        Ok(source) => source,
+        Err(err) => return Outcome::Unreadable(err),
+    };
+
+    let key = cache.key(&source);
+    let output_path = output_path(file_name, output_dir);
+    if let Some(warnings) = cache.lookup(&class_name(file_name), key, &output_path, declarations) {
+        return Outcome::Fresh(Report {
+            source,
+            diagnostics: warnings,
+        });
+    }
 
+    let compilation = compile(&source, declarations, options);
+    let report = Report {
+        source,
+        diagnostics: compilation.diagnostics,
+    };
+    match compilation.output {
+        Some(output) => Outcome::Compiled(
+            Compiled {
+                file_name: file_name.to_path_buf(),
+                output_path,
+                key,
+                output,
+                dependencies: compilation.dependencies,
+                warnings: report.diagnostics.clone(),
+                interface: None,
+            },
+            report,
+        ),
+        None => Outcome::Failed(report),
+    }
+}
+
+fn compile(source: &str, declarations: &Declarations, options: &Options) -> Compilation {
+    let tokenizer = Tokenizer::build(source)
+        .unwrap()
+        .with_dialect(options.dialect)
+        .spanned()
+        .filter(|(_, x)| {
+            !matches!(
+                x,
+                Token::Whitespace(_) | Token::SingleLineComment(_) | Token::BlockComment(_)
+            )
+        });
+
+    let mut compilation_engine = CompilationEngine::new(source, tokenizer, declarations)
+        .with_dialect(options.dialect)
+        .with_precedence(options.precedence);
     compilation_engine.compile_class();
+    compilation_engine.finish()
+}
+
+/// Stages every output before renaming any of them into place, so a failure
+/// to write one file leaves all previous outputs intact. Should a rename fail,
+/// the outputs already renamed are rolled back to their previous contents.
+fn write_outputs(
+    outputs: Vec<Compiled>,
+    cache: &BuildCache,
+    declarations: &Declarations,
+) -> Result<(), String> {
+    let mut staged = Vec::new();
+    for compiled in &outputs {
+        let mut files = vec![(compiled.output_path.clone(), &compiled.output)];
+        if let Some(interface) = &compiled.interface {
+            files.push((compiled.output_path.with_extension("jacki"), interface));
+        }
+        for (path, contents) in files {
+            match StagedOutput::stage(&path, contents) {
+                Ok(output) => staged.push(output),
+                Err(err) => {
+                    staged.into_iter().for_each(StagedOutput::discard);
+                    return Err(format!("{}: {}", path.display(), err));
+                }
+            }
+        }
+    }
+
+    if let Err((path, err)) = output::commit_all(staged) {
+        return Err(format!("{}: {}", path.display(), err));
+    }
+
+    for compiled in outputs {
+        let class_name = class_name(&compiled.file_name);
+        let stored = cache.store(
+            &class_name,
+            compiled.key,
+            &compiled.output,
+            &compiled.dependencies,
+            &compiled.warnings,
+            declarations,
+        );
+        if let Err(err) = stored {
+            eprintln!("WARNING: could not update build cache: {}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the path of the `.vm` compiled from `file_name`, in `output_dir`
+/// or next to the source.
+fn output_path(file_name: &Path, output_dir: Option<&Path>) -> PathBuf {
+    match output_dir {
+        Some(output_dir) => output_dir.join(format!("{}.vm", class_name(file_name))),
+        None => file_name.with_extension("vm"),
+    }
+}
+
+fn class_name(file_name: &Path) -> String {
+    file_name
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .expect("Expected file_stem() successfully")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jack-main-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn main_class(statement: &str) -> String {
+        format!(
+            "class Main {{\n    function void main() {{\n        var int x;\n        {statement}\n        return;\n    }}\n}}\n"
+        )
+    }
+
+    fn options(dialect: Dialect) -> Options {
+        Options {
+            dialect,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn writes_the_runtime_for_extended_builds_using_it() {
+        let dir = temp_dir("runtime");
+        let runtime = dir.join("JackRuntime.vm");
+        fs::write(dir.join("Main.jack"), main_class("let x = 7;")).unwrap();
+        assert!(build(&dir, &options(Dialect::Standard)).is_success());
+        assert!(build(&dir, &options(Dialect::Extended)).is_success());
+        assert!(!runtime.exists());
+
+        fs::write(dir.join("Main.jack"), main_class("let x = 7 % 2;")).unwrap();
+        assert!(!build(&dir, &options(Dialect::Standard)).is_success());
+        assert!(!runtime.exists());
+        assert!(build(&dir, &options(Dialect::Extended)).is_success());
+        assert_eq!(fs::read_to_string(&runtime).unwrap(), runtime::compile());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reserves_the_runtime_class_name() {
+        let dir = temp_dir("reserved");
+        let source = main_class("let x = 7;").replace("Main", "JackRuntime");
+        fs::write(dir.join("JackRuntime.jack"), source).unwrap();
+        assert!(!build(&dir, &options(Dialect::Extended)).is_success());
+        assert!(!dir.join("JackRuntime.vm").exists());
+        assert!(build(&dir, &options(Dialect::Standard)).is_success());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Writes `source` to `path` with a modification time `seconds` after
+    /// the epoch, as writes within a poll can share one.
+    fn write_at(path: &Path, source: &str, seconds: u64) {
+        fs::write(path, source).unwrap();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn rebuilds_when_sources_change() {
+        let dir = temp_dir("watch");
+        let main = dir.join("Main.jack");
+        write_at(&main, &main_class("let x = Point.new();"), 1);
+        let point = dir.join("Point.jack");
+        let point_class =
+            "class Point {\n    constructor Point new() {\n        return this;\n    }\n}\n";
+        write_at(&point, point_class, 1);
+        let options = Options::default();
+        let mut watcher = Watcher::default();
+        let mut poll = || watcher.poll(source_files(&dir), || build(&dir, &options));
+
+        let summary = poll().unwrap();
+        assert_eq!((summary.compiled, summary.fresh), (2, 0));
+        assert!(poll().is_none());
+
+        // only the modified class is compiled again
+        let body = "do Output.printInt(1);\n        return this;";
+        write_at(&point, &point_class.replace("return this;", body), 2);
+        let summary = poll().unwrap();
+        assert_eq!((summary.compiled, summary.fresh), (1, 1));
+        assert!(poll().is_none());
+
+        // so are the classes calling one whose interface changed
+        write_at(&point, &point_class.replace("new()", "new(int x)"), 4);
+        let summary = poll().unwrap();
+        assert_eq!((summary.compiled, summary.failed), (1, 1));
+        assert_eq!(summary.errors, 1);
+
+        // as well as when a class is removed, leaving only its `.vm` whose
+        // calls are not checked, or added
+        fs::remove_file(&point).unwrap();
+        let summary = poll().unwrap();
+        assert_eq!((summary.compiled, summary.failed), (1, 0));
+        write_at(&point, point_class, 5);
+        let summary = poll().unwrap();
+        assert_eq!((summary.compiled, summary.failed), (2, 0));
+        assert!(poll().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn checks_the_entry_signature() {
+        let mut declarations = os_api::declarations();
+        for source in [
+            "class Main { function void main(); }",
+            "class Game { method void main(); }",
+            "class Args { function void main(int argc); }",
+            "class Code { function int main(); }",
+            "class Empty { function void run(); }",
+        ] {
+            declarations
+                .add(interface::parse(source).unwrap().remove(0))
+                .unwrap();
+        }
+        assert_eq!(check_entry(&declarations, "Main"), Ok(()));
+        assert_eq!(
+            check_entry(&declarations, "Game").unwrap_err(),
+            "entry class `Game` must declare `function void main()`, not `method void main()`"
+        );
+        assert!(check_entry(&declarations, "Args")
+            .unwrap_err()
+            .ends_with("not `function void main(int argc)`"));
+        assert!(check_entry(&declarations, "Code")
+            .unwrap_err()
+            .ends_with("not `function int main()`"));
+        for entry in ["Empty", "Missing"] {
+            assert_eq!(
+                check_entry(&declarations, entry).unwrap_err(),
+                format!("entry class `{entry}` must declare `function void main()`")
+            );
+        }
+    }
 }
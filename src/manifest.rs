@@ -0,0 +1,576 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the manifest file at the root of a project.
+pub const MANIFEST: &str = "jack.toml";
+
+/// A project, as described by its `jack.toml`:
+///
+/// ```toml
+/// [package]
+/// name = "pong"
+/// entry = "Main"
+/// sources = ["src"]
+/// output = "build"
+/// os = "bundled"
+///
+/// [dependencies]
+/// sprites = { path = "../sprites" }
+///
+/// [build]
+/// flags = ["--all-or-nothing"]
+/// ```
+///
+/// Paths are relative to the directory of the manifest.
+#[derive(Debug)]
+pub struct Manifest {
+    /// Directory of the manifest.
+    pub root: PathBuf,
+    pub name: String,
+    /// Class whose `main` function starts the program.
+    pub entry: String,
+    /// Directories holding the `.jack` sources of the project.
+    pub sources: Vec<PathBuf>,
+    /// Directory all `.vm` files of the program are written to.
+    pub output: PathBuf,
+    pub os: Os,
+    pub dependencies: Vec<Dependency>,
+    /// Flags passed to the compiler before those of the command line.
+    pub flags: Vec<String>,
+}
+
+/// Where the implementation of the OS classes comes from.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Os {
+    /// Provided by the emulator running the program, as the nand2tetris VM
+    /// emulator does when no `.vm` file implements an OS class.
+    Builtins,
+    /// The OS implementation shipped with the compiler, compiled into the
+    /// output.
+    Bundled,
+    /// A directory of OS `.vm` files copied into the output.
+    External(PathBuf),
+}
+
+/// A local library, either sources compiled along with the project or `.vm`
+/// files with their `.jacki` interfaces.
+#[derive(Debug)]
+pub struct Dependency {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Manifest, String> {
+        let text =
+            fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let root = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        Manifest::parse(&text, root).map_err(|err| format!("{}:{}", path.display(), err))
+    }
+
+    /// Parses the manifest `text`, resolving paths against `root`. Errors are
+    /// prefixed with the line they occur on.
+    pub fn parse(text: &str, root: &Path) -> Result<Manifest, String> {
+        let mut manifest = Manifest {
+            root: root.to_path_buf(),
+            name: String::new(),
+            entry: String::from("Main"),
+            sources: vec![root.join("src")],
+            output: root.join("build"),
+            os: Os::Builtins,
+            dependencies: Vec::new(),
+            flags: Vec::new(),
+        };
+
+        for entry in parse_toml(text)? {
+            let line = entry.line;
+            let error = |message: String| format!("{line}: {message}");
+            match (entry.table.as_str(), entry.key.as_str()) {
+                ("package", "name") => manifest.name = entry.value.into_string().map_err(error)?,
+                ("package", "entry") => {
+                    manifest.entry = entry.value.into_string().map_err(error)?
+                }
+                ("package", "sources") => {
+                    manifest.sources = entry
+                        .value
+                        .into_strings()
+                        .map_err(error)?
+                        .into_iter()
+                        .map(|x| root.join(x))
+                        .collect()
+                }
+                ("package", "output") => {
+                    manifest.output = root.join(entry.value.into_string().map_err(error)?)
+                }
+                ("package", "os") => {
+                    manifest.os = match entry.value {
+                        Value::String(os) if os == "builtins" => Os::Builtins,
+                        Value::String(os) if os == "bundled" => Os::Bundled,
+                        value => Os::External(root.join(value.into_path().map_err(error)?)),
+                    }
+                }
+                ("dependencies", name) => manifest.dependencies.push(Dependency {
+                    name: name.to_string(),
+                    path: root.join(entry.value.into_path().map_err(error)?),
+                }),
+                ("build", "flags") => manifest.flags = entry.value.into_strings().map_err(error)?,
+                (table, key) => {
+                    let key = if table.is_empty() {
+                        key.to_string()
+                    } else {
+                        format!("{table}.{key}")
+                    };
+                    return Err(error(format!("unknown key `{key}`")));
+                }
+            }
+        }
+
+        if manifest.name.is_empty() {
+            return Err(String::from("1: missing `package.name`"));
+        }
+        Ok(manifest)
+    }
+}
+
+/// A value of the subset of TOML manifests are written in.
+#[derive(Debug, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Array(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+
+    fn into_string(self) -> Result<String, String> {
+        match self {
+            Value::String(string) => Ok(string),
+            value => Err(format!("expected a string, found {}", value.describe())),
+        }
+    }
+
+    fn into_strings(self) -> Result<Vec<String>, String> {
+        match self {
+            Value::Array(values) => values.into_iter().map(Value::into_string).collect(),
+            value => Err(format!(
+                "expected an array of strings, found {}",
+                value.describe()
+            )),
+        }
+    }
+
+    /// A path, written as `{ path = "..." }`.
+    fn into_path(self) -> Result<String, String> {
+        match self {
+            Value::Table(entries) => {
+                let mut path = None;
+                for (key, value) in entries {
+                    match key.as_str() {
+                        "path" => path = Some(value.into_string()?),
+                        key => return Err(format!("unknown key `{key}`")),
+                    }
+                }
+                path.ok_or_else(|| String::from("missing `path`"))
+            }
+            value => Err(format!(
+                "expected {{ path = \"...\" }}, found {}",
+                value.describe()
+            )),
+        }
+    }
+}
+
+/// A `key = value` pair of a TOML document.
+struct Entry {
+    /// Header of the table the pair is in, empty before the first header.
+    table: String,
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+/// Parses the subset of TOML manifests need: tables, bare or quoted keys,
+/// strings, arrays and inline tables. Keys may only be given once.
+fn parse_toml(text: &str) -> Result<Vec<Entry>, String> {
+    let mut parser = TomlParser {
+        chars: text.chars().collect(),
+        position: 0,
+        line: 1,
+    };
+    let mut table = String::new();
+    let mut entries = Vec::new();
+    loop {
+        parser.skip_whitespace(true);
+        match parser.peek() {
+            None => return Ok(entries),
+            Some('[') => {
+                parser.position += 1;
+                parser.skip_whitespace(false);
+                table = parser.key()?;
+                parser.skip_whitespace(false);
+                parser.expect(']')?;
+            }
+            Some(_) => {
+                let line = parser.line;
+                let key = parser.key()?;
+                if entries
+                    .iter()
+                    .any(|entry: &Entry| entry.table == table && entry.key == key)
+                {
+                    return Err(parser.error(format!("duplicate key `{key}`")));
+                }
+                parser.skip_whitespace(false);
+                parser.expect('=')?;
+                parser.skip_whitespace(false);
+                let value = parser.value()?;
+                entries.push(Entry {
+                    table: table.clone(),
+                    key,
+                    value,
+                    line,
+                });
+            }
+        }
+        parser.skip_whitespace(false);
+        match parser.peek() {
+            None | Some('\n') => {}
+            Some(c) => return Err(parser.error(format!("expected a new line, found `{c}`"))),
+        }
+    }
+}
+
+struct TomlParser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl TomlParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: String) -> String {
+        format!("{}: {}", self.line, message)
+    }
+
+    /// Skips spaces and comments, and new lines too when `newlines`.
+    fn skip_whitespace(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => {}
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.next();
+                    }
+                    continue;
+                }
+                _ => break,
+            }
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected `{expected}`, found `{c}`"))),
+            None => Err(self.error(format!("expected `{expected}`, found end of file"))),
+        }
+    }
+
+    /// Parses a bare or quoted key, or a dotted table name.
+    fn key(&mut self) -> Result<String, String> {
+        if self.peek() == Some('"') {
+            return self.string();
+        }
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        {
+            self.next();
+        }
+        if start == self.position {
+            return Err(self.error(String::from("expected a key")));
+        }
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.next();
+                let mut values = Vec::new();
+                loop {
+                    self.skip_whitespace(true);
+                    if self.peek() == Some(']') {
+                        self.next();
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_whitespace(true);
+                    match self.peek() {
+                        Some(',') => {
+                            self.next();
+                        }
+                        Some(']') => {}
+                        _ => return Err(self.error(String::from("expected `,` or `]`"))),
+                    }
+                }
+            }
+            Some('{') => {
+                self.next();
+                let mut entries = Vec::new();
+                loop {
+                    self.skip_whitespace(false);
+                    if self.peek() == Some('}') {
+                        self.next();
+                        return Ok(Value::Table(entries));
+                    }
+                    let key = self.key()?;
+                    if entries.iter().any(|(other, _)| *other == key) {
+                        return Err(self.error(format!("duplicate key `{key}`")));
+                    }
+                    self.skip_whitespace(false);
+                    self.expect('=')?;
+                    self.skip_whitespace(false);
+                    entries.push((key, self.value()?));
+                    self.skip_whitespace(false);
+                    match self.peek() {
+                        Some(',') => {
+                            self.next();
+                        }
+                        Some('}') => {}
+                        _ => return Err(self.error(String::from("expected `,` or `}`"))),
+                    }
+                }
+            }
+            Some(c) => Err(self.error(format!("expected a value, found `{c}`"))),
+            None => Err(self.error(String::from("expected a value, found end of file"))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some(c) => return Err(self.error(format!("unknown escape `\\{c}`"))),
+                    None => break,
+                },
+                Some('\n') | None => break,
+                Some(c) => string.push(c),
+            }
+        }
+        Err(self.error(String::from("unterminated string")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Manifest, String> {
+        Manifest::parse(text, Path::new("project"))
+    }
+
+    fn values(text: &str) -> Result<Vec<(String, Value)>, String> {
+        let entries = parse_toml(text)?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| (format!("{}.{}", entry.table, entry.key), entry.value))
+            .collect())
+    }
+
+    #[test]
+    fn parses_manifests() {
+        let manifest = parse(
+            "# a game
+[package]
+name = \"pong\"
+entry = \"Game\"
+sources = [\"src\", \"lib\"]
+output = \"out\"
+os = \"bundled\"
+
+[dependencies]
+sprites = { path = \"../sprites\" }
+
+[build]
+flags = [\"--all-or-nothing\"]
+",
+        )
+        .unwrap();
+        assert_eq!(manifest.name, "pong");
+        assert_eq!(manifest.entry, "Game");
+        let root = Path::new("project");
+        assert_eq!(manifest.sources, [root.join("src"), root.join("lib")]);
+        assert_eq!(manifest.output, root.join("out"));
+        assert_eq!(manifest.os, Os::Bundled);
+        assert_eq!(manifest.dependencies[0].name, "sprites");
+        assert_eq!(manifest.dependencies[0].path, root.join("../sprites"));
+        assert_eq!(manifest.flags, ["--all-or-nothing"]);
+    }
+
+    #[test]
+    fn defaults_to_builtins() {
+        let manifest = parse("[package]\nname = \"pong\"\n").unwrap();
+        assert_eq!(manifest.entry, "Main");
+        assert_eq!(manifest.sources, [Path::new("project").join("src")]);
+        assert_eq!(manifest.os, Os::Builtins);
+        let manifest = parse("[package]\nname = \"pong\"\nos = { path = \"os\" }\n").unwrap();
+        assert_eq!(manifest.os, Os::External(Path::new("project").join("os")));
+        assert_eq!(parse("").unwrap_err(), "1: missing `package.name`");
+    }
+
+    #[test]
+    fn reads_strings_and_escapes() {
+        let text = r#"a = "plain"
+"quoted key" = "tab\there"
+b = "\"quoted\" \\ back\nslash"
+c = ""
+"#;
+        assert_eq!(
+            values(text).unwrap(),
+            [
+                (String::from(".a"), Value::String(String::from("plain"))),
+                (
+                    String::from(".quoted key"),
+                    Value::String(String::from("tab\there"))
+                ),
+                (
+                    String::from(".b"),
+                    Value::String(String::from("\"quoted\" \\ back\nslash"))
+                ),
+                (String::from(".c"), Value::String(String::new())),
+            ]
+        );
+        assert_eq!(
+            values("a = \"\\x\"").unwrap_err(),
+            "1: unknown escape `\\x`"
+        );
+        assert_eq!(
+            values("\na = \"open\n").unwrap_err(),
+            "3: unterminated string"
+        );
+    }
+
+    #[test]
+    fn reads_arrays_and_inline_tables() {
+        let text = "[t]
+empty = []
+list = [
+    \"a\",  # first
+    [\"b\"],
+]
+table = { path = \"x\", other = { y = \"z\" } }
+";
+        let string = |x: &str| Value::String(String::from(x));
+        assert_eq!(
+            values(text).unwrap(),
+            [
+                (String::from("t.empty"), Value::Array(Vec::new())),
+                (
+                    String::from("t.list"),
+                    Value::Array(vec![string("a"), Value::Array(vec![string("b")])])
+                ),
+                (
+                    String::from("t.table"),
+                    Value::Table(vec![
+                        (String::from("path"), string("x")),
+                        (
+                            String::from("other"),
+                            Value::Table(vec![(String::from("y"), string("z"))])
+                        ),
+                    ])
+                ),
+            ]
+        );
+        assert_eq!(
+            values("a = [\"b\" \"c\"]").unwrap_err(),
+            "1: expected `,` or `]`"
+        );
+        assert_eq!(
+            values("a = { b = \"c\"").unwrap_err(),
+            "1: expected `,` or `}`"
+        );
+        assert_eq!(
+            values("a = 1").unwrap_err(),
+            "1: expected a value, found `1`"
+        );
+        assert_eq!(
+            values("a = \"b\" c").unwrap_err(),
+            "1: expected a new line, found `c`"
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_keys() {
+        let text = "[package]\nname = \"a\"\n\nname = \"b\"\n";
+        assert_eq!(parse(text).unwrap_err(), "4: duplicate key `name`");
+        // the same key in another table is another key
+        assert!(values("[a]\nname = \"a\"\n[b]\nname = \"b\"\n").is_ok());
+        let text = "[dependencies]\nlib = { path = \"a\", path = \"b\" }\n";
+        assert_eq!(parse(text).unwrap_err(), "2: duplicate key `path`");
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_values() {
+        let text = "[package]\nname = \"pong\"\n\n[package]\ncolor = \"red\"\n";
+        assert_eq!(parse(text).unwrap_err(), "5: unknown key `package.color`");
+        assert_eq!(
+            parse("name = \"pong\"").unwrap_err(),
+            "1: unknown key `name`"
+        );
+        let text = "[package]\nname = \"pong\"\nos = { dir = \"os\" }\n";
+        assert_eq!(parse(text).unwrap_err(), "3: unknown key `dir`");
+        let text = "[package]\nname = [\"pong\"]\n";
+        assert_eq!(
+            parse(text).unwrap_err(),
+            "2: expected a string, found an array"
+        );
+        let text = "[build]\nflags = \"--verbose\"\n";
+        assert_eq!(
+            parse(text).unwrap_err(),
+            "2: expected an array of strings, found a string"
+        );
+        let text = "[dependencies]\nlib = \"../lib\"\n";
+        assert_eq!(
+            parse(text).unwrap_err(),
+            "2: expected { path = \"...\" }, found a string"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{
+    compilation_engine::{Compilation, CompilationEngine},
+    interface::{ClassInterface, Declarations},
+    os_api,
+    tokenizer::{Dialect, Token, Tokenizer},
+};
+
+/// Sources of the OS implementation bundled with the compiler, written to
+/// the output of projects built with `os = "bundled"`.
+pub const SOURCES: &[(&str, &str)] = &[
+    ("Array", include_str!("os/Array.jack")),
+    ("Keyboard", include_str!("os/Keyboard.jack")),
+    ("Math", include_str!("os/Math.jack")),
+    ("Memory", include_str!("os/Memory.jack")),
+    ("Output", include_str!("os/Output.jack")),
+    ("Screen", include_str!("os/Screen.jack")),
+    ("String", include_str!("os/String.jack")),
+    ("Sys", include_str!("os/Sys.jack")),
+];
+
+/// Compiles the OS classes to VM code, along with the name of each class.
+/// `Sys.init` starts the program by calling `<entry>.main`.
+pub fn compile(entry: &str) -> Vec<(&'static str, String)> {
+    let sys = SOURCES
+        .iter()
+        .find(|(class, _)| *class == "Sys")
+        .expect("Expected the OS to include Sys")
+        .1
+        .replace("Main.main", &format!("{entry}.main"));
+
+    let declarations = declarations(entry);
+
+    SOURCES
+        .iter()
+        .map(|&(class, source)| {
+            let source = if class == "Sys" { &sys } else { source };
+            let code = compile_class(source, &declarations)
+                .output
+                .expect("Expected the OS classes to compile");
+            (class, code)
+        })
+        .collect()
+}
+
+/// Declarations of the OS classes and of `entry`, the class `Sys.init`
+/// calls. The OS classes call helpers of each other the interface leaves out.
+fn declarations(entry: &str) -> Declarations {
+    let mut declarations = os_api::declarations();
+    for (_, source) in SOURCES {
+        let interface = ClassInterface::scan(source, Dialect::Standard)
+            .expect("Expected the OS classes to declare their subroutines");
+        declarations
+            .add(interface)
+            .expect("Expected the OS classes to have distinct names");
+    }
+    declarations.declare(entry);
+    declarations
+}
+
+fn compile_class(source: &str, declarations: &Declarations) -> Compilation {
+    let tokenizer = Tokenizer::build(source)
+        .unwrap()
+        .spanned()
+        .filter(|(_, x)| {
+            !matches!(
+                x,
+                Token::Whitespace(_) | Token::SingleLineComment(_) | Token::BlockComment(_)
+            )
+        });
+    let mut compilation_engine = CompilationEngine::new(source, tokenizer, declarations);
+    compilation_engine.compile_class();
+    compilation_engine.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interface::Signature;
+
+    #[test]
+    fn compiles_without_diagnostics() {
+        let declarations = declarations("Main");
+        for (class, source) in SOURCES {
+            let compilation = compile_class(source, &declarations);
+            assert!(
+                compilation.diagnostics.is_empty(),
+                "{class}: {:?}",
+                compilation.diagnostics
+            );
+        }
+    }
+
+    #[test]
+    fn implements_the_os_interface() {
+        let os = os_api::declarations();
+        let classes: Vec<&str> = SOURCES.iter().map(|(class, _)| *class).collect();
+        let mut declared: Vec<&str> = os.class_names().collect();
+        declared.sort();
+        assert_eq!(classes, declared);
+        for (class, source) in SOURCES {
+            let implemented = ClassInterface::scan(source, Dialect::Standard).unwrap();
+            for signature in &os.get(class).unwrap().subroutines {
+                let subroutine = implemented.subroutine(&signature.name);
+                let subroutine = subroutine.unwrap_or_else(|| panic!("{class}.{}", signature.name));
+                assert_eq!(
+                    subroutine.kind, signature.kind,
+                    "{class}.{}",
+                    signature.name
+                );
+                assert_eq!(subroutine.return_type, signature.return_type);
+                let types = |x: &Signature| {
+                    x.parameters
+                        .iter()
+                        .map(|x| x._type.clone())
+                        .collect::<Vec<_>>()
+                };
+                assert_eq!(
+                    types(subroutine),
+                    types(signature),
+                    "{class}.{}",
+                    signature.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn starts_the_entry_class() {
+        let compiled = compile("Game");
+        let sys = compiled.iter().find(|(class, _)| *class == "Sys").unwrap();
+        assert!(sys.1.contains("call Game.main 0\n"));
+        assert!(!sys.1.contains("Main.main"));
+        assert_eq!(compiled.len(), SOURCES.len());
+    }
+}
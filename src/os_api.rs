@@ -0,0 +1,16 @@
+use crate::interface::{self, Declarations};
+
+/// Interface of the Jack operating system, whose classes every program can
+/// use without compiling them.
+pub const INTERFACE: &str = include_str!("os.jacki");
+
+/// Returns the declarations of the OS classes.
+pub fn declarations() -> Declarations {
+    let mut declarations = Declarations::new();
+    for class in interface::parse(INTERFACE).expect("Expected the OS interface to parse") {
+        declarations
+            .add(class)
+            .expect("Expected the OS classes to have distinct names");
+    }
+    declarations
+}
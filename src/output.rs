@@ -0,0 +1,157 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+/// An output written to a temporary file next to its destination, waiting to
+/// be renamed into place. Until `commit` is called any previous output at the
+/// destination is left untouched.
+pub struct StagedOutput {
+    path: PathBuf,
+    temp_path: PathBuf,
+}
+
+impl StagedOutput {
+    pub fn stage(path: &Path, contents: &str) -> Result<StagedOutput, Error> {
+        let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+        temp_name.push(".tmp");
+        let temp_path = path.with_file_name(temp_name);
+
+        if let Err(err) = fs::write(&temp_path, contents) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(err);
+        }
+
+        Ok(StagedOutput {
+            path: path.to_path_buf(),
+            temp_path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn commit(self) -> Result<(), Error> {
+        fs::rename(&self.temp_path, &self.path).inspect_err(|_| {
+            let _ = fs::remove_file(&self.temp_path);
+        })
+    }
+
+    /// Renames the output into place, keeping any previous output as a
+    /// `.bak` backup, and records it in `committed` once renamed.
+    fn replace(&self, committed: &mut Vec<(PathBuf, Option<PathBuf>)>) -> Result<(), Error> {
+        let mut backup_name = self.path.file_name().unwrap_or_default().to_os_string();
+        backup_name.push(".bak");
+        let backup_path = self.path.with_file_name(backup_name);
+        let backup = match fs::rename(&self.path, &backup_path) {
+            Ok(()) => Some(backup_path),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        let renamed = fs::rename(&self.temp_path, &self.path);
+        match (renamed, backup) {
+            (Ok(()), backup) => {
+                committed.push((self.path.clone(), backup));
+                Ok(())
+            }
+            (Err(err), backup) => {
+                if let Some(backup) = backup {
+                    let _ = fs::rename(backup, &self.path);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    pub fn discard(self) {
+        let _ = fs::remove_file(&self.temp_path);
+    }
+}
+
+/// Commits all of `outputs` or none of them: previous outputs are moved
+/// aside while the staged ones are renamed into place, and moved back if
+/// any rename fails. Returns the path that failed along with the error.
+pub fn commit_all(outputs: Vec<StagedOutput>) -> Result<(), (PathBuf, Error)> {
+    // committed outputs, along with the backup of what they replaced
+    let mut committed: Vec<(PathBuf, Option<PathBuf>)> = Vec::new();
+    let mut outputs = outputs.into_iter();
+    while let Some(output) = outputs.next() {
+        if let Err(err) = output.replace(&mut committed) {
+            let path = output.path.clone();
+            output.discard();
+            outputs.for_each(StagedOutput::discard);
+            for (path, backup) in committed.into_iter().rev() {
+                let _ = match backup {
+                    Some(backup) => fs::rename(backup, &path),
+                    None => fs::remove_file(&path),
+                };
+            }
+            return Err((path, err));
+        }
+    }
+    for (_, backup) in committed {
+        if let Some(backup) = backup {
+            let _ = fs::remove_file(backup);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jack-output-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn commit_all_replaces_every_output() {
+        let dir = temp_dir("commit");
+        fs::write(dir.join("A.vm"), "old a").unwrap();
+        let outputs = vec![
+            StagedOutput::stage(&dir.join("A.vm"), "new a").unwrap(),
+            StagedOutput::stage(&dir.join("B.vm"), "new b").unwrap(),
+        ];
+        commit_all(outputs).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("A.vm")).unwrap(), "new a");
+        assert_eq!(fs::read_to_string(dir.join("B.vm")).unwrap(), "new b");
+        assert_eq!(files(&dir), ["A.vm", "B.vm"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn commit_all_rolls_back_on_failure() {
+        let dir = temp_dir("rollback");
+        fs::write(dir.join("A.vm"), "old a").unwrap();
+        fs::write(dir.join("B.vm"), "old b").unwrap();
+        // B.vm cannot be moved aside onto a directory that isn't empty
+        fs::create_dir(dir.join("B.vm.bak")).unwrap();
+        fs::write(dir.join("B.vm.bak").join("x"), "").unwrap();
+        let outputs = vec![
+            StagedOutput::stage(&dir.join("A.vm"), "new a").unwrap(),
+            StagedOutput::stage(&dir.join("B.vm"), "new b").unwrap(),
+            StagedOutput::stage(&dir.join("C.vm"), "new c").unwrap(),
+        ];
+        let (path, _) = commit_all(outputs).unwrap_err();
+        assert_eq!(path, dir.join("B.vm"));
+        assert_eq!(fs::read_to_string(dir.join("A.vm")).unwrap(), "old a");
+        assert_eq!(fs::read_to_string(dir.join("B.vm")).unwrap(), "old b");
+        assert_eq!(files(&dir), ["A.vm", "B.vm", "B.vm.bak"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{
+    compilation_engine::CompilationEngine,
+    os_api,
+    tokenizer::{Token, Tokenizer},
+};
+
+/// Class of the functions the extended dialect compiles `%` to, along with
+/// `<<` by an amount only known at runtime and `>>` by most amounts.
+pub const CLASS: &str = "JackRuntime";
+
+/// Source of the runtime class.
+pub const SOURCE: &str = include_str!("runtime.jack");
+
+/// Compiles the runtime class to VM code.
+pub fn compile() -> String {
+    let declarations = os_api::declarations();
+    let tokenizer = Tokenizer::build(SOURCE)
+        .unwrap()
+        .spanned()
+        .filter(|(_, x)| {
+            !matches!(
+                x,
+                Token::Whitespace(_) | Token::SingleLineComment(_) | Token::BlockComment(_)
+            )
+        });
+    let mut compilation_engine = CompilationEngine::new(SOURCE, tokenizer, &declarations);
+    compilation_engine.compile_class();
+    compilation_engine
+        .finish()
+        .output
+        .expect("Expected the runtime class to compile")
+}
+
+/// Whether the VM code of a class calls into the runtime class.
+pub fn is_used_by(code: &str) -> bool {
+    code.contains(&format!("call {CLASS}."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiles_the_operators() {
+        let code = compile();
+        for function in ["mod 0", "shiftLeft 0", "shiftRight 1"] {
+            assert!(code.contains(&format!("function {CLASS}.{function}\n")));
+        }
+        // the functions do not call each other
+        assert!(!is_used_by(&code));
+    }
+
+    #[test]
+    fn detects_calls_into_the_runtime() {
+        assert!(is_used_by(
+            "push constant 7\npush constant 2\ncall JackRuntime.mod 2\n"
+        ));
+        assert!(!is_used_by("call Math.divide 2\n"));
+        assert!(!is_used_by("function JackRuntime.mod 0\n"));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::{
+    hack, linker,
+    tokenizer::{Dialect, Span, Token, Tokenizer},
+    vm::{Command, Function},
+    vm_writer::Segment,
+};
+
+/// Size of a function of a compiled program.
+pub struct FunctionSize {
+    pub name: String,
+    pub class: String,
+    /// VM commands, the `function` declaration included.
+    pub commands: usize,
+    /// Estimated Hack instructions once translated to assembly.
+    pub instructions: usize,
+    /// Whether the function is reachable from the entry point of the
+    /// program, and so kept by the linker.
+    pub reachable: bool,
+}
+
+/// Measures each of `functions`, the whole program. When the program has no
+/// entry point, e.g. a library, every function counts as reachable.
+pub fn function_sizes(functions: &[Function]) -> Vec<FunctionSize> {
+    let roots = linker::entry_points(functions);
+    let has_entry = functions.iter().any(|function| function.name == roots[0]);
+    let reached = linker::reachable(functions, &roots);
+    functions
+        .iter()
+        .map(|function| FunctionSize {
+            name: function.name.clone(),
+            class: function.class.clone(),
+            commands: function.body.len() + 1,
+            instructions: hack::instruction_count(function),
+            reachable: !has_entry || reached.contains(function.name.as_str()),
+        })
+        .collect()
+}
+
+/// Estimated number of ROM words the linked program takes: the bootstrap
+/// code and the reachable functions.
+pub fn rom_size(sizes: &[FunctionSize]) -> usize {
+    let functions: usize = sizes
+        .iter()
+        .filter(|size| size.reachable)
+        .map(|size| size.instructions)
+        .sum();
+    hack::bootstrap_size() + functions
+}
+
+/// A string constant of a source file. Each one is built at runtime by a
+/// call to `String.new` followed by a `String.appendChar` per character.
+pub struct StringLiteral<'a> {
+    pub span: Span,
+    pub text: &'a str,
+    dialect: Dialect,
+}
+
+impl StringLiteral<'_> {
+    /// The characters of the string, escape sequences of the extended
+    /// dialect counting as one.
+    fn chars(&self) -> Vec<u8> {
+        let mut chars = Vec::new();
+        let mut bytes = self.text.bytes();
+        while let Some(char) = bytes.next() {
+            match char {
+                b'\\' if self.dialect == Dialect::Extended => chars.extend(bytes.next()),
+                char => chars.push(char),
+            }
+        }
+        chars
+    }
+
+    /// Number of characters of the string.
+    pub fn characters(&self) -> usize {
+        self.chars().len()
+    }
+
+    fn code(&self) -> Function {
+        let chars = self.chars();
+        let mut body = vec![
+            Command::Push(Segment::CONSTANT, chars.len()),
+            Command::Call(String::from("String.new"), 1),
+        ];
+        for char in chars {
+            body.push(Command::Push(Segment::CONSTANT, char.into()));
+            body.push(Command::Call(String::from("String.appendChar"), 2));
+        }
+        Function {
+            name: String::new(),
+            class: String::new(),
+            n_locals: 0,
+            body,
+        }
+    }
+
+    /// VM commands building the string.
+    pub fn commands(&self) -> usize {
+        self.code().body.len()
+    }
+
+    /// Estimated Hack instructions building the string.
+    pub fn instructions(&self) -> usize {
+        hack::instruction_count(&self.code())
+    }
+}
+
+/// Returns the string constants of `source`, in order.
+pub fn string_literals(source: &str, dialect: Dialect) -> Vec<StringLiteral<'_>> {
+    let Ok(tokenizer) = Tokenizer::build(source) else {
+        return Vec::new();
+    };
+    tokenizer
+        .with_dialect(dialect)
+        .spanned()
+        .filter_map(|(span, token)| match token {
+            Token::StringConst(text) => Some(StringLiteral {
+                span,
+                text,
+                dialect,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm;
+
+    #[test]
+    fn measures_functions() {
+        let text = "function Main.main 0
+call Main.used 0
+return
+function Main.used 0
+push constant 1
+return
+function Main.unused 0
+return
+";
+        let functions = vm::parse("Main", text).unwrap();
+        let sizes = function_sizes(&functions);
+        let reachable: Vec<bool> = sizes.iter().map(|x| x.reachable).collect();
+        assert_eq!(reachable, [true, true, false]);
+        assert_eq!(sizes[1].commands, 3);
+        assert_eq!(sizes[1].class, "Main");
+        assert_eq!(
+            sizes[1].instructions,
+            hack::instruction_count(&functions[1])
+        );
+        assert_eq!(
+            rom_size(&sizes),
+            hack::bootstrap_size() + sizes[0].instructions + sizes[1].instructions
+        );
+
+        // a library without an entry point is kept whole
+        let library = vm::parse("Lib", "function Lib.f 0\nreturn\n").unwrap();
+        assert!(function_sizes(&library)[0].reachable);
+    }
+
+    #[test]
+    fn measures_string_literals() {
+        let source = r#"class Main {
+    function void main() {
+        do Output.printString("Hi");
+        do Output.printString("a\nb");
+        return;
+    }
+}
+"#;
+        let literals = string_literals(source, Dialect::Standard);
+        let texts: Vec<&str> = literals.iter().map(|x| x.text).collect();
+        assert_eq!(texts, ["Hi", "a\\nb"]);
+        assert_eq!(
+            &source[literals[0].span.start..literals[0].span.end],
+            "\"Hi\""
+        );
+        // String.new and an appendChar per character
+        assert_eq!(literals[0].characters(), 2);
+        assert_eq!(literals[0].commands(), 6);
+        assert!(literals[0].instructions() > literals[0].commands());
+        assert_eq!(literals[1].characters(), 4);
+
+        let literals = string_literals(source, Dialect::Extended);
+        assert_eq!(literals[1].characters(), 3);
+        assert_eq!(literals[1].commands(), 8);
+
+        assert!(string_literals("\"unterminated", Dialect::Standard).is_empty());
+    }
+}
@@ -0,0 +1,442 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    linker,
+    vm::{Command, Function},
+    vm_writer::{Arithmetic, Segment},
+};
+
+/// Words of RAM the Hack stack can grow into, from address 256 up to the
+/// heap at 2048.
+pub const STACK_SIZE: usize = 2048 - 256;
+
+/// Words a `call` saves on the stack: the return address and the `LCL`,
+/// `ARG`, `THIS` and `THAT` pointers of the caller.
+const SAVED_FRAME: usize = 5;
+
+/// Stack usage of a function of a program.
+pub struct FunctionStack {
+    pub name: String,
+    /// Deepest the operand stack of the function gets.
+    pub max_depth: usize,
+    /// Words of the frame of a call to the function: its arguments, the
+    /// saved frame of the caller and its locals.
+    pub frame: usize,
+    /// Worst-case stack usage of a call to the function, its frame and those
+    /// of the functions it calls included. Calls back into a recursive cycle
+    /// the function is part of are not counted.
+    pub worst: usize,
+    /// Function called on the path reaching `worst`.
+    pub deepest_call: Option<String>,
+    pub recursive: bool,
+}
+
+pub struct StackAnalysis {
+    /// The functions of the program, in the order they were given.
+    pub functions: Vec<FunctionStack>,
+    /// Groups of functions calling each other recursively, sorted by name.
+    pub cycles: Vec<Vec<String>>,
+    /// Function the program starts from, if defined.
+    pub entry: Option<String>,
+}
+
+impl StackAnalysis {
+    pub fn get(&self, name: &str) -> Option<&FunctionStack> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// Worst-case stack usage of the whole program along with the call path
+    /// reaching it, from the entry point.
+    pub fn worst_path(&self) -> Option<(usize, Vec<&str>)> {
+        let entry = self.get(self.entry.as_deref()?)?;
+        let mut path = vec![entry.name.as_str()];
+        let mut function = entry;
+        while let Some(next) = function.deepest_call.as_deref().and_then(|x| self.get(x)) {
+            path.push(&next.name);
+            function = next;
+        }
+        Some((entry.worst, path))
+    }
+}
+
+/// Returns the functions each of `functions` calls.
+pub fn call_graph(functions: &[Function]) -> BTreeMap<&str, BTreeSet<&str>> {
+    functions
+        .iter()
+        .map(|function| (function.name.as_str(), function.calls().collect()))
+        .collect()
+}
+
+/// A `call` command along with the depth of the operand stack once its
+/// arguments are popped.
+struct Call<'a> {
+    name: &'a str,
+    n_args: usize,
+    depth: usize,
+}
+
+/// Follows every path through the body of `function`, returning the deepest
+/// the operand stack gets and the calls it makes.
+fn operand_stack(function: &Function) -> (usize, Vec<Call<'_>>) {
+    let labels: HashMap<&str, usize> = function
+        .body
+        .iter()
+        .enumerate()
+        .filter_map(|(i, command)| match command {
+            Command::Label(label) => Some((label.as_str(), i)),
+            _ => None,
+        })
+        .collect();
+    let mut depths: Vec<Option<usize>> = vec![None; function.body.len() + 1];
+    let mut pending = vec![(0, 0)];
+    let mut max_depth = 0;
+    let mut calls = Vec::new();
+    while let Some((pc, depth)) = pending.pop() {
+        // compiled code reaches labels with the same depth whatever the path,
+        // so each command is only followed once
+        if depths[pc].is_some() {
+            continue;
+        }
+        depths[pc] = Some(depth);
+        max_depth = max_depth.max(depth);
+        let Some(command) = function.body.get(pc) else {
+            continue;
+        };
+        let jump = |label: &str| labels.get(label).copied();
+        match command {
+            Command::Push(..) => pending.push((pc + 1, depth + 1)),
+            Command::Pop(..) => pending.push((pc + 1, depth.saturating_sub(1))),
+            Command::Arithmetic(Arithmetic::NEG | Arithmetic::NOT) => pending.push((pc + 1, depth)),
+            Command::Arithmetic(_) => pending.push((pc + 1, depth.saturating_sub(1))),
+            Command::Label(_) => pending.push((pc + 1, depth)),
+            Command::Goto(label) => pending.extend(jump(label).map(|target| (target, depth))),
+            Command::IfGoto(label) => {
+                let depth = depth.saturating_sub(1);
+                pending.extend(jump(label).map(|target| (target, depth)));
+                pending.push((pc + 1, depth));
+            }
+            Command::Call(name, n_args) => {
+                let depth = depth.saturating_sub(*n_args);
+                calls.push(Call {
+                    name,
+                    n_args: *n_args,
+                    depth,
+                });
+                // the return value
+                pending.push((pc + 1, depth + 1));
+            }
+            Command::Return => {}
+        }
+    }
+    (max_depth, calls)
+}
+
+/// Analyzes the stack usage of `functions`, the whole program.
+pub fn analyze(functions: &[Function]) -> StackAnalysis {
+    let graph = call_graph(functions);
+    let cycles = cycles(&graph);
+    let mut cycle_of: HashMap<&str, usize> = HashMap::new();
+    for (i, cycle) in cycles.iter().enumerate() {
+        for name in cycle {
+            cycle_of.insert(name, i);
+        }
+    }
+
+    // functions get as many arguments as they are called with, or as the
+    // highest argument they use otherwise
+    let mut n_args: HashMap<&str, usize> = HashMap::new();
+    let mut bodies = HashMap::new();
+    for function in functions {
+        let (max_depth, calls) = operand_stack(function);
+        for call in &calls {
+            let n = n_args.entry(call.name).or_default();
+            *n = (*n).max(call.n_args);
+        }
+        let used = function
+            .body
+            .iter()
+            .filter_map(|command| match command {
+                Command::Push(Segment::ARGUMENT, i) | Command::Pop(Segment::ARGUMENT, i) => {
+                    Some(i + 1)
+                }
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+        let n = n_args.entry(&function.name).or_default();
+        *n = (*n).max(used);
+        bodies.insert(function.name.as_str(), (function, max_depth, calls));
+    }
+
+    let mut worst: HashMap<&str, (usize, Option<&str>)> = HashMap::new();
+    for function in functions {
+        worst_usage(&function.name, &bodies, &cycle_of, &mut worst);
+    }
+
+    let entry = linker::entry_points(functions)[0];
+    StackAnalysis {
+        functions: functions
+            .iter()
+            .map(|function| {
+                let name = function.name.as_str();
+                let args = n_args[name];
+                let (usage, deepest_call) = worst[name];
+                FunctionStack {
+                    name: function.name.clone(),
+                    max_depth: bodies[name].1,
+                    frame: args + SAVED_FRAME + function.n_locals,
+                    worst: args + usage,
+                    deepest_call: deepest_call.map(String::from),
+                    recursive: cycle_of.contains_key(name),
+                }
+            })
+            .collect(),
+        cycles,
+        entry: bodies.contains_key(entry).then(|| entry.to_string()),
+    }
+}
+
+type Body<'a> = (&'a Function, usize, Vec<Call<'a>>);
+
+/// Computes the worst-case stack usage of a call to `name`, arguments
+/// excluded, along with the function called on the way. Calls within the
+/// recursive cycle of `name` are ignored, which makes the remaining graph
+/// acyclic, and functions outside the program, such as the OS classes the
+/// emulator implements, use no stack.
+fn worst_usage<'a>(
+    name: &'a str,
+    bodies: &HashMap<&'a str, Body<'a>>,
+    cycle_of: &HashMap<&str, usize>,
+    worst: &mut HashMap<&'a str, (usize, Option<&'a str>)>,
+) -> (usize, Option<&'a str>) {
+    if let Some(&usage) = worst.get(name) {
+        return usage;
+    }
+    let Some((function, max_depth, calls)) = bodies.get(name) else {
+        return (0, None);
+    };
+    let mut deepest = (*max_depth, None);
+    for call in calls {
+        let same_cycle = cycle_of
+            .get(name)
+            .is_some_and(|cycle| cycle_of.get(call.name) == Some(cycle));
+        if same_cycle {
+            continue;
+        }
+        let (usage, _) = worst_usage(call.name, bodies, cycle_of, worst);
+        let depth = call.depth + call.n_args + usage;
+        if depth > deepest.0 || deepest.1.is_none() && depth == deepest.0 {
+            deepest = (depth, Some(call.name));
+        }
+    }
+    let usage = (SAVED_FRAME + function.n_locals + deepest.0, deepest.1);
+    worst.insert(name, usage);
+    usage
+}
+
+/// Returns the recursive cycles of `graph`: its strongly connected
+/// components of several functions, and the functions calling themselves.
+fn cycles(graph: &BTreeMap<&str, BTreeSet<&str>>) -> Vec<Vec<String>> {
+    struct Tarjan<'a, 'b> {
+        graph: &'b BTreeMap<&'a str, BTreeSet<&'a str>>,
+        index: HashMap<&'a str, usize>,
+        low: HashMap<&'a str, usize>,
+        stack: Vec<&'a str>,
+        components: Vec<Vec<&'a str>>,
+    }
+
+    impl<'a> Tarjan<'a, '_> {
+        fn visit(&mut self, name: &'a str) {
+            let index = self.index.len();
+            self.index.insert(name, index);
+            self.low.insert(name, index);
+            self.stack.push(name);
+            for &callee in self.graph.get(name).into_iter().flatten() {
+                if !self.graph.contains_key(callee) {
+                    continue;
+                }
+                if !self.index.contains_key(callee) {
+                    self.visit(callee);
+                    let low = self.low[name].min(self.low[callee]);
+                    self.low.insert(name, low);
+                } else if self.stack.contains(&callee) {
+                    let low = self.low[name].min(self.index[callee]);
+                    self.low.insert(name, low);
+                }
+            }
+            if self.low[name] == index {
+                let start = self
+                    .stack
+                    .iter()
+                    .position(|&x| x == name)
+                    .expect("Expected the function on the stack");
+                self.components.push(self.stack.split_off(start));
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        graph,
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: Vec::new(),
+        components: Vec::new(),
+    };
+    for &name in graph.keys() {
+        if !tarjan.index.contains_key(name) {
+            tarjan.visit(name);
+        }
+    }
+
+    let mut cycles: Vec<Vec<String>> = tarjan
+        .components
+        .into_iter()
+        .filter(|component| component.len() > 1 || graph[component[0]].contains(component[0]))
+        .map(|component| {
+            let mut cycle: Vec<String> = component.into_iter().map(String::from).collect();
+            cycle.sort();
+            cycle
+        })
+        .collect();
+    cycles.sort();
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm;
+
+    fn analyze_vm(text: &str) -> StackAnalysis {
+        analyze(&vm::parse("Main", text).unwrap())
+    }
+
+    #[test]
+    fn adds_up_frames_along_the_deepest_call() {
+        let analysis = analyze_vm(
+            "function Main.main 0
+push constant 1
+push constant 2
+call Main.f 2
+pop temp 0
+push constant 0
+call Output.printInt 1
+return
+function Main.f 1
+push argument 0
+push argument 1
+push local 0
+add
+add
+return
+",
+        );
+        let f = analysis.get("Main.f").unwrap();
+        assert_eq!(f.max_depth, 3);
+        // 2 arguments, the saved frame and 1 local
+        assert_eq!(f.frame, 8);
+        assert_eq!(f.worst, 2 + 5 + 1 + 3);
+        assert_eq!(f.deepest_call, None);
+
+        let main = analysis.get("Main.main").unwrap();
+        assert_eq!(main.max_depth, 2);
+        assert_eq!(main.frame, 5);
+        // the arguments pushed for Main.f, then its own usage
+        assert_eq!(main.worst, 5 + 2 + 9);
+        assert_eq!(main.deepest_call.as_deref(), Some("Main.f"));
+        assert!(!main.recursive);
+        assert_eq!(analysis.entry.as_deref(), Some("Main.main"));
+        assert_eq!(
+            analysis.worst_path(),
+            Some((16, vec!["Main.main", "Main.f"]))
+        );
+        assert!(analysis.cycles.is_empty());
+    }
+
+    #[test]
+    fn follows_every_branch() {
+        let analysis = analyze_vm(
+            "function Main.main 0
+push constant 1
+if-goto DEEP
+push constant 1
+goto END
+label DEEP
+push constant 1
+push constant 2
+push constant 3
+add
+add
+label END
+return
+",
+        );
+        assert_eq!(analysis.get("Main.main").unwrap().max_depth, 3);
+    }
+
+    #[test]
+    fn finds_recursive_cycles() {
+        let program = "function Sys.init 0
+push constant 4
+call Main.even 1
+push constant 4
+call Main.fact 1
+return
+function Main.even 0
+push argument 0
+call Main.odd 1
+return
+function Main.odd 0
+push argument 0
+call Main.even 1
+return
+function Main.fact 0
+push argument 0
+push constant 1
+sub
+call Main.fact 1
+return
+function Main.leaf 0
+return
+";
+        let analysis = analyze_vm(program);
+        assert_eq!(
+            analysis.cycles,
+            [vec!["Main.even", "Main.odd"], vec!["Main.fact"]]
+        );
+        assert!(analysis.get("Main.odd").unwrap().recursive);
+        assert!(analysis.get("Main.fact").unwrap().recursive);
+        assert!(!analysis.get("Main.leaf").unwrap().recursive);
+        assert!(!analysis.get("Sys.init").unwrap().recursive);
+        assert_eq!(analysis.entry.as_deref(), Some("Sys.init"));
+
+        // calls back into the cycle are not counted
+        let fact = analysis.get("Main.fact").unwrap();
+        assert_eq!(fact.worst, 1 + 5 + 2);
+        assert_eq!(fact.deepest_call, None);
+        let init = analysis.get("Sys.init").unwrap();
+        // the result of Main.even stays on the stack under the argument of
+        // Main.fact
+        assert_eq!(init.worst, 5 + 1 + fact.worst);
+    }
+
+    #[test]
+    fn builds_the_call_graph() {
+        let functions = vm::parse(
+            "Main",
+            "function Main.a 0\ncall Main.b 0\ncall Main.c 0\ncall Main.b 0\nreturn\nfunction Main.b 0\nreturn\n",
+        )
+        .unwrap();
+        let graph = call_graph(&functions);
+        assert_eq!(
+            graph["Main.a"].iter().copied().collect::<Vec<_>>(),
+            ["Main.b", "Main.c"]
+        );
+        assert!(graph["Main.b"].is_empty());
+        assert!(cycles(&graph).is_empty());
+        // no entry point
+        let analysis = analyze(&functions);
+        assert_eq!(analysis.entry, None);
+        assert_eq!(analysis.worst_path(), None);
+    }
+}
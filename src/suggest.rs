@@ -0,0 +1,102 @@
+/// Returns the candidate closest to `name`, if it is close enough to be a
+/// likely misspelling and no other candidate is just as close.
+pub fn closest<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let max_distance = (name.chars().count() / 3).max(1) * 2;
+    let mut best: Option<(usize, &str)> = None;
+    let mut tied = false;
+    for candidate in candidates {
+        if candidate == name {
+            continue;
+        }
+        let distance = doubled_distance(name, candidate);
+        if distance > max_distance {
+            continue;
+        }
+        match best {
+            Some((best_distance, best_candidate)) if distance == best_distance => {
+                tied |= candidate != best_candidate;
+            }
+            Some((best_distance, _)) if distance > best_distance => {}
+            _ => {
+                best = Some((distance, candidate));
+                tied = false;
+            }
+        }
+    }
+    best.filter(|_| !tied).map(|(_, candidate)| candidate)
+}
+
+/// Optimal string alignment distance: the number of insertions, deletions,
+/// substitutions and transpositions of adjacent characters turning `a` into
+/// `b`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    doubled_distance(a, b).div_ceil(2)
+}
+
+/// Twice the edit distance, except that substitutions differing in case only
+/// cost 1 so that `output` is closer to `Output` than to `Outpat`.
+fn doubled_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i * 2;
+    }
+    for (j, distance) in rows[0].iter_mut().enumerate() {
+        *distance = j * 2;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = if a[i - 1] == b[j - 1] {
+                0
+            } else if a[i - 1].eq_ignore_ascii_case(&b[j - 1]) {
+                1
+            } else {
+                2
+            };
+            let mut distance = (rows[i - 1][j] + 2)
+                .min(rows[i][j - 1] + 2)
+                .min(rows[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 2);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(edit_distance("count", "count"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("retrun", "return"), 1);
+        assert_eq!(edit_distance("cuont", "count"), 1);
+        assert_eq!(edit_distance("lenght", "length"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("output", "Output"), 1);
+        assert_eq!(edit_distance("é", "e"), 1);
+    }
+
+    #[test]
+    fn suggests_the_unique_closest_candidate() {
+        assert_eq!(closest("retrun", ["return", "let", "do"]), Some("return"));
+        assert_eq!(closest("output", ["Outpat", "Output"]), Some("Output"));
+        // an exact match is no misspelling
+        assert_eq!(closest("count", ["count"]), None);
+        // too far
+        assert_eq!(closest("x", ["abc"]), None);
+        assert_eq!(closest("size", ["width", "height"]), None);
+        // tied
+        assert_eq!(closest("cat", ["bat", "hat"]), None);
+        assert_eq!(closest("cat", ["bat", "bat"]), Some("bat"));
+        assert_eq!(closest("anything", std::iter::empty()), None);
+    }
+}
@@ -1,12 +1,20 @@
-use std::{collections::HashMap, fmt};
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+};
+
+use crate::{diagnostic::SourceFile, json, tokenizer::Span};
 
 pub struct SymbolTable {
     map: HashMap<String, Symbol>,
     index_map: HashMap<Category, usize>,
 }
 
-#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
 pub enum Category {
+    /// A constant or enum member of the extended dialect, compiled to its
+    /// value rather than stored.
+    Const,
     Static,
     Field,
     Arg,
@@ -16,6 +24,7 @@ pub enum Category {
 impl fmt::Display for Category {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Category::Const => write!(f, "const"),
             Category::Static => write!(f, "static"),
             Category::Field => write!(f, "field"),
             Category::Arg => write!(f, "arg"),
@@ -24,10 +33,137 @@ impl fmt::Display for Category {
     }
 }
 
-struct Symbol {
-    _type: String,
-    kind: Category,
-    index: usize,
+#[derive(Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub _type: String,
+    pub kind: Category,
+    /// Index in the segment of `kind`: the offset from `this` of a field, the
+    /// `Class.<index>` slot of a static. Unused for a constant.
+    pub index: usize,
+    /// The 16-bit word of a constant, `None` for the other categories.
+    pub value: Option<u16>,
+    /// Where the symbol is declared.
+    pub span: Span,
+}
+
+/// The symbols of a class and of each of its subroutines, as defined while
+/// compiling it.
+pub struct ClassSymbols {
+    pub class: String,
+    pub symbols: Vec<Symbol>,
+    pub subroutines: Vec<SubroutineSymbols>,
+}
+
+impl ClassSymbols {
+    /// Number of words an object of the class takes on the heap.
+    pub fn object_size(&self) -> usize {
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.kind == Category::Field)
+            .count()
+    }
+
+    /// Formats the symbols as tables, the class first and then each
+    /// subroutine, locating declarations in `file`.
+    pub fn to_text(&self, file: &SourceFile) -> String {
+        let statics = self
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.kind == Category::Static)
+            .count();
+        let mut text = format!(
+            "class {}: {} words per object, {} static slots\n",
+            self.class,
+            self.object_size(),
+            statics
+        );
+        write_table(&mut text, &self.symbols, &self.class, file);
+        for subroutine in &self.subroutines {
+            let _ = writeln!(text, "  {} {}", subroutine.kind, subroutine.name);
+            write_table(&mut text, &subroutine.symbols, &self.class, file);
+        }
+        text
+    }
+
+    /// Formats the symbols as a JSON object, locating declarations in `file`.
+    pub fn to_json(&self, file: &SourceFile) -> String {
+        let subroutines = json::array(self.subroutines.iter().map(|subroutine| {
+            format!(
+                "{{\"name\":{},\"kind\":{},\"symbols\":{}}}",
+                json::string(&subroutine.name),
+                json::string(&subroutine.kind),
+                symbols_json(&subroutine.symbols, file)
+            )
+        }));
+        format!(
+            "{{\"class\":{},\"file\":{},\"object_size\":{},\"symbols\":{},\"subroutines\":{}}}",
+            json::string(&self.class),
+            json::string(file.path),
+            self.object_size(),
+            symbols_json(&self.symbols, file),
+            subroutines
+        )
+    }
+}
+
+/// Writes a line per symbol: fields at their offset from `this`, statics at
+/// their `Class.<index>` slot and the others at their VM segment index.
+fn write_table(text: &mut String, symbols: &[Symbol], class: &str, file: &SourceFile) {
+    for symbol in symbols {
+        let slot = match symbol.kind {
+            Category::Const => format!("= {}", symbol.value.unwrap_or_default() as i16),
+            Category::Field => format!("this+{}", symbol.index),
+            Category::Static => format!("{class}.{}", symbol.index),
+            Category::Arg => format!("argument {}", symbol.index),
+            Category::Var => format!("local {}", symbol.index),
+        };
+        let position = file.position(symbol.span.start);
+        let _ = writeln!(
+            text,
+            "    {:<7} {:<16} {:<10} {:<16} {}:{}:{}",
+            symbol.kind.to_string(),
+            slot,
+            symbol._type,
+            symbol.name,
+            file.path,
+            position.line,
+            position.column
+        );
+    }
+}
+
+fn symbols_json(symbols: &[Symbol], file: &SourceFile) -> String {
+    json::array(symbols.iter().map(|symbol| {
+        let position = file.position(symbol.span.start);
+        let slot = match symbol.value {
+            Some(word) => format!("\"value\":{}", word as i16),
+            None => format!("\"index\":{}", symbol.index),
+        };
+        format!(
+            "{{\"name\":{},\"type\":{},\"category\":{},{},\"line\":{},\"column\":{}}}",
+            json::string(&symbol.name),
+            json::string(&symbol._type),
+            json::string(&symbol.kind.to_string()),
+            slot,
+            position.line,
+            position.column
+        )
+    }))
+}
+
+pub struct SubroutineSymbols {
+    /// `Class.subroutine`.
+    pub name: String,
+    /// `constructor`, `function` or `method`.
+    pub kind: String,
+    pub symbols: Vec<Symbol>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SymbolTable {
@@ -43,23 +179,60 @@ impl SymbolTable {
     }
 
     pub fn reset(&mut self) {
+        self.map.clear();
         self.index_map.insert(Category::Static, 0);
         self.index_map.insert(Category::Field, 0);
         self.index_map.insert(Category::Arg, 0);
         self.index_map.insert(Category::Var, 0);
     }
 
-    pub fn define(&mut self, name: &str, _type: &str, kind: Category) {
+    pub fn define(&mut self, name: &str, _type: &str, kind: Category, span: Span) {
         let index = self.var_count(kind);
         let symbol = Symbol {
+            name: String::from(name),
             _type: String::from(_type),
             index,
+            value: None,
             kind,
+            span,
         };
         self.map.insert(String::from(name), symbol);
         self.index_map.insert(kind, index + 1);
     }
 
+    /// Defines a constant of value `word`, which takes no slot in any
+    /// segment.
+    pub fn define_constant(&mut self, name: &str, _type: &str, word: u16, span: Span) {
+        let symbol = Symbol {
+            name: String::from(name),
+            _type: String::from(_type),
+            index: 0,
+            value: Some(word),
+            kind: Category::Const,
+            span,
+        };
+        self.map.insert(String::from(name), symbol);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(String::as_str)
+    }
+
+    /// Returns the symbols defined, by category and then index, constants
+    /// in the order they are declared.
+    pub fn symbols(&self) -> Vec<&Symbol> {
+        let mut symbols: Vec<&Symbol> = self.map.values().collect();
+        symbols.sort_by_key(|symbol| match symbol.kind {
+            Category::Const => (symbol.kind, symbol.span.start),
+            _ => (symbol.kind, symbol.index),
+        });
+        symbols
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.map.get(name)
+    }
+
     pub fn var_count(&self, kind: Category) -> usize {
         *self.index_map.get(&kind).unwrap()
     }
@@ -75,4 +248,101 @@ impl SymbolTable {
     pub fn index_of(&self, name: &str) -> Option<usize> {
         self.map.get(name).map(|s| s.index)
     }
+
+    pub fn value_of(&self, name: &str) -> Option<u16> {
+        self.map.get(name).and_then(|s| s.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "class P {\n  field int x;\n  static int n;\n  const int MIN = -2;\n  method void f(int d) {\n    var int t;\n  }\n}\n";
+
+    fn span_of(text: &str) -> Span {
+        let start = SOURCE.find(text).unwrap();
+        Span::new(start, start + text.len())
+    }
+
+    fn symbols() -> ClassSymbols {
+        let mut class = SymbolTable::new();
+        class.define("x", "int", Category::Field, span_of("x;"));
+        class.define("n", "int", Category::Static, span_of("n;"));
+        class.define_constant("MIN", "int", (-2i16) as u16, span_of("MIN"));
+        let mut subroutine = SymbolTable::new();
+        subroutine.define("d", "int", Category::Arg, span_of("d)"));
+        subroutine.define("t", "int", Category::Var, span_of("t;"));
+        ClassSymbols {
+            class: String::from("P"),
+            symbols: class.symbols().into_iter().cloned().collect(),
+            subroutines: vec![SubroutineSymbols {
+                name: String::from("P.f"),
+                kind: String::from("method"),
+                symbols: subroutine.symbols().into_iter().cloned().collect(),
+            }],
+        }
+    }
+
+    #[test]
+    fn forgets_symbols_on_reset() {
+        let mut table = SymbolTable::new();
+        table.define("a", "int", Category::Arg, span_of("d)"));
+        table.define("t", "int", Category::Var, span_of("t;"));
+        table.reset();
+        assert!(table.get("a").is_none());
+        assert_eq!(table.names().count(), 0);
+        table.define("u", "int", Category::Var, span_of("t;"));
+        assert_eq!(table.index_of("u"), Some(0));
+    }
+
+    #[test]
+    fn keeps_constant_values_apart_from_indices() {
+        let mut table = SymbolTable::new();
+        table.define_constant("MIN", "int", (-2i16) as u16, span_of("MIN"));
+        table.define("x", "int", Category::Field, span_of("x;"));
+        assert_eq!(table.value_of("MIN"), Some(0xFFFE));
+        assert_eq!(table.value_of("x"), None);
+        assert_eq!(table.index_of("x"), Some(0));
+        assert_eq!(table.var_count(Category::Field), 1);
+    }
+
+    #[test]
+    fn formats_tables() {
+        let file = SourceFile::new("P.jack", SOURCE);
+        let text = symbols().to_text(&file);
+        let lines: Vec<Vec<&str>> = text
+            .lines()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            text.lines().next(),
+            Some("class P: 1 words per object, 1 static slots")
+        );
+        assert_eq!(lines[1], ["const", "=", "-2", "int", "MIN", "P.jack:4:13"]);
+        assert_eq!(lines[2], ["static", "P.0", "int", "n", "P.jack:3:14"]);
+        assert_eq!(lines[3], ["field", "this+0", "int", "x", "P.jack:2:13"]);
+        assert_eq!(lines[4], ["method", "P.f"]);
+        assert_eq!(
+            lines[5],
+            ["arg", "argument", "0", "int", "d", "P.jack:5:21"]
+        );
+        assert_eq!(lines[6], ["var", "local", "0", "int", "t", "P.jack:6:13"]);
+    }
+
+    #[test]
+    fn formats_json() {
+        let file = SourceFile::new("P.jack", SOURCE);
+        let json = symbols().to_json(&file);
+        assert!(json.starts_with("{\"class\":\"P\",\"file\":\"P.jack\",\"object_size\":1,"));
+        assert!(json.contains(
+            "{\"name\":\"MIN\",\"type\":\"int\",\"category\":\"const\",\"value\":-2,\"line\":4,\"column\":13}"
+        ));
+        assert!(json.contains(
+            "{\"name\":\"x\",\"type\":\"int\",\"category\":\"field\",\"index\":0,\"line\":2,\"column\":13}"
+        ));
+        assert!(
+            json.contains("\"subroutines\":[{\"name\":\"P.f\",\"kind\":\"method\",\"symbols\":[")
+        );
+    }
 }
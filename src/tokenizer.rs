@@ -1,4 +1,5 @@
 use std::{
+    fmt,
     iter::{Enumerate, Peekable},
     str::Bytes,
 };
@@ -9,19 +10,67 @@ pub struct Tokenizer<'a> {
     iterator: Peekable<Enumerate<Bytes<'a>>>,
     cur_token_start: usize,
     state: State,
+    dialect: Dialect,
 }
 
+/// Variant of the Jack language a file is written in.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum Dialect {
+    /// The language as specified by nand2tetris.
+    #[default]
+    Standard,
+    /// The language along with the extensions of this compiler, such as
+    /// `break` and `continue`.
+    Extended,
+}
+
+impl Dialect {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Dialect::Standard => "standard",
+            Dialect::Extended => "extended",
+        }
+    }
+}
+
+/// Keywords only reserved in the extended dialect.
+pub const EXTENDED_KEYWORDS: &[&str] = &[
+    "break", "case", "const", "continue", "default", "enum", "for", "switch",
+];
+
+/// Operators of two characters, only read as such in the extended dialect.
+const EXTENDED_OPERATORS: &[&str] = &["&&", "||", "<=", ">=", "!=", "<<", ">>"];
+
 impl<'a> Tokenizer<'a> {
-    pub fn build(file_contents: &str) -> Result<Tokenizer, &'static str> {
+    pub fn build(file_contents: &str) -> Result<Tokenizer<'_>, &'static str> {
         let iterator = file_contents.bytes().enumerate().peekable();
         let tokenizer = Tokenizer {
             file_contents,
             iterator,
             cur_token_start: 0,
             state: State::Code,
+            dialect: Dialect::Standard,
         };
         Ok(tokenizer)
     }
+
+    pub fn with_dialect(mut self, dialect: Dialect) -> Tokenizer<'a> {
+        self.dialect = dialect;
+        self
+    }
+}
+
+/// Byte range of a token in the tokenized file.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -31,6 +80,12 @@ pub enum Token<'a> {
     Identifier(&'a str),
     IntConstant(usize),
     StringConst(&'a str),
+    /// An operator of the extended dialect other than the Jack symbols, e.g.
+    /// `&&` or `%`.
+    Operator(&'a str),
+    /// A character constant of the extended dialect, without its quotes and
+    /// with its escape sequence if any, e.g. `a` or `\n`.
+    CharConst(&'a str),
     Whitespace(char),
     SingleLineComment(&'a str),
     BlockComment(&'a str),
@@ -41,6 +96,10 @@ pub enum Token<'a> {
 enum State {
     Code,
     StringLiteral,
+    // after a `\` in a string or character constant
+    StringEscape,
+    CharLiteral,
+    CharEscape,
     SingleLineComment,
     BlockComment,
     BlockCommentEndStar,
@@ -50,74 +109,182 @@ impl<'a> Iterator for Tokenizer<'a> {
     type Item = Token<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        self.next_spanned().map(|(_, token)| token)
+    }
+}
+
+/// Iterator over the tokens of a file along with their spans, see
+/// `Tokenizer::spanned`.
+#[derive(Clone)]
+pub struct Spanned<'a> {
+    tokenizer: Tokenizer<'a>,
+}
+
+impl<'a> Iterator for Spanned<'a> {
+    type Item = (Span, Token<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tokenizer.next_spanned()
+    }
+}
+
+impl<'a> Tokenizer<'a> {
+    /// Turns the tokenizer into an iterator yielding each token along with
+    /// the byte range it was read from.
+    pub fn spanned(self) -> Spanned<'a> {
+        Spanned { tokenizer: self }
+    }
+
+    fn next_spanned(&mut self) -> Option<(Span, Token<'a>)> {
         loop {
             let (i, b) = match self.iterator.next() {
                 Some(i) => i,
-                None => break None,
-            };
-            let (next_i, next_b) = match self.iterator.peek() {
-                Some(i) => i,
-                None => break None,
+                None => break self.finish(),
             };
+            // the end of the file behaves like trailing whitespace so the last
+            // token is terminated too
+            let (next_i, next_b) = self
+                .iterator
+                .peek()
+                .copied()
+                .unwrap_or((self.file_contents.len(), b' '));
             match self.state {
                 State::Code => match b {
                     b if b.is_ascii_whitespace() => {
-                        self.cur_token_start = *next_i;
-                        break Some(Token::Whitespace(b as char));
+                        self.cur_token_start = next_i;
+                        break Some((Span::new(i, next_i), Token::Whitespace(b as char)));
                     }
                     b'"' => {
-                        self.cur_token_start = *next_i;
+                        self.cur_token_start = next_i;
                         self.state = State::StringLiteral;
                         continue;
                     }
-                    b'/' if *next_b == b'/' => {
+                    b'\'' if self.dialect == Dialect::Extended => {
+                        self.cur_token_start = next_i;
+                        self.state = State::CharLiteral;
+                        continue;
+                    }
+                    b'/' if next_b == b'/' => {
                         let (i, _) = self.iterator.next().expect("Expect next value as token will have been returned immediately otherwise"); // skip the next *
                         self.cur_token_start = i + 1;
                         self.state = State::SingleLineComment;
                         continue;
                     }
-                    b'/' if *next_b == b'*' => {
+                    b'/' if next_b == b'*' => {
                         let (i, _) = self.iterator.next().expect("Expect next value as token will have been returned immediately otherwise"); // skip the next *
                         self.cur_token_start = i + 1;
                         self.state = State::BlockComment;
                         continue;
                     }
                     b => {
+                        let operator = self.file_contents.get(i..i + 2).unwrap_or_default();
+                        if self.dialect == Dialect::Extended
+                            && EXTENDED_OPERATORS.contains(&operator)
+                        {
+                            self.iterator.next();
+                            self.cur_token_start = i + 2;
+                            break Some((Span::new(i, i + 2), Token::Operator(operator)));
+                        }
+                        if self.dialect == Dialect::Extended && b == b'%' {
+                            self.cur_token_start = next_i;
+                            break Some((Span::new(i, next_i), Token::Operator("%")));
+                        }
+                        // ends the `case` labels of a `switch`
+                        if self.dialect == Dialect::Extended && b == b':' {
+                            self.cur_token_start = next_i;
+                            break Some((Span::new(i, next_i), Token::Symbol(':')));
+                        }
                         if is_symbol(b) {
-                            self.cur_token_start = *next_i;
-                            break Some(Token::Symbol(b as char));
+                            self.cur_token_start = next_i;
+                            break Some((Span::new(i, next_i), Token::Symbol(b as char)));
                         }
-                        if next_b.is_ascii_whitespace() || is_symbol(*next_b) {
-                            let token = &self.file_contents[self.cur_token_start..*next_i];
+                        let starts_operator = self.dialect == Dialect::Extended
+                            && matches!(next_b, b'%' | b'!' | b':');
+                        if next_b.is_ascii_whitespace() || is_symbol(next_b) || starts_operator {
+                            let span = Span::new(self.cur_token_start, next_i);
+                            let token = &self.file_contents[self.cur_token_start..next_i];
                             let token = match token {
                                 token if is_keyword(token) => Token::Keyword(token),
+                                token
+                                    if self.dialect == Dialect::Extended
+                                        && EXTENDED_KEYWORDS.contains(&token) =>
+                                {
+                                    Token::Keyword(token)
+                                }
+                                token
+                                    if self.dialect == Dialect::Extended
+                                        && has_radix_prefix(token) =>
+                                {
+                                    match parse_radix(token) {
+                                        Some(c) => Token::IntConstant(c),
+                                        None => Token::Invalid(token),
+                                    }
+                                }
+                                // constants too large even for a usize are
+                                // reported out of range by the compilation
                                 token if is_numeric(token) => {
-                                    Token::IntConstant(token.parse().unwrap())
+                                    Token::IntConstant(token.parse().unwrap_or(usize::MAX))
                                 }
                                 token if is_valid_identifier(token) => Token::Identifier(token),
                                 token => Token::Invalid(token),
                             };
-                            self.cur_token_start = *next_i;
-                            break Some(token);
+                            self.cur_token_start = next_i;
+                            break Some((span, token));
                         }
                         continue;
                     }
                 },
-                State::StringLiteral => match b {
-                    b'"' => {
+                State::StringLiteral | State::CharLiteral
+                    if b == b'\\' && self.dialect == Dialect::Extended =>
+                {
+                    self.state = match self.state {
+                        State::StringLiteral => State::StringEscape,
+                        _ => State::CharEscape,
+                    };
+                    continue;
+                }
+                State::StringEscape | State::CharEscape if b != b'\n' => {
+                    self.state = match self.state {
+                        State::StringEscape => State::StringLiteral,
+                        _ => State::CharLiteral,
+                    };
+                    continue;
+                }
+                State::CharLiteral if b == b'\'' => {
+                    let span = Span::new(self.cur_token_start - 1, next_i);
+                    let token = &self.file_contents[self.cur_token_start..i];
+                    self.cur_token_start = next_i;
+                    self.state = State::Code;
+                    break Some((span, Token::CharConst(token)));
+                }
+                State::StringLiteral
+                | State::StringEscape
+                | State::CharLiteral
+                | State::CharEscape => match b {
+                    b'"' if matches!(self.state, State::StringLiteral) => {
+                        let span = Span::new(self.cur_token_start - 1, next_i);
                         let token = &self.file_contents[self.cur_token_start..i];
-                        self.cur_token_start = *next_i;
+                        self.cur_token_start = next_i;
+                        self.state = State::Code;
+                        break Some((span, Token::StringConst(token)));
+                    }
+                    // string and character constants cannot span lines
+                    b'\n' => {
+                        let span = Span::new(self.cur_token_start - 1, i);
+                        let token = &self.file_contents[span.start..span.end];
+                        self.cur_token_start = next_i;
                         self.state = State::Code;
-                        break Some(Token::StringConst(token));
+                        break Some((span, Token::Invalid(token.trim_end())));
                     }
                     _ => continue,
                 },
                 State::SingleLineComment => match b {
                     b'\n' => {
-                        let comment = &self.file_contents[self.cur_token_start..*next_i].trim();
-                        self.cur_token_start = *next_i;
+                        let span = Span::new(self.cur_token_start - 2, next_i);
+                        let comment = &self.file_contents[self.cur_token_start..next_i].trim();
+                        self.cur_token_start = next_i;
                         self.state = State::Code;
-                        break Some(Token::SingleLineComment(comment));
+                        break Some((span, Token::SingleLineComment(comment)));
                     }
                     _ => continue,
                 },
@@ -130,11 +297,13 @@ impl<'a> Iterator for Tokenizer<'a> {
                 },
                 State::BlockCommentEndStar => match b {
                     b'/' => {
-                        let comment = &self.file_contents[self.cur_token_start..*next_i - 2].trim();
-                        self.cur_token_start = *next_i;
+                        let span = Span::new(self.cur_token_start - 2, next_i);
+                        let comment = &self.file_contents[self.cur_token_start..next_i - 2].trim();
+                        self.cur_token_start = next_i;
                         self.state = State::Code;
-                        break Some(Token::BlockComment(comment));
+                        break Some((span, Token::BlockComment(comment)));
                     }
+                    b'*' => continue,
                     _ => {
                         self.state = State::BlockComment;
                         continue;
@@ -143,6 +312,69 @@ impl<'a> Iterator for Tokenizer<'a> {
             }
         }
     }
+
+    /// Handles a file ending inside a string constant or comment.
+    fn finish(&mut self) -> Option<(Span, Token<'a>)> {
+        let end = self.file_contents.len();
+        let token = match self.state {
+            State::Code => return None,
+            State::SingleLineComment => {
+                let comment = self.file_contents[self.cur_token_start..].trim();
+                (self.cur_token_start - 2, Token::SingleLineComment(comment))
+            }
+            State::StringLiteral | State::StringEscape | State::CharLiteral | State::CharEscape => {
+                let start = self.cur_token_start - 1;
+                (start, Token::Invalid(&self.file_contents[start..]))
+            }
+            State::BlockComment | State::BlockCommentEndStar => {
+                let start = self.cur_token_start - 2;
+                (start, Token::Invalid(&self.file_contents[start..]))
+            }
+        };
+        self.cur_token_start = end;
+        self.state = State::Code;
+        Some((Span::new(token.0, end), token.1))
+    }
+}
+
+/// Why the tokenizer produced a `Token::Invalid`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Invalid {
+    Token,
+    UnterminatedString,
+    UnterminatedChar,
+    UnterminatedComment,
+}
+
+impl Invalid {
+    pub fn of(token: &str, dialect: Dialect) -> Invalid {
+        if token.starts_with('"') {
+            Invalid::UnterminatedString
+        } else if token.starts_with('\'') && dialect == Dialect::Extended {
+            Invalid::UnterminatedChar
+        } else if token.starts_with("/*") {
+            Invalid::UnterminatedComment
+        } else {
+            Invalid::Token
+        }
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Keyword(k) => write!(f, "keyword `{k}`"),
+            Token::Symbol(c) => write!(f, "`{c}`"),
+            Token::Operator(o) => write!(f, "`{o}`"),
+            Token::Identifier(i) => write!(f, "identifier `{i}`"),
+            Token::IntConstant(c) => write!(f, "integer constant `{c}`"),
+            Token::StringConst(c) => write!(f, "string constant \"{c}\""),
+            Token::CharConst(c) => write!(f, "character constant '{c}'"),
+            Token::Whitespace(_) => write!(f, "whitespace"),
+            Token::SingleLineComment(_) | Token::BlockComment(_) => write!(f, "comment"),
+            Token::Invalid(t) => write!(f, "`{t}`"),
+        }
+    }
 }
 
 fn is_valid_identifier(token: &str) -> bool {
@@ -154,6 +386,23 @@ fn is_numeric(token: &str) -> bool {
     token.chars().all(char::is_numeric)
 }
 
+/// Whether `token` is written as a hexadecimal (`0x`) or binary (`0b`)
+/// integer constant.
+pub fn has_radix_prefix(token: &str) -> bool {
+    token.starts_with("0x") || token.starts_with("0b")
+}
+
+fn parse_radix(token: &str) -> Option<usize> {
+    let (digits, radix) = match token.strip_prefix("0x") {
+        Some(digits) => (digits, 16),
+        None => (token.strip_prefix("0b")?, 2),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    Some(usize::from_str_radix(digits, radix).unwrap_or(usize::MAX))
+}
+
 fn is_keyword(token: &str) -> bool {
     matches!(
         token,
@@ -204,3 +453,80 @@ fn is_symbol(c: u8) -> bool {
             | b'~'
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str, dialect: Dialect) -> Vec<(Span, Token<'_>)> {
+        Tokenizer::build(source)
+            .unwrap()
+            .with_dialect(dialect)
+            .spanned()
+            .filter(|(_, token)| !matches!(token, Token::Whitespace(_)))
+            .collect()
+    }
+
+    #[test]
+    fn spans_tokens() {
+        let source = "let x = a[12] + \"hi\"; // done";
+        let tokens = tokens(source, Dialect::Standard);
+        let texts: Vec<&str> = tokens
+            .iter()
+            .map(|(span, _)| &source[span.start..span.end])
+            .collect();
+        assert_eq!(
+            texts,
+            ["let", "x", "=", "a", "[", "12", "]", "+", "\"hi\"", ";", "// done"]
+        );
+        assert_eq!(tokens[0].1, Token::Keyword("let"));
+        assert_eq!(tokens[5].1, Token::IntConstant(12));
+        assert_eq!(tokens[8].1, Token::StringConst("hi"));
+        assert_eq!(tokens[10].1, Token::SingleLineComment("done"));
+    }
+
+    #[test]
+    fn reports_invalid_tokens() {
+        let tokens = tokens("let 2x = \"open", Dialect::Standard);
+        assert_eq!(tokens[1], (Span::new(4, 6), Token::Invalid("2x")));
+        let (_, last) = tokens.last().unwrap();
+        assert!(matches!(last, Token::Invalid(token)
+            if Invalid::of(token, Dialect::Standard) == Invalid::UnterminatedString));
+        let comment = self::tokens("/* never closed", Dialect::Standard);
+        assert!(
+            matches!(comment[0].1, Token::Invalid(token) if Invalid::of(token, Dialect::Standard) == Invalid::UnterminatedComment)
+        );
+    }
+
+    #[test]
+    fn reads_extended_tokens_only_in_extended_dialect() {
+        let source = "a<=b%c";
+        let extended: Vec<Token> = tokens(source, Dialect::Extended)
+            .into_iter()
+            .map(|(_, token)| token)
+            .collect();
+        assert_eq!(
+            extended,
+            [
+                Token::Identifier("a"),
+                Token::Operator("<="),
+                Token::Identifier("b"),
+                Token::Operator("%"),
+                Token::Identifier("c")
+            ]
+        );
+        let standard = tokens(source, Dialect::Standard);
+        assert_eq!(standard[1].1, Token::Symbol('<'));
+        assert_eq!(standard[2].1, Token::Symbol('='));
+        assert_eq!(
+            tokens("0x1F 'a' for", Dialect::Extended)[0].1,
+            Token::IntConstant(31)
+        );
+        assert_eq!(tokens("'a'", Dialect::Extended)[0].1, Token::CharConst("a"));
+        assert_eq!(tokens("for", Dialect::Extended)[0].1, Token::Keyword("for"));
+        assert_eq!(
+            tokens("for", Dialect::Standard)[0].1,
+            Token::Identifier("for")
+        );
+    }
+}
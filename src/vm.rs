@@ -0,0 +1,224 @@
+use std::fmt;
+
+use crate::vm_writer::{Arithmetic, Segment};
+
+#[derive(PartialEq, Eq, Clone)]
+pub enum Command {
+    Push(Segment, usize),
+    Pop(Segment, usize),
+    Arithmetic(Arithmetic),
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Call(String, usize),
+    Return,
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Push(segment, index) => write!(f, "push {segment} {index}"),
+            Command::Pop(segment, index) => write!(f, "pop {segment} {index}"),
+            Command::Arithmetic(command) => write!(f, "{command}"),
+            Command::Label(label) => write!(f, "label {label}"),
+            Command::Goto(label) => write!(f, "goto {label}"),
+            Command::IfGoto(label) => write!(f, "if-goto {label}"),
+            Command::Call(name, n_args) => write!(f, "call {name} {n_args}"),
+            Command::Return => write!(f, "return"),
+        }
+    }
+}
+
+/// A function of a VM program.
+#[derive(Clone)]
+pub struct Function {
+    pub name: String,
+    /// Name of the `.vm` file the function was read from, which its static
+    /// variables belong to.
+    pub class: String,
+    pub n_locals: usize,
+    pub body: Vec<Command>,
+}
+
+impl Function {
+    /// Names of the functions this function calls.
+    pub fn calls(&self) -> impl Iterator<Item = &str> {
+        self.body.iter().filter_map(|command| match command {
+            Command::Call(name, _) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// Formats the function in VM syntax, its declaration followed by its body.
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "function {} {}", self.name, self.n_locals)?;
+        for command in &self.body {
+            writeln!(f, "{command}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the VM code of `class`, as found in `<class>.vm`. Errors are
+/// prefixed with the line they occur on.
+pub fn parse(class: &str, text: &str) -> Result<Vec<Function>, String> {
+    let mut functions: Vec<Function> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| format!("{}: {}", i + 1, message);
+        let line = line.split("//").next().unwrap_or_default();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words[..] {
+            [] => continue,
+            ["function", name, n_locals] => {
+                functions.push(Function {
+                    name: name.to_string(),
+                    class: class.to_string(),
+                    n_locals: number(n_locals).map_err(error)?,
+                    body: Vec::new(),
+                });
+                continue;
+            }
+            ["push", segment, index] => Command::Push(
+                parse_segment(segment).map_err(error)?,
+                number(index).map_err(error)?,
+            ),
+            ["pop", segment, index] => match parse_segment(segment).map_err(error)? {
+                Segment::CONSTANT => return Err(error(String::from("cannot pop to constant"))),
+                segment => Command::Pop(segment, number(index).map_err(error)?),
+            },
+            ["label", label] => Command::Label(label.to_string()),
+            ["goto", label] => Command::Goto(label.to_string()),
+            ["if-goto", label] => Command::IfGoto(label.to_string()),
+            ["call", name, n_args] => {
+                Command::Call(name.to_string(), number(n_args).map_err(error)?)
+            }
+            ["return"] => Command::Return,
+            [command] => Command::Arithmetic(parse_arithmetic(command).map_err(error)?),
+            _ => return Err(error(format!("invalid command `{}`", line.trim()))),
+        };
+        match functions.last_mut() {
+            Some(function) => function.body.push(command),
+            None => return Err(error(String::from("command outside of a function"))),
+        }
+    }
+    Ok(functions)
+}
+
+fn number(word: &str) -> Result<usize, String> {
+    word.parse()
+        .map_err(|_| format!("expected a number, found `{word}`"))
+}
+
+fn parse_segment(word: &str) -> Result<Segment, String> {
+    match word {
+        "constant" => Ok(Segment::CONSTANT),
+        "argument" => Ok(Segment::ARGUMENT),
+        "local" => Ok(Segment::LOCAL),
+        "static" => Ok(Segment::STATIC),
+        "this" => Ok(Segment::THIS),
+        "that" => Ok(Segment::THAT),
+        "pointer" => Ok(Segment::POINTER),
+        "temp" => Ok(Segment::TEMP),
+        _ => Err(format!("unknown segment `{word}`")),
+    }
+}
+
+fn parse_arithmetic(word: &str) -> Result<Arithmetic, String> {
+    match word {
+        "add" => Ok(Arithmetic::ADD),
+        "sub" => Ok(Arithmetic::SUB),
+        "neg" => Ok(Arithmetic::NEG),
+        "eq" => Ok(Arithmetic::EQ),
+        "gt" => Ok(Arithmetic::GT),
+        "lt" => Ok(Arithmetic::LT),
+        "and" => Ok(Arithmetic::AND),
+        "or" => Ok(Arithmetic::OR),
+        "not" => Ok(Arithmetic::NOT),
+        _ => Err(format!("unknown command `{word}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAIN: &str = "// compiled
+function Main.main 1
+    push constant 7   // seven
+    pop local 0
+label LOOP
+    push local 0
+    if-goto LOOP
+    goto END
+label END
+    call Output.printInt 1
+    pop temp 0
+    push static 3
+    not
+    return
+function Main.other 0
+call Main.main 0
+return
+";
+
+    #[test]
+    fn parses_functions() {
+        let functions = parse("Main", MAIN).unwrap();
+        assert_eq!(functions.len(), 2);
+        let main = &functions[0];
+        assert_eq!(main.name, "Main.main");
+        assert_eq!(main.class, "Main");
+        assert_eq!(main.n_locals, 1);
+        assert_eq!(main.body.len(), 12);
+        assert!(main.body[0] == Command::Push(Segment::CONSTANT, 7));
+        assert!(main.body[4] == Command::IfGoto(String::from("LOOP")));
+        assert!(main.body[10] == Command::Arithmetic(Arithmetic::NOT));
+        assert_eq!(main.calls().collect::<Vec<_>>(), ["Output.printInt"]);
+        assert_eq!(functions[1].calls().collect::<Vec<_>>(), ["Main.main"]);
+        assert!(parse("Empty", "").unwrap().is_empty());
+    }
+
+    #[test]
+    fn formats_what_it_parses() {
+        let functions = parse("Main", MAIN).unwrap();
+        let formatted: String = functions.iter().map(Function::to_string).collect();
+        assert!(formatted.starts_with("function Main.main 1\npush constant 7\npop local 0\n"));
+        let reparsed = parse("Main", &formatted).unwrap();
+        assert!(reparsed
+            .iter()
+            .zip(&functions)
+            .all(|(a, b)| a.body == b.body));
+    }
+
+    #[test]
+    fn reports_the_line_of_errors() {
+        let error = |text: &str| parse("Main", text).err().unwrap();
+        assert_eq!(error("push constant 1"), "1: command outside of a function");
+        assert_eq!(
+            error("function Main.main 0\n\npop constant 1"),
+            "3: cannot pop to constant"
+        );
+        assert_eq!(
+            error("function Main.main 0\npush stack 1"),
+            "2: unknown segment `stack`"
+        );
+        assert_eq!(
+            error("function Main.main 0\npush local -1"),
+            "2: expected a number, found `-1`"
+        );
+        assert_eq!(
+            error("function Main.main x"),
+            "1: expected a number, found `x`"
+        );
+        assert_eq!(
+            error("function Main.main 0\nmul"),
+            "2: unknown command `mul`"
+        );
+        assert_eq!(
+            error("function Main.main 0\ngoto A B  // two"),
+            "2: invalid command `goto A B`"
+        );
+    }
+}
@@ -1,12 +1,8 @@
-use std::{
-    fmt,
-    fs::File,
-    io::{Error, Write},
-    path::PathBuf,
-};
+use std::fmt;
 
+#[derive(Default)]
 pub struct VmWriter {
-    file: File,
+    output: String,
 }
 
 #[derive(PartialEq, Eq, Hash, Copy, Clone)]
@@ -37,6 +33,7 @@ impl fmt::Display for Segment {
     }
 }
 
+#[derive(PartialEq, Eq, Copy, Clone)]
 pub enum Arithmetic {
     ADD,
     SUB,
@@ -67,10 +64,48 @@ impl fmt::Display for Arithmetic {
 }
 
 impl VmWriter {
-    pub fn build(path: PathBuf) -> Result<VmWriter, Error> {
-        let file = File::create(&path)?;
+    pub fn new() -> VmWriter {
+        VmWriter {
+            output: String::new(),
+        }
+    }
+
+    pub fn into_output(self) -> String {
+        self.output
+    }
+
+    /// Appends the code written to `other`, compiled ahead of where it runs.
+    pub fn append(&mut self, other: VmWriter) {
+        self.output.push_str(&other.output);
+    }
+
+    /// Length of the code written so far, to refer back to it.
+    pub fn position(&self) -> usize {
+        self.output.len()
+    }
+
+    /// Removes the code written since `position` when it is a single
+    /// `push constant`, returning the constant.
+    pub fn take_constant(&mut self, position: usize) -> Option<usize> {
+        let constant = self.output[position..]
+            .strip_prefix("push constant ")?
+            .strip_suffix('\n')?
+            .parse()
+            .ok()?;
+        self.output.truncate(position);
+        Some(constant)
+    }
 
-        Ok(VmWriter { file })
+    /// The word the code written computes when it is a `push constant`
+    /// followed by any number of `neg` and `not`.
+    pub fn constant_word(&self) -> Option<u16> {
+        let mut lines = self.output.lines();
+        let constant: u16 = lines.next()?.strip_prefix("push constant ")?.parse().ok()?;
+        lines.try_fold(constant, |word, line| match line {
+            "neg" => Some(word.wrapping_neg()),
+            "not" => Some(!word),
+            _ => None,
+        })
     }
 
     pub fn write_push(&mut self, segment: Segment, index: usize) {
@@ -110,6 +145,7 @@ impl VmWriter {
     }
 
     fn writeln(&mut self, str: &str) {
-        let _ = self.file.write_all(format!("{}\n", str).as_bytes());
+        self.output.push_str(str);
+        self.output.push('\n');
     }
 }
@@ -0,0 +1,103 @@
+//! Compiles the programs in `samples` and compares their VM code with that of
+//! the original compiler, kept in `tests/baseline`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const COMPILER: &str = env!("CARGO_BIN_EXE_jack-compiler");
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Copies the sources of the sample `name` to a fresh directory.
+fn copy_sample(name: &str, run: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("jack-samples-{}-{run}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for entry in fs::read_dir(root().join("samples").join(name)).unwrap() {
+        let path = entry.unwrap().path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "jack")
+        {
+            fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+    dir
+}
+
+fn samples() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(root().join("tests").join("baseline"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+fn assert_matches_baseline(name: &str, dir: &Path) {
+    let baseline = root().join("tests").join("baseline").join(name);
+    for entry in fs::read_dir(&baseline).unwrap() {
+        let expected_path = entry.unwrap().path();
+        let file_name = expected_path.file_name().unwrap();
+        let expected = fs::read_to_string(&expected_path).unwrap();
+        let actual = fs::read_to_string(dir.join(file_name))
+            .unwrap_or_else(|err| panic!("{name}/{}: {err}", file_name.to_string_lossy()));
+        assert!(
+            expected == actual,
+            "{name}/{} differs from the baseline",
+            file_name.to_string_lossy()
+        );
+    }
+}
+
+fn compile_samples(run: &str, flags: &[&str]) {
+    let names = samples();
+    assert!(!names.is_empty());
+    for name in names {
+        let dir = copy_sample(&name, run);
+        let output = Command::new(COMPILER)
+            .arg(&dir)
+            .args(flags)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{name}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_matches_baseline(&name, &dir);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn samples_match_baseline() {
+    compile_samples("parallel", &[]);
+}
+
+#[test]
+fn samples_match_baseline_on_one_thread() {
+    compile_samples("serial", &["--jobs", "1"]);
+}
+
+#[test]
+fn samples_match_baseline_in_extended_dialect() {
+    compile_samples("extended", &["--dialect=extended"]);
+}
+
+#[test]
+fn cached_samples_are_left_unchanged() {
+    let dir = copy_sample("Pong", "cached");
+    for _ in 0..2 {
+        let status = Command::new(COMPILER).arg(&dir).status().unwrap();
+        assert!(status.success());
+    }
+    assert_matches_baseline("Pong", &dir);
+    fs::remove_dir_all(dir).unwrap();
+}